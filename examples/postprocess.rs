@@ -0,0 +1,142 @@
+//! Post-processing the whole frame with a chain of passes.
+//!
+//! Press 1 to toggle bloom, 2 for blur, 3 for the CRT effect and
+//! 4 for sepia color grading.
+
+extern crate cgmath;
+extern crate ggez;
+
+use ggez::event::{self, KeyCode, KeyMods};
+use ggez::graphics::postprocess::{BloomPass, BlurPass, CrtPass, LutPass, Pass, PostProcess};
+use ggez::graphics::{self, DrawMode};
+use ggez::timer;
+use ggez::{Context, GameResult};
+use std::env;
+use std::path;
+
+/// Makes a 16x16x16 lookup table that turns colors sepia.
+fn sepia_lut(ctx: &mut Context) -> GameResult<graphics::Image> {
+    const SIZE: usize = 16;
+    let mut rgba = Vec::with_capacity(SIZE * SIZE * SIZE * 4);
+    for green in 0..SIZE {
+        for blue in 0..SIZE {
+            for red in 0..SIZE {
+                let (r, g, b) = (
+                    red as f32 / (SIZE - 1) as f32,
+                    green as f32 / (SIZE - 1) as f32,
+                    blue as f32 / (SIZE - 1) as f32,
+                );
+                let sepia = [
+                    r * 0.393 + g * 0.769 + b * 0.189,
+                    r * 0.349 + g * 0.686 + b * 0.168,
+                    r * 0.272 + g * 0.534 + b * 0.131,
+                ];
+                for channel in &sepia {
+                    rgba.push((channel.min(1.0) * 255.0) as u8);
+                }
+                rgba.push(255);
+            }
+        }
+    }
+    graphics::Image::from_rgba8(ctx, (SIZE * SIZE) as u16, SIZE as u16, &rgba)
+}
+
+struct MainState {
+    post: PostProcess,
+    bloom: BloomPass,
+    blur: BlurPass,
+    crt: CrtPass,
+    lut: LutPass,
+    enabled: [bool; 4],
+}
+
+impl MainState {
+    fn new(ctx: &mut Context) -> GameResult<MainState> {
+        let lut = sepia_lut(ctx)?;
+        Ok(MainState {
+            post: PostProcess::new(ctx)?,
+            bloom: BloomPass::new(ctx)?,
+            blur: BlurPass::new(ctx, 4.0)?,
+            crt: CrtPass::new(ctx)?,
+            lut: LutPass::new(ctx, lut)?,
+            enabled: [true, false, true, false],
+        })
+    }
+}
+
+impl event::EventHandler for MainState {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.post.begin(ctx)?;
+        graphics::clear(ctx, [0.1, 0.2, 0.3, 1.0].into());
+        let t = timer::get_ticks(ctx) as f32 / 60.0;
+        for i in 0..8 {
+            let angle = t + i as f32 * 0.785;
+            let brightness = i as f32 / 7.0;
+            graphics::circle(
+                ctx,
+                graphics::Color::new(brightness, brightness * 0.8, 1.0 - brightness, 1.0),
+                DrawMode::Fill,
+                cgmath::Point2::new(400.0 + angle.cos() * 200.0, 300.0 + angle.sin() * 200.0),
+                40.0,
+                1.0,
+            )?;
+        }
+
+        let mut passes: Vec<&mut dyn Pass> = Vec::new();
+        if self.enabled[0] {
+            passes.push(&mut self.bloom);
+        }
+        if self.enabled[1] {
+            passes.push(&mut self.blur);
+        }
+        if self.enabled[3] {
+            passes.push(&mut self.lut);
+        }
+        if self.enabled[2] {
+            passes.push(&mut self.crt);
+        }
+        self.post.end(ctx, &mut passes)?;
+
+        graphics::present(ctx)?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        let index = match keycode {
+            KeyCode::Key1 => 0,
+            KeyCode::Key2 => 1,
+            KeyCode::Key3 => 2,
+            KeyCode::Key4 => 3,
+            _ => return,
+        };
+        if !repeat {
+            self.enabled[index] = !self.enabled[index];
+        }
+    }
+}
+
+pub fn main() -> GameResult {
+    let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+        let mut path = path::PathBuf::from(manifest_dir);
+        path.push("resources");
+        path
+    } else {
+        path::PathBuf::from("./resources")
+    };
+
+    let cb = ggez::ContextBuilder::new("postprocess", "ggez").add_resource_path(resource_dir);
+    let (ctx, event_loop) = &mut cb.build()?;
+
+    let state = &mut MainState::new(ctx)?;
+    event::run(ctx, event_loop, state)
+}
@@ -34,6 +34,8 @@ impl MainState {
             "Dim",
             None,
         )?;
+        // Try editing resources/dimmer_150.glslf while this runs.
+        graphics::set_shader_hot_reload(ctx, true);
         Ok(MainState { dim, shader })
     }
 }
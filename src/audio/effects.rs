@@ -0,0 +1,498 @@
+//! Simple DSP effects that can be applied to a sound source.
+//!
+//! These are deliberately basic, meant for things like muffling
+//! sound while the player is underwater rather than for studio-quality
+//! processing.  Each `Effect` only holds parameters; the state it needs
+//! (filter history, delay lines) lives in the audio thread and is kept
+//! as long as the effect at that position in the chain stays the same
+//! kind, so parameters can be tweaked every frame without clicks.
+
+use std::f32;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio;
+
+use timer;
+
+/// A single effect in a source's effect chain.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Effect {
+    /// Removes frequencies above the cutoff, in Hz.
+    LowPass {
+        /// Cutoff frequency in Hz.
+        cutoff: f32,
+    },
+    /// Removes frequencies below the cutoff, in Hz.
+    HighPass {
+        /// Cutoff frequency in Hz.
+        cutoff: f32,
+    },
+    /// Repeats the sound after a delay, with each repeat
+    /// quieter than the last.
+    Echo {
+        /// Time between repeats.
+        delay: Duration,
+        /// How much of each repeat is fed into the next one,
+        /// from 0.0 to just under 1.0.
+        feedback: f32,
+        /// How loud the repeats are relative to the original sound.
+        mix: f32,
+    },
+    /// A simple room reverb.
+    Reverb {
+        /// How large the room sounds, from 0.0 to 1.0.
+        room_size: f32,
+        /// How quickly high frequencies die off, from 0.0 to 1.0.
+        damping: f32,
+        /// How loud the reverberation is relative to the original sound.
+        mix: f32,
+    },
+}
+
+/// How many samples pass between checks for changed effect parameters.
+const PARAMETER_UPDATE_SAMPLES: u32 = 512;
+
+/// The effect parameters a source shares with the audio thread.
+pub(crate) type SharedEffects = Arc<Mutex<Vec<Effect>>>;
+
+/// A biquad filter, using the formulas from the
+/// [Audio EQ Cookbook](http://www.musicdsp.org/files/Audio-EQ-Cookbook.txt).
+#[derive(Debug, Clone)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    /// `(x1, x2, y1, y2)` for each channel.
+    history: Vec<(f32, f32, f32, f32)>,
+}
+
+impl Biquad {
+    fn new(effect: &Effect, sample_rate: u32, channels: u16) -> Self {
+        let mut filter = Biquad {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            history: vec![(0.0, 0.0, 0.0, 0.0); usize::from(channels)],
+        };
+        filter.set_params(effect, sample_rate);
+        filter
+    }
+
+    fn set_params(&mut self, effect: &Effect, sample_rate: u32) {
+        let (cutoff, low_pass) = match *effect {
+            Effect::LowPass { cutoff } => (cutoff, true),
+            Effect::HighPass { cutoff } => (cutoff, false),
+            _ => unreachable!("Biquad built from a non-filter effect"),
+        };
+        let nyquist = sample_rate as f32 / 2.0;
+        let cutoff = cutoff.max(1.0).min(nyquist * 0.99);
+        let w0 = 2.0 * f32::consts::PI * cutoff / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        // Q of 1/sqrt(2), for a flat passband
+        let alpha = sin / (2.0 * f32::consts::FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+        let (b0, b1) = if low_pass {
+            ((1.0 - cos) / 2.0, 1.0 - cos)
+        } else {
+            ((1.0 + cos) / 2.0, -(1.0 + cos))
+        };
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b0 / a0;
+        self.a1 = -2.0 * cos / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        let (x1, x2, y1, y2) = self.history[channel];
+        let y = self.b0 * x + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+        self.history[channel] = (x, x1, y, y1);
+        y
+    }
+}
+
+/// A circular buffer of past samples for one channel.
+#[derive(Debug, Clone)]
+struct DelayLine {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; length.max(1)],
+            position: 0,
+        }
+    }
+
+    /// Changes the length of the delay, keeping as much
+    /// of the existing history as fits.
+    fn resize(&mut self, length: usize) {
+        let length = length.max(1);
+        if length != self.buffer.len() {
+            self.buffer.resize(length, 0.0);
+            self.position %= length;
+        }
+    }
+
+    /// The sample written `len()` samples ago.
+    fn read(&self) -> f32 {
+        self.buffer[self.position]
+    }
+
+    fn write(&mut self, sample: f32) {
+        self.buffer[self.position] = sample;
+        self.position = (self.position + 1) % self.buffer.len();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Echo {
+    lines: Vec<DelayLine>,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Echo {
+    fn new(effect: &Effect, sample_rate: u32, channels: u16) -> Self {
+        let mut echo = Echo {
+            lines: vec![DelayLine::new(1); usize::from(channels)],
+            feedback: 0.0,
+            mix: 0.0,
+        };
+        echo.set_params(effect, sample_rate);
+        echo
+    }
+
+    fn set_params(&mut self, effect: &Effect, sample_rate: u32) {
+        if let Effect::Echo {
+            delay,
+            feedback,
+            mix,
+        } = *effect
+        {
+            let length = (timer::duration_to_f64(delay) * f64::from(sample_rate)) as usize;
+            for line in &mut self.lines {
+                line.resize(length);
+            }
+            self.feedback = feedback.max(0.0).min(0.99);
+            self.mix = mix;
+        }
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        let line = &mut self.lines[channel];
+        let delayed = line.read();
+        line.write(x + delayed * self.feedback);
+        x + delayed * self.mix
+    }
+}
+
+/// Comb filter delay lengths from Freeverb, in samples at 44.1 kHz.
+const COMB_TUNINGS: [usize; 4] = [1116, 1188, 1277, 1356];
+/// Allpass filter delay lengths from Freeverb, in samples at 44.1 kHz.
+const ALLPASS_TUNINGS: [usize; 2] = [556, 441];
+/// Extra delay added to the right channel's lines to decorrelate them.
+const STEREO_SPREAD: usize = 23;
+
+/// A cut-down Freeverb: four damped comb filters in parallel,
+/// followed by two allpass filters in series.
+#[derive(Debug, Clone)]
+struct Reverb {
+    /// Comb lines and the damping filter state for each, per channel.
+    combs: Vec<Vec<(DelayLine, f32)>>,
+    allpasses: Vec<Vec<DelayLine>>,
+    feedback: f32,
+    damping: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    fn new(effect: &Effect, sample_rate: u32, channels: u16) -> Self {
+        let scale = |tuning: usize, channel: usize| {
+            let tuning = tuning + channel * STEREO_SPREAD;
+            tuning * sample_rate as usize / 44_100
+        };
+        let channels = usize::from(channels);
+        let combs = (0..channels)
+            .map(|c| {
+                COMB_TUNINGS
+                    .iter()
+                    .map(|&t| (DelayLine::new(scale(t, c)), 0.0))
+                    .collect()
+            })
+            .collect();
+        let allpasses = (0..channels)
+            .map(|c| {
+                ALLPASS_TUNINGS
+                    .iter()
+                    .map(|&t| DelayLine::new(scale(t, c)))
+                    .collect()
+            })
+            .collect();
+        let mut reverb = Reverb {
+            combs,
+            allpasses,
+            feedback: 0.0,
+            damping: 0.0,
+            mix: 0.0,
+        };
+        reverb.set_params(effect);
+        reverb
+    }
+
+    fn set_params(&mut self, effect: &Effect) {
+        if let Effect::Reverb {
+            room_size,
+            damping,
+            mix,
+        } = *effect
+        {
+            self.feedback = 0.7 + 0.28 * room_size.max(0.0).min(1.0);
+            self.damping = 0.4 * damping.max(0.0).min(1.0);
+            self.mix = mix;
+        }
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        // Freeverb's fixed input gain, to keep the combs from clipping.
+        let input = x * 0.015;
+        let mut wet = 0.0;
+        for &mut (ref mut line, ref mut filtered) in &mut self.combs[channel] {
+            let delayed = line.read();
+            *filtered = delayed * (1.0 - self.damping) + *filtered * self.damping;
+            line.write(input + *filtered * self.feedback);
+            wet += delayed;
+        }
+        for line in &mut self.allpasses[channel] {
+            let delayed = line.read();
+            line.write(wet + delayed * 0.5);
+            wet = delayed - wet;
+        }
+        x + wet * self.mix * 3.0
+    }
+}
+
+/// The audio-thread state for one `Effect`.
+#[derive(Debug, Clone)]
+enum Processor {
+    Filter(Biquad),
+    Echo(Echo),
+    Reverb(Reverb),
+}
+
+impl Processor {
+    fn new(effect: &Effect, sample_rate: u32, channels: u16) -> Self {
+        match *effect {
+            Effect::LowPass { .. } | Effect::HighPass { .. } => {
+                Processor::Filter(Biquad::new(effect, sample_rate, channels))
+            }
+            Effect::Echo { .. } => Processor::Echo(Echo::new(effect, sample_rate, channels)),
+            Effect::Reverb { .. } => Processor::Reverb(Reverb::new(effect, sample_rate, channels)),
+        }
+    }
+
+    /// Updates the parameters in place if `effect` is the same kind of
+    /// effect this was built from, and returns whether it was.
+    fn update(&mut self, effect: &Effect, sample_rate: u32) -> bool {
+        match (self, effect) {
+            (&mut Processor::Filter(ref mut f), &Effect::LowPass { .. })
+            | (&mut Processor::Filter(ref mut f), &Effect::HighPass { .. }) => {
+                f.set_params(effect, sample_rate)
+            }
+            (&mut Processor::Echo(ref mut e), &Effect::Echo { .. }) => {
+                e.set_params(effect, sample_rate)
+            }
+            (&mut Processor::Reverb(ref mut r), &Effect::Reverb { .. }) => r.set_params(effect),
+            _ => return false,
+        }
+        true
+    }
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        match *self {
+            Processor::Filter(ref mut f) => f.process(channel, x),
+            Processor::Echo(ref mut e) => e.process(channel, x),
+            Processor::Reverb(ref mut r) => r.process(channel, x),
+        }
+    }
+}
+
+/// A rodio source that runs its input through a chain of effects,
+/// picking up changes to the shared effect parameters as it plays.
+pub(crate) struct EffectChain<I> {
+    input: I,
+    effects: SharedEffects,
+    /// The parameters the processors were last updated with.
+    current: Vec<Effect>,
+    processors: Vec<Processor>,
+    channels: u16,
+    sample_rate: u32,
+    channel: usize,
+    samples_until_update: u32,
+}
+
+impl<I> EffectChain<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    pub(crate) fn new(input: I, effects: SharedEffects) -> Self {
+        let channels = input.channels();
+        let sample_rate = input.sample_rate();
+        let mut chain = EffectChain {
+            input,
+            effects,
+            current: Vec::new(),
+            processors: Vec::new(),
+            channels,
+            sample_rate,
+            channel: 0,
+            samples_until_update: 0,
+        };
+        chain.update();
+        chain
+    }
+
+    /// Syncs the processors with the shared effect parameters.
+    fn update(&mut self) {
+        let effects = self.effects.lock().unwrap();
+        if *effects == self.current {
+            return;
+        }
+        self.processors.truncate(effects.len());
+        for (i, effect) in effects.iter().enumerate() {
+            if i >= self.processors.len() {
+                self.processors
+                    .push(Processor::new(effect, self.sample_rate, self.channels));
+            } else if !self.processors[i].update(effect, self.sample_rate) {
+                self.processors[i] = Processor::new(effect, self.sample_rate, self.channels);
+            }
+        }
+        self.current = effects.clone();
+    }
+}
+
+impl<I> Iterator for EffectChain<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.samples_until_update == 0 {
+            self.update();
+            self.samples_until_update = PARAMETER_UPDATE_SAMPLES;
+        }
+        self.samples_until_update -= 1;
+
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % usize::from(self.channels.max(1));
+        Some(
+            self.processors
+                .iter_mut()
+                .fold(sample, |x, p| p.process(channel, x)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for EffectChain<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        // Echo and reverb tails get cut off when the input ends,
+        // so the duration is unchanged.
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mono sine wave of the given frequency at 44.1 kHz.
+    fn sine(freq: f32, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| (i as f32 * 2.0 * f32::consts::PI * freq / 44_100.0).sin())
+            .collect()
+    }
+
+    /// Peak amplitude of a signal, ignoring the first
+    /// half so filters have time to settle.
+    fn peak(samples: &[f32]) -> f32 {
+        samples[samples.len() / 2..]
+            .iter()
+            .fold(0.0, |acc: f32, x| acc.max(x.abs()))
+    }
+
+    fn run(effect: Effect, input: &[f32]) -> Vec<f32> {
+        let mut processor = Processor::new(&effect, 44_100, 1);
+        input.iter().map(|&x| processor.process(0, x)).collect()
+    }
+
+    #[test]
+    fn filters() {
+        let low = sine(100.0, 4410);
+        let high = sine(10_000.0, 4410);
+        let low_pass = Effect::LowPass { cutoff: 1000.0 };
+        let high_pass = Effect::HighPass { cutoff: 1000.0 };
+        assert!(peak(&run(low_pass, &low)) > 0.9);
+        assert!(peak(&run(low_pass, &high)) < 0.05);
+        assert!(peak(&run(high_pass, &low)) < 0.05);
+        assert!(peak(&run(high_pass, &high)) > 0.9);
+    }
+
+    #[test]
+    fn echo() {
+        let mut impulse = vec![0.0; 100];
+        impulse[0] = 1.0;
+        let echo = Effect::Echo {
+            delay: Duration::from_millis(1),
+            feedback: 0.5,
+            mix: 1.0,
+        };
+        let output = run(echo, &impulse);
+        // 1 ms at 44.1 kHz is 44 samples.
+        assert_eq!(output[0], 1.0);
+        assert_eq!(output[44], 1.0);
+        assert_eq!(output[88], 0.5);
+        assert_eq!(output[50], 0.0);
+    }
+
+    #[test]
+    fn processors_keep_state_when_updated() {
+        let mut processor = Processor::new(&Effect::LowPass { cutoff: 500.0 }, 44_100, 1);
+        assert!(processor.update(&Effect::LowPass { cutoff: 800.0 }, 44_100));
+        // Both filters share the same state, so they can switch in place.
+        assert!(processor.update(&Effect::HighPass { cutoff: 800.0 }, 44_100));
+        let reverb = Effect::Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.3,
+        };
+        assert!(!processor.update(&reverb, 44_100));
+    }
+}
@@ -0,0 +1,214 @@
+//! Notifications sent from the audio thread about what
+//! sources are doing, so games can sequence sounds without
+//! polling every source each frame.
+
+use std::io;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rodio;
+
+use super::SoundData;
+
+/// How many `AudioEvent`s are kept waiting to be collected before
+/// new ones are dropped.
+pub(crate) const MAX_QUEUED_EVENTS: usize = 1024;
+
+/// A unique identifier for a source, used to tell
+/// which source an `AudioEvent` is about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceId(pub(crate) usize);
+
+/// Something that happened to a playing source.  These are queued
+/// up by the audio thread and collected with
+/// `AudioContext::drain_events()`.
+///
+/// At most 1024 events are kept waiting to be collected; any more
+/// are dropped, so a game that never drains them doesn't use up
+/// memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AudioEvent {
+    /// The sound played by the source reached its end, or was
+    /// stopped by `fade_out_and_stop()`.  Calling `stop()` on a
+    /// source, or dropping it, does not send this.
+    Finished(SourceId),
+    /// A repeating source got to the end of its sound and
+    /// started it over.
+    Looped(SourceId),
+}
+
+/// A rodio source that sends `AudioEvent::Finished` when its input ends.
+pub(crate) struct NotifyFinished<I> {
+    input: I,
+    id: SourceId,
+    events: mpsc::SyncSender<AudioEvent>,
+    finished: bool,
+}
+
+impl<I> NotifyFinished<I> {
+    pub(crate) fn new(input: I, id: SourceId, events: mpsc::SyncSender<AudioEvent>) -> Self {
+        NotifyFinished {
+            input,
+            id,
+            events,
+            finished: false,
+        }
+    }
+}
+
+impl<I> Iterator for NotifyFinished<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next();
+        if sample.is_none() && !self.finished {
+            self.finished = true;
+            // This fails if the queue is full because nobody is
+            // draining it, or if the receiver went away along with
+            // the Context; either way nobody cares.
+            let _ = self.events.try_send(AudioEvent::Finished(self.id));
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> rodio::Source for NotifyFinished<I>
+where
+    I: rodio::Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+type Decoded = rodio::source::SamplesConverter<rodio::Decoder<io::Cursor<SoundData>>, f32>;
+
+/// A rodio source that plays some sound data over and over, sending
+/// `AudioEvent::Looped` each time it starts again.
+///
+/// This is used instead of `rodio::Source::repeat_infinite()`, which
+/// gives no way to tell when a repeat happens.
+pub(crate) struct Looping {
+    data: io::Cursor<SoundData>,
+    current: Decoded,
+    id: SourceId,
+    events: mpsc::SyncSender<AudioEvent>,
+}
+
+impl Looping {
+    pub(crate) fn new(
+        data: io::Cursor<SoundData>,
+        id: SourceId,
+        events: mpsc::SyncSender<AudioEvent>,
+    ) -> Result<Self, rodio::decoder::DecoderError> {
+        use rodio::Source;
+        let current = rodio::Decoder::new(data.clone())?.convert_samples();
+        Ok(Looping {
+            data,
+            current,
+            id,
+            events,
+        })
+    }
+}
+
+impl Iterator for Looping {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        use rodio::Source;
+        if let Some(sample) = self.current.next() {
+            return Some(sample);
+        }
+        // It decoded fine the first time, so this shouldn't fail,
+        // but if it does there's nothing to do but end the sound.
+        self.current = rodio::Decoder::new(self.data.clone()).ok()?.convert_samples();
+        let _ = self.events.try_send(AudioEvent::Looped(self.id));
+        // If the sound is empty this ends it rather than looping forever.
+        self.current.next()
+    }
+}
+
+impl rodio::Source for Looping {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.current.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.current.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.current.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::raw::{RawData, RawSource};
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn finished_is_sent_once() {
+        let (tx, rx) = mpsc::sync_channel(MAX_QUEUED_EVENTS);
+        let generator = |buffer: &mut [f32]| {
+            buffer[0] = 1.0;
+            1
+        };
+        let raw = RawSource::new(RawData {
+            generator: Arc::new(Mutex::new(generator)),
+            channels: 1,
+            sample_rate: 44_100,
+            endless: false,
+        });
+        let mut source = NotifyFinished::new(raw, SourceId(3), tx);
+        assert_eq!(source.next(), Some(1.0));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(source.next(), None);
+        assert_eq!(source.next(), None);
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events, vec![AudioEvent::Finished(SourceId(3))]);
+    }
+
+    #[test]
+    fn full_queue_drops_events() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        for id in 0..2 {
+            let raw = RawSource::new(RawData {
+                generator: Arc::new(Mutex::new(|_: &mut [f32]| 0usize)),
+                channels: 1,
+                sample_rate: 44_100,
+                endless: false,
+            });
+            // This would block forever if sending waited for room.
+            let mut source = NotifyFinished::new(raw, SourceId(id), tx.clone());
+            assert_eq!(source.next(), None);
+        }
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events, vec![AudioEvent::Finished(SourceId(0))]);
+    }
+}
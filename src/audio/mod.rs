@@ -0,0 +1,974 @@
+//! Provides an interface to output sound to the user's speakers.
+//!
+//! It consists of two main types: `SoundData` is just raw sound data,
+//! and a `Source` is a `SoundData` connected to a particular sound
+//! channel.  A `SpatialSource` is a `Source` with a position in the
+//! world, whose volume and panning depend on where it is relative to
+//! the listener stored in the `AudioContext`.
+//!
+//! Both kinds of source have the same playback controls, which are
+//! also collected in the `SoundSource` trait for code that works with
+//! either, and can run their sound through a chain of `Effect`s such
+//! as filters and reverb.
+//!
+//! Sources report when they finish or loop through `AudioEvent`s,
+//! collected with `AudioContext::drain_events()`.
+//!
+//! Sources don't have to play sound files; `Source::from_generator()`
+//! and `Source::from_queue()` play raw samples produced by the game,
+//! for synthesizers, voice chat and the like.
+
+use std::f32;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::path;
+use std::time::Duration;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use mint;
+use nalgebra as na;
+use rodio;
+
+use context::Context;
+use filesystem;
+use timer;
+use GameError;
+use GameResult;
+
+mod effects;
+mod events;
+mod raw;
+
+pub use self::effects::Effect;
+use self::effects::{EffectChain, SharedEffects};
+pub use self::events::{AudioEvent, SourceId};
+use self::events::{Looping, NotifyFinished};
+pub use self::raw::SampleQueue;
+use self::raw::{RawData, RawSource};
+
+/// A struct that contains all information for tracking sound info.
+///
+/// You generally don't have to create this yourself, it will be part
+/// of your `Context` object.
+pub struct AudioContext {
+    device: rodio::Device,
+    /// Position of the listener, shared with the audio thread
+    /// so that playing `SpatialSource`s pick up changes to it.
+    listener: Arc<Mutex<na::Point3<f32>>>,
+    events_tx: mpsc::SyncSender<AudioEvent>,
+    events_rx: mpsc::Receiver<AudioEvent>,
+    next_source_id: usize,
+}
+
+impl AudioContext {
+    /// Create new AudioContext.
+    pub fn new() -> GameResult<AudioContext> {
+        let device = rodio::default_output_device().ok_or_else(|| {
+            GameError::AudioError(String::from(
+                "Could not initialize sound system (for some reason)",
+            ))
+        })?;
+        let (events_tx, events_rx) = mpsc::sync_channel(events::MAX_QUEUED_EVENTS);
+        Ok(AudioContext {
+            device: device,
+            listener: Arc::new(Mutex::new(na::Point3::origin())),
+            events_tx,
+            events_rx,
+            next_source_id: 0,
+        })
+    }
+
+    /// Returns all the `AudioEvent`s that have happened since this
+    /// was last called, oldest first.  Call it once a frame, say in
+    /// your `EventHandler::update()`, to find out when sources have
+    /// finished playing.
+    ///
+    /// Only the first 1024 events are kept until this is called;
+    /// any more are dropped.
+    pub fn drain_events(&mut self) -> Vec<AudioEvent> {
+        self.events_rx.try_iter().collect()
+    }
+
+    fn new_source_id(&mut self) -> SourceId {
+        let id = SourceId(self.next_source_id);
+        self.next_source_id += 1;
+        id
+    }
+
+    /// Returns the current position of the listener.
+    pub fn listener_position(&self) -> na::Point3<f32> {
+        *self.listener.lock().unwrap()
+    }
+
+    /// Sets the position of the listener.  The listener's
+    /// ears always lie along the X axis, with the right
+    /// ear towards positive X.
+    pub fn set_listener_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        *self.listener.lock().unwrap() = na::Point3::from(position.into());
+    }
+}
+
+impl fmt::Debug for AudioContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<AudioContext: {:p}>", self)
+    }
+}
+
+/// Static sound data stored in memory.
+/// It is Arc'ed, so cheap to clone.
+#[derive(Clone, Debug)]
+pub struct SoundData(Arc<[u8]>);
+
+impl SoundData {
+    /// Create a new SoundData from the file at the given path.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let file = &mut filesystem::open(context, path)?;
+        SoundData::from_read(file)
+    }
+
+    /// Copies the data in the given slice into a new SoundData object.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        SoundData(Arc::from(data))
+    }
+
+    /// Creates a SoundData from any Read object; this involves
+    /// copying it into a buffer.
+    pub fn from_read<R>(reader: &mut R) -> GameResult<Self>
+    where
+        R: Read,
+    {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer)?;
+
+        Ok(SoundData::from(buffer))
+    }
+}
+
+impl From<Arc<[u8]>> for SoundData {
+    #[inline]
+    fn from(arc: Arc<[u8]>) -> Self {
+        SoundData(arc)
+    }
+}
+
+impl From<Vec<u8>> for SoundData {
+    fn from(v: Vec<u8>) -> Self {
+        SoundData(Arc::from(v))
+    }
+}
+
+impl From<Box<[u8]>> for SoundData {
+    fn from(b: Box<[u8]>) -> Self {
+        SoundData(Arc::from(b))
+    }
+}
+
+impl AsRef<[u8]> for SoundData {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+/// A trait containing the playback controls shared by all the
+/// sound source types in this module.
+pub trait SoundSource {
+    /// Returns the identifier used for this source in `AudioEvent`s.
+    fn id(&self) -> SourceId;
+
+    /// Plays the source.
+    fn play(&self) -> GameResult;
+
+    /// Sets the source to repeat playback infinitely on next `play()`
+    fn set_repeat(&mut self, repeat: bool);
+
+    /// Gets whether or not the source is set to repeat.
+    fn repeat(&self) -> bool;
+
+    /// Pauses playback
+    fn pause(&self);
+
+    /// Resumes playback
+    fn resume(&self);
+
+    /// Stops playback
+    fn stop(&self);
+
+    /// Returns whether or not the source is stopped
+    /// -- that is, has no more data to play.
+    ///
+    /// This is also true for a source that has never been played;
+    /// to be told when a sound has finished, look for
+    /// `AudioEvent::Finished` instead.
+    fn stopped(&self) -> bool;
+
+    /// Gets the current volume
+    fn volume(&self) -> f32;
+
+    /// Sets the current volume
+    fn set_volume(&mut self, value: f32);
+
+    /// Get whether or not the source is paused
+    fn paused(&self) -> bool;
+
+    /// Get whether or not the source is playing (ie, not paused
+    /// and not stopped)
+    fn playing(&self) -> bool {
+        !self.paused() && !self.stopped()
+    }
+
+    /// Plays the source, ramping its volume up from silence
+    /// over the given duration.
+    fn fade_in(&self, duration: Duration) -> GameResult;
+
+    /// Ramps the volume of the source down to silence over the
+    /// given duration, then stops it.
+    fn fade_out_and_stop(&self, duration: Duration);
+
+    /// Gets the chain of effects the sound is run through.
+    fn effects(&self) -> Vec<Effect>;
+
+    /// Sets the chain of effects the sound is run through, in order.
+    /// This takes effect immediately, even on sounds that are already
+    /// playing, so it can be called every frame to sweep a filter or
+    /// such.
+    fn set_effects(&mut self, effects: &[Effect]);
+}
+
+/// Fades out `from` while fading in `to` over the same duration,
+/// for instance to switch between two pieces of music.
+pub fn crossfade<A, B>(from: &A, to: &B, duration: Duration) -> GameResult
+where
+    A: SoundSource + ?Sized,
+    B: SoundSource + ?Sized,
+{
+    from.fade_out_and_stop(duration);
+    to.fade_in(duration)
+}
+
+/// A volume ramp applied on top of a source's volume.
+///
+/// This is advanced by the audio thread as the sound plays,
+/// so it pauses along with the sound.
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: Duration,
+    elapsed: Duration,
+    stop_when_done: bool,
+}
+
+impl Fade {
+    /// A fade that does nothing, leaving the volume alone.
+    fn none() -> Self {
+        Fade::new(1.0, 1.0, Duration::from_secs(0), false)
+    }
+
+    fn new(from: f32, to: f32, duration: Duration, stop_when_done: bool) -> Self {
+        Fade {
+            from,
+            to,
+            duration,
+            elapsed: Duration::from_secs(0),
+            stop_when_done,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// The volume multiplier at the current point in the fade.
+    fn gain(&self) -> f32 {
+        if self.finished() {
+            self.to
+        } else {
+            let t = timer::duration_to_f64(self.elapsed) / timer::duration_to_f64(self.duration);
+            self.from + (self.to - self.from) * t as f32
+        }
+    }
+}
+
+/// How often, in milliseconds, a playing source updates its fade.
+const FADE_UPDATE_PERIOD_MS: u64 = 5;
+
+/// The type of sound that gets appended to a source's sink.
+type BoxedSource = Box<dyn rodio::Source<Item = f32> + Send>;
+
+/// Wraps the sound so that its volume follows the given `Fade`.
+fn apply_fade(source: BoxedSource, fade: Arc<Mutex<Fade>>) -> BoxedSource {
+    use rodio::Source;
+    let period = Duration::from_millis(FADE_UPDATE_PERIOD_MS);
+    // `periodic_access()` counts samples rather than frames,
+    // so with more than one channel it fires more often.
+    let tick = period / u32::from(source.channels());
+    let initial_gain = fade.lock().unwrap().gain();
+    let faded = source
+        .amplify(initial_gain)
+        .stoppable()
+        .periodic_access(period, move |src| {
+            let mut fade = fade.lock().unwrap();
+            fade.elapsed += tick;
+            if fade.stop_when_done && fade.finished() {
+                src.stop();
+            } else {
+                src.inner_mut().set_factor(fade.gain());
+            }
+        });
+    Box::new(faded)
+}
+
+/// Where a `Source` gets its sound from.
+enum SourceData {
+    /// A sound file, decoded afresh each time it is played.
+    Encoded(io::Cursor<SoundData>),
+    /// Raw samples produced by the game.
+    Raw(RawData),
+}
+
+/// A source of audio data connected to a particular `Channel`.
+/// Will stop playing when dropped.
+// TODO: Check and see if this matches Love2d's semantics!
+// Eventually it might read from a streaming decoder of some kind,
+// but for now it is just an in-memory SoundData structure.
+// The source of a rodio decoder must be Send, which something
+// that contains a reference to a ZipFile is not, so we are going
+// to just slurp all the data into memory for now.
+// There's really a lot of work that needs to be done here, since
+// rodio has gotten better (if still somewhat arcane) and our filesystem
+// code has done the data-slurping-from-zip's for us
+// but for now it works.
+pub struct Source {
+    data: SourceData,
+    sink: rodio::Sink,
+    repeat: bool,
+    fade: Arc<Mutex<Fade>>,
+    effects: SharedEffects,
+    id: SourceId,
+    events: mpsc::SyncSender<AudioEvent>,
+}
+
+impl Source {
+    /// Create a new Source from the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let data = SoundData::new(context, path)?;
+        Source::from_data(context, data)
+    }
+
+    /// Creates a new Source using the given SoundData object.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        let cursor = io::Cursor::new(data);
+        Source::from_source_data(context, SourceData::Encoded(cursor))
+    }
+
+    /// Creates a new Source that plays samples produced by the given
+    /// callback.  Each time more sound is needed it is handed a buffer
+    /// to fill with `f32` samples between -1.0 and 1.0, interleaved if
+    /// there is more than one channel, and returns how many samples it
+    /// wrote.  Writing fewer than the whole buffer ends the sound.
+    ///
+    /// The callback runs on the audio thread, so it should be quick.
+    /// All the times this source is played share the same callback,
+    /// and it ignores `set_repeat()`.
+    pub fn from_generator<F>(
+        context: &mut Context,
+        channels: u16,
+        sample_rate: u32,
+        generator: F,
+    ) -> GameResult<Self>
+    where
+        F: FnMut(&mut [f32]) -> usize + Send + 'static,
+    {
+        Source::from_raw(
+            context,
+            RawData {
+                generator: Arc::new(Mutex::new(generator)),
+                channels,
+                sample_rate,
+                endless: false,
+            },
+        )
+    }
+
+    /// Creates a new Source that plays whatever samples are pushed
+    /// into the given `SampleQueue`.  It plays silence while the queue
+    /// is empty, so it does not stop on its own, and it ignores
+    /// `set_repeat()`.
+    pub fn from_queue(context: &mut Context, queue: &SampleQueue) -> GameResult<Self> {
+        Source::from_raw(
+            context,
+            RawData {
+                generator: queue.generator(),
+                channels: queue.channels(),
+                sample_rate: queue.sample_rate(),
+                endless: true,
+            },
+        )
+    }
+
+    fn from_raw(context: &mut Context, data: RawData) -> GameResult<Self> {
+        if data.channels == 0 || data.sample_rate == 0 {
+            return Err(GameError::AudioError(format!(
+                "Invalid raw audio format: {} channels at {} Hz",
+                data.channels, data.sample_rate
+            )));
+        }
+        Source::from_source_data(context, SourceData::Raw(data))
+    }
+
+    fn from_source_data(context: &mut Context, data: SourceData) -> GameResult<Self> {
+        let audio = &mut context.audio_context;
+        let sink = rodio::Sink::new(&audio.device);
+        Ok(Source {
+            sink,
+            data,
+            repeat: false,
+            fade: Arc::new(Mutex::new(Fade::none())),
+            effects: Arc::new(Mutex::new(Vec::new())),
+            id: audio.new_source_id(),
+            events: audio.events_tx.clone(),
+        })
+    }
+
+    /// Decodes the sound data into something that can be
+    /// appended to the sink, repeating it if necessary and
+    /// running it through the effects and fades, and
+    /// reporting when it ends.
+    fn decode(&self) -> GameResult<BoxedSource> {
+        // Creating a new Decoder each time seems a little messy,
+        // since it may do checking and data-type detection that is
+        // redundant, but it's not super expensive.
+        // See https://github.com/ggez/ggez/issues/98 for discussion
+        use rodio::Source;
+        let decoded: BoxedSource = match self.data {
+            SourceData::Encoded(ref cursor) => {
+                if self.repeat {
+                    Box::new(Looping::new(cursor.clone(), self.id, self.events.clone())?)
+                } else {
+                    Box::new(rodio::Decoder::new(cursor.clone())?.convert_samples())
+                }
+            }
+            SourceData::Raw(ref data) => Box::new(RawSource::new(data.clone())),
+        };
+        let processed = EffectChain::new(decoded, self.effects.clone());
+        let faded = apply_fade(Box::new(processed), self.fade.clone());
+        Ok(Box::new(NotifyFinished::new(
+            faded,
+            self.id,
+            self.events.clone(),
+        )))
+    }
+
+    /// Resets the fade and appends a freshly decoded sound to the sink.
+    fn play_with_fade(&self, fade: Fade) -> GameResult {
+        *self.fade.lock().unwrap() = fade;
+        let source = self.decode()?;
+        self.sink.append(source);
+        Ok(())
+    }
+
+    fn fade_out(&self, duration: Duration) {
+        let mut fade = self.fade.lock().unwrap();
+        *fade = Fade::new(fade.gain(), 0.0, duration, true);
+    }
+}
+
+impl Source {
+    /// Returns the identifier used for this source in `AudioEvent`s.
+    pub fn id(&self) -> SourceId {
+        self.id
+    }
+
+    /// Plays the Source.
+    pub fn play(&self) -> GameResult {
+        self.play_with_fade(Fade::none())
+    }
+
+    /// Sets the source to repeat playback infinitely on next `play()`
+    pub fn set_repeat(&mut self, repeat: bool) {
+        self.repeat = repeat;
+    }
+
+    /// Gets whether or not the source is set to repeat.
+    pub fn repeat(&self) -> bool {
+        self.repeat
+    }
+
+    /// Pauses playback
+    pub fn pause(&self) {
+        self.sink.pause()
+    }
+
+    /// Resumes playback
+    pub fn resume(&self) {
+        self.sink.play()
+    }
+
+    /// Stops playback
+    pub fn stop(&self) {
+        self.sink.stop()
+    }
+
+    /// Returns whether or not the source is stopped
+    /// -- that is, has no more data to play.
+    ///
+    /// This is also true for a source that has never been played;
+    /// to be told when a sound has finished, look for
+    /// `AudioEvent::Finished` instead.
+    pub fn stopped(&self) -> bool {
+        self.sink.empty()
+    }
+
+    /// Gets the current volume
+    pub fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+
+    /// Sets the current volume
+    pub fn set_volume(&mut self, value: f32) {
+        self.sink.set_volume(value)
+    }
+
+    /// Get whether or not the source is paused
+    pub fn paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    /// Get whether or not the source is playing (ie, not paused
+    /// and not stopped)
+    pub fn playing(&self) -> bool {
+        !self.paused() && !self.stopped()
+    }
+
+    /// Plays the source, ramping its volume up from silence
+    /// over the given duration.
+    pub fn fade_in(&self, duration: Duration) -> GameResult {
+        self.play_with_fade(Fade::new(0.0, 1.0, duration, false))
+    }
+
+    /// Ramps the volume of the source down to silence over the
+    /// given duration, then stops it.
+    pub fn fade_out_and_stop(&self, duration: Duration) {
+        self.fade_out(duration)
+    }
+
+    /// Gets the chain of effects the sound is run through.
+    pub fn effects(&self) -> Vec<Effect> {
+        self.effects.lock().unwrap().clone()
+    }
+
+    /// Sets the chain of effects the sound is run through, in order.
+    /// This takes effect immediately, even on sounds that are already
+    /// playing, so it can be called every frame to sweep a filter or
+    /// such.
+    pub fn set_effects(&mut self, effects: &[Effect]) {
+        *self.effects.lock().unwrap() = effects.to_vec();
+    }
+}
+
+impl SoundSource for Source {
+    fn id(&self) -> SourceId {
+        Source::id(self)
+    }
+
+    fn play(&self) -> GameResult {
+        Source::play(self)
+    }
+
+    fn set_repeat(&mut self, repeat: bool) {
+        Source::set_repeat(self, repeat)
+    }
+
+    fn repeat(&self) -> bool {
+        Source::repeat(self)
+    }
+
+    fn pause(&self) {
+        Source::pause(self)
+    }
+
+    fn resume(&self) {
+        Source::resume(self)
+    }
+
+    fn stop(&self) {
+        Source::stop(self)
+    }
+
+    fn stopped(&self) -> bool {
+        Source::stopped(self)
+    }
+
+    fn volume(&self) -> f32 {
+        Source::volume(self)
+    }
+
+    fn set_volume(&mut self, value: f32) {
+        Source::set_volume(self, value)
+    }
+
+    fn paused(&self) -> bool {
+        Source::paused(self)
+    }
+
+    fn fade_in(&self, duration: Duration) -> GameResult {
+        Source::fade_in(self, duration)
+    }
+
+    fn fade_out_and_stop(&self, duration: Duration) {
+        Source::fade_out_and_stop(self, duration)
+    }
+
+    fn effects(&self) -> Vec<Effect> {
+        Source::effects(self)
+    }
+
+    fn set_effects(&mut self, effects: &[Effect]) {
+        Source::set_effects(self, effects)
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Audio source: {:p}>", self)
+    }
+}
+
+/// Describes how the volume of a `SpatialSource` falls off with
+/// its distance from the listener.
+///
+/// Distances closer than the source's reference distance play at
+/// full volume, and distances beyond its max distance are treated
+/// as if they were exactly the max distance.  This is the same
+/// model OpenAL uses.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rolloff {
+    /// No attenuation at all; only panning is applied.
+    None,
+    /// Volume falls linearly from full at the reference
+    /// distance to silent at the max distance.
+    Linear,
+    /// Volume is `ref / (ref + factor * (distance - ref))`.
+    Inverse(f32),
+    /// Volume is `(distance / ref) ^ -factor`.
+    Exponential(f32),
+}
+
+impl Default for Rolloff {
+    fn default() -> Self {
+        Rolloff::Inverse(1.0)
+    }
+}
+
+impl Rolloff {
+    /// Returns the gain for a sound at the given distance.
+    pub fn gain(&self, distance: f32, ref_distance: f32, max_distance: f32) -> f32 {
+        let distance = distance.max(ref_distance).min(max_distance);
+        match *self {
+            Rolloff::None => 1.0,
+            Rolloff::Linear => {
+                if max_distance <= ref_distance {
+                    1.0
+                } else {
+                    1.0 - (distance - ref_distance) / (max_distance - ref_distance)
+                }
+            }
+            Rolloff::Inverse(factor) => {
+                ref_distance / (ref_distance + factor * (distance - ref_distance))
+            }
+            Rolloff::Exponential(factor) => (distance / ref_distance).powf(-factor),
+        }
+    }
+}
+
+/// The part of a `SpatialSource` that the audio thread needs to
+/// see in order to work out its volume.
+#[derive(Debug, Copy, Clone)]
+struct Emitter {
+    position: na::Point3<f32>,
+    ref_distance: f32,
+    max_distance: f32,
+    rolloff: Rolloff,
+}
+
+impl Emitter {
+    /// Returns the `(left, right)` channel volumes for this emitter
+    /// as heard by a listener at the given position.
+    fn channel_volumes(&self, listener: na::Point3<f32>) -> (f32, f32) {
+        let offset = self.position - listener;
+        let distance = offset.norm();
+        let gain = self.rolloff
+            .gain(distance, self.ref_distance, self.max_distance);
+        // -1 is hard left, 1 is hard right.
+        let pan = if distance > f32::EPSILON {
+            offset.x / distance
+        } else {
+            0.0
+        };
+        (gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0))
+    }
+}
+
+/// How often, in milliseconds, a playing `SpatialSource` rechecks its position.
+const SPATIAL_UPDATE_PERIOD_MS: u64 = 10;
+
+/// A `Source` with a position in 2D or 3D space.  Its volume and
+/// stereo panning are worked out from where it is relative to the
+/// listener position set with `AudioContext::set_listener_position()`,
+/// and are kept up to date while it plays.
+///
+/// For 2D games just leave the Z coordinates at zero.
+pub struct SpatialSource {
+    source: Source,
+    listener: Arc<Mutex<na::Point3<f32>>>,
+    emitter: Arc<Mutex<Emitter>>,
+}
+
+impl SpatialSource {
+    /// Create a new SpatialSource from the given file.
+    pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let data = SoundData::new(context, path)?;
+        SpatialSource::from_data(context, data)
+    }
+
+    /// Creates a new SpatialSource using the given SoundData object.
+    /// It starts out at the origin, with a reference distance of
+    /// 1.0, no max distance and `Rolloff::default()`.
+    pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
+        let source = Source::from_data(context, data)?;
+        let emitter = Emitter {
+            position: na::Point3::origin(),
+            ref_distance: 1.0,
+            max_distance: f32::MAX,
+            rolloff: Rolloff::default(),
+        };
+        Ok(SpatialSource {
+            source,
+            listener: context.audio_context.listener.clone(),
+            emitter: Arc::new(Mutex::new(emitter)),
+        })
+    }
+
+    /// Returns the position of the sound emitter.
+    pub fn position(&self) -> na::Point3<f32> {
+        self.emitter.lock().unwrap().position
+    }
+
+    /// Sets the position of the sound emitter.
+    pub fn set_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point3<f32>>,
+    {
+        self.emitter.lock().unwrap().position = na::Point3::from(position.into());
+    }
+
+    /// Sets the position of the sound emitter on the XY plane,
+    /// for 2D games.
+    pub fn set_position_2d<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let p: mint::Point2<f32> = position.into();
+        self.set_position(na::Point3::new(p.x, p.y, 0.0));
+    }
+
+    /// Returns the rolloff curve used to attenuate the sound with distance.
+    pub fn rolloff(&self) -> Rolloff {
+        self.emitter.lock().unwrap().rolloff
+    }
+
+    /// Sets the rolloff curve used to attenuate the sound with distance.
+    pub fn set_rolloff(&mut self, rolloff: Rolloff) {
+        self.emitter.lock().unwrap().rolloff = rolloff;
+    }
+
+    /// Sets the distances the rolloff curve is evaluated between.
+    /// The sound plays at full volume closer than `ref_distance`,
+    /// and gets no quieter further away than `max_distance`.
+    /// `ref_distance` must be greater than zero.
+    pub fn set_distances(&mut self, ref_distance: f32, max_distance: f32) {
+        let mut emitter = self.emitter.lock().unwrap();
+        emitter.ref_distance = ref_distance;
+        emitter.max_distance = max_distance;
+    }
+}
+
+impl SpatialSource {
+    /// Like `Source::play_with_fade()`, but positions the sound
+    /// before appending it.
+    fn play_with_fade(&self, fade: Fade) -> GameResult {
+        use rodio::Source;
+        *self.source.fade.lock().unwrap() = fade;
+        let listener = self.listener.clone();
+        let emitter = self.emitter.clone();
+        let volumes = |listener: &Mutex<na::Point3<f32>>, emitter: &Mutex<Emitter>| {
+            let listener = *listener.lock().unwrap();
+            emitter.lock().unwrap().channel_volumes(listener)
+        };
+        let (left, right) = volumes(&listener, &emitter);
+        let source = self.source.decode()?;
+        // `ChannelVolume` mixes all the input channels together
+        // so we scale them back down to avoid clipping.
+        let mix = 1.0 / f32::from(source.channels());
+        let spatial = rodio::source::ChannelVolume::new(source.amplify(mix), vec![left, right])
+            .periodic_access(Duration::from_millis(SPATIAL_UPDATE_PERIOD_MS), move |src| {
+                let (left, right) = volumes(&listener, &emitter);
+                src.set_volume(0, left);
+                src.set_volume(1, right);
+            });
+        self.source.sink.append(spatial);
+        Ok(())
+    }
+}
+
+impl SoundSource for SpatialSource {
+    fn id(&self) -> SourceId {
+        self.source.id()
+    }
+
+    fn play(&self) -> GameResult {
+        self.play_with_fade(Fade::none())
+    }
+
+    fn set_repeat(&mut self, repeat: bool) {
+        self.source.set_repeat(repeat)
+    }
+
+    fn repeat(&self) -> bool {
+        self.source.repeat()
+    }
+
+    fn pause(&self) {
+        self.source.pause()
+    }
+
+    fn resume(&self) {
+        self.source.resume()
+    }
+
+    fn stop(&self) {
+        self.source.stop()
+    }
+
+    fn stopped(&self) -> bool {
+        self.source.stopped()
+    }
+
+    fn volume(&self) -> f32 {
+        self.source.volume()
+    }
+
+    fn set_volume(&mut self, value: f32) {
+        self.source.set_volume(value)
+    }
+
+    fn paused(&self) -> bool {
+        self.source.paused()
+    }
+
+    fn fade_in(&self, duration: Duration) -> GameResult {
+        self.play_with_fade(Fade::new(0.0, 1.0, duration, false))
+    }
+
+    fn fade_out_and_stop(&self, duration: Duration) {
+        self.source.fade_out(duration)
+    }
+
+    fn effects(&self) -> Vec<Effect> {
+        self.source.effects()
+    }
+
+    fn set_effects(&mut self, effects: &[Effect]) {
+        self.source.set_effects(effects)
+    }
+}
+
+impl fmt::Debug for SpatialSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Spatial audio source: {:p}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolloff_curves() {
+        assert_eq!(Rolloff::None.gain(50.0, 1.0, 100.0), 1.0);
+        assert_eq!(Rolloff::Linear.gain(0.5, 1.0, 101.0), 1.0);
+        assert_eq!(Rolloff::Linear.gain(51.0, 1.0, 101.0), 0.5);
+        assert_eq!(Rolloff::Linear.gain(500.0, 1.0, 101.0), 0.0);
+        assert_eq!(Rolloff::Inverse(1.0).gain(4.0, 1.0, 100.0), 0.25);
+        assert_eq!(Rolloff::Inverse(1.0).gain(400.0, 1.0, 100.0), 0.01);
+        assert_eq!(Rolloff::Exponential(2.0).gain(2.0, 1.0, 100.0), 0.25);
+    }
+
+    #[test]
+    fn fade_ramps() {
+        let mut fade = Fade::new(0.0, 1.0, Duration::from_millis(100), false);
+        assert_eq!(fade.gain(), 0.0);
+        fade.elapsed = Duration::from_millis(25);
+        assert_eq!(fade.gain(), 0.25);
+        assert!(!fade.finished());
+        fade.elapsed = Duration::from_millis(150);
+        assert_eq!(fade.gain(), 1.0);
+        assert!(fade.finished());
+        assert_eq!(Fade::none().gain(), 1.0);
+    }
+
+    /// Returns how many samples of a constant sound it takes for a
+    /// fade out over 100ms to reach silence.
+    fn samples_to_fade_out(channels: u16) -> usize {
+        let sample_rate = 1000;
+        let samples = vec![1.0f32; 1000 * usize::from(channels)];
+        let sound = rodio::buffer::SamplesBuffer::new(channels, sample_rate, samples);
+        let fade = Fade::new(1.0, 0.0, Duration::from_millis(100), false);
+        apply_fade(Box::new(sound), Arc::new(Mutex::new(fade)))
+            .position(|sample| sample == 0.0)
+            .expect("Fade never reached silence")
+    }
+
+    #[test]
+    fn fade_timing_follows_channels() {
+        // 100ms at 1000 Hz is 100 frames, however many channels
+        // each frame has.
+        let mono = samples_to_fade_out(1);
+        let stereo = samples_to_fade_out(2);
+        assert!(mono >= 95 && mono <= 105, "mono fade took {} samples", mono);
+        assert!(
+            stereo >= 190 && stereo <= 210,
+            "stereo fade took {} samples",
+            stereo
+        );
+    }
+
+    #[test]
+    fn spatial_panning() {
+        let emitter = Emitter {
+            position: na::Point3::new(3.0, 0.0, 0.0),
+            ref_distance: 1.0,
+            max_distance: 100.0,
+            rolloff: Rolloff::None,
+        };
+        assert_eq!(emitter.channel_volumes(na::Point3::origin()), (0.0, 1.0));
+        assert_eq!(
+            emitter.channel_volumes(na::Point3::new(6.0, 0.0, 0.0)),
+            (1.0, 0.0)
+        );
+        assert_eq!(
+            emitter.channel_volumes(na::Point3::new(3.0, 5.0, 0.0)),
+            (1.0, 1.0)
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Playing raw PCM samples that come from the game rather than from
+//! an encoded sound file.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio;
+
+/// How many frames a `RawSource` asks its generator for at a time.
+const FRAMES_PER_CHUNK: usize = 512;
+
+/// A callback that fills a buffer of interleaved samples and returns
+/// how many it wrote.  Writing fewer than the whole buffer ends the sound.
+pub(crate) type Generator = Arc<Mutex<dyn FnMut(&mut [f32]) -> usize + Send>>;
+
+/// A handle to a queue of raw samples to be played by a `Source`
+/// created with `Source::from_queue()`.
+///
+/// Samples are `f32`s between -1.0 and 1.0, interleaved if there is
+/// more than one channel.  If the queue runs dry the source plays
+/// silence until more samples are pushed, rather than stopping.
+/// It is cheap to clone, and clones all refer to the same queue, so
+/// one can be handed to another thread (for instance one receiving
+/// voice data from the network).
+#[derive(Clone)]
+pub struct SampleQueue {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl SampleQueue {
+    /// Creates a new, empty queue for samples with the given
+    /// number of channels and sample rate.
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        SampleQueue {
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Adds samples to the end of the queue.
+    pub fn push(&self, samples: &[f32]) {
+        self.samples.lock().unwrap().extend(samples);
+    }
+
+    /// Returns the number of samples waiting to be played.
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    /// Returns whether there are no samples waiting to be played.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Throws away all the samples waiting to be played.
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear()
+    }
+
+    /// Returns the number of channels the samples are for.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the sample rate the samples are played at.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Turns the queue into a generator that hands out as many whole
+    /// frames as are queued, up to the size of the buffer.  It is meant
+    /// for an endless `RawSource`, which doesn't stop when it gets a
+    /// short write.
+    pub(crate) fn generator(&self) -> Generator {
+        let samples = self.samples.clone();
+        let channels = usize::from(self.channels.max(1));
+        Arc::new(Mutex::new(move |buffer: &mut [f32]| {
+            let mut samples = samples.lock().unwrap();
+            // Only whole frames, so the channels stay in step.
+            let count = buffer.len().min(samples.len() / channels * channels);
+            for (out, sample) in buffer.iter_mut().zip(samples.drain(..count)) {
+                *out = sample;
+            }
+            count
+        }))
+    }
+}
+
+impl fmt::Debug for SampleQueue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<SampleQueue: {} channels at {} Hz, {} samples queued>",
+            self.channels,
+            self.sample_rate,
+            self.len()
+        )
+    }
+}
+
+/// Where a `Source` gets the samples for a raw sound from.
+#[derive(Clone)]
+pub(crate) struct RawData {
+    pub(crate) generator: Generator,
+    pub(crate) channels: u16,
+    pub(crate) sample_rate: u32,
+    /// Whether the sound keeps going when the generator runs short,
+    /// playing silence while it has nothing, instead of ending.
+    pub(crate) endless: bool,
+}
+
+/// A rodio source that pulls samples from a `Generator` a chunk at a time.
+pub(crate) struct RawSource {
+    data: RawData,
+    buffer: Vec<f32>,
+    position: usize,
+    /// How much of `buffer` the generator filled last time.
+    filled: usize,
+}
+
+impl RawSource {
+    pub(crate) fn new(data: RawData) -> Self {
+        let chunk = FRAMES_PER_CHUNK * usize::from(data.channels.max(1));
+        RawSource {
+            data,
+            buffer: vec![0.0; chunk],
+            position: 0,
+            filled: 0,
+        }
+    }
+
+    /// Whether the last chunk was short, meaning the sound is over
+    /// once it has been played.
+    fn finishing(&self) -> bool {
+        !self.data.endless && self.filled < self.buffer.len()
+    }
+}
+
+impl Iterator for RawSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.filled {
+            if self.filled > 0 && self.finishing() {
+                return None;
+            }
+            let mut generator = self.data.generator.lock().unwrap();
+            self.filled = generator(&mut self.buffer).min(self.buffer.len());
+            self.position = 0;
+            if self.filled == 0 {
+                if !self.data.endless {
+                    return None;
+                }
+                // Nothing to play yet, so play a single frame of
+                // silence and ask again after it, to keep the delay
+                // short once more samples arrive.
+                let frame = usize::from(self.data.channels.max(1));
+                for out in &mut self.buffer[..frame] {
+                    *out = 0.0;
+                }
+                self.filled = frame;
+            }
+        }
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl rodio::Source for RawSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.data.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.data.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_pads_with_silence() {
+        let queue = SampleQueue::new(2, 44_100);
+        queue.push(&[0.5, -0.5, 0.25]);
+        let mut source = RawSource::new(RawData {
+            generator: queue.generator(),
+            channels: 2,
+            sample_rate: 44_100,
+            endless: true,
+        });
+        assert_eq!(source.next(), Some(0.5));
+        assert_eq!(source.next(), Some(-0.5));
+        // Half a frame isn't played until the rest of it arrives.
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(queue.len(), 1);
+        // Samples pushed later play right after the current frame of
+        // silence rather than after a whole chunk of it.
+        queue.push(&[0.75]);
+        assert_eq!(source.next(), Some(0.25));
+        assert_eq!(source.next(), Some(0.75));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn short_generator_ends_sound() {
+        let mut remaining = FRAMES_PER_CHUNK + 3;
+        let generator = move |buffer: &mut [f32]| {
+            let count = remaining.min(buffer.len());
+            remaining -= count;
+            for out in &mut buffer[..count] {
+                *out = 1.0;
+            }
+            count
+        };
+        let source = RawSource::new(RawData {
+            generator: Arc::new(Mutex::new(generator)),
+            channels: 1,
+            sample_rate: 44_100,
+            endless: false,
+        });
+        assert_eq!(source.count(), FRAMES_PER_CHUNK + 3);
+    }
+}
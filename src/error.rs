@@ -12,6 +12,7 @@ use app_dirs2::AppDirsError;
 use gilrs;
 use image;
 use rodio::decoder::DecoderError;
+use serde_json;
 use toml;
 use zip;
 
@@ -45,6 +46,9 @@ pub enum GameError {
     VideoError(String),
     /// Something went wrong compiling shaders
     ShaderProgramError(gfx::shade::ProgramError),
+    /// A shader failed to validate or compile; the message names the
+    /// shader, the stage and the offending source line
+    ShaderError(String),
     /// Something went wrong with Gilrs
     GamepadError(String),
 }
@@ -60,6 +64,7 @@ impl fmt::Display for GameError {
                 s, paths
             ),
             GameError::WindowError(ref e) => write!(f, "Window creation error: {}", e),
+            GameError::ShaderError(ref s) => write!(f, "Shader error: {}", s),
             _ => write!(f, "GameError {:?}", self),
         }
     }
@@ -81,6 +86,7 @@ impl Error for GameError {
             GameError::FontError(_) => "Font error",
             GameError::VideoError(_) => "Video error",
             GameError::ShaderProgramError(_) => "Shader program error",
+            GameError::ShaderError(_) => "Shader error",
             GameError::GamepadError(_) => "Gamepad error",
         }
     }
@@ -125,6 +131,13 @@ impl From<toml::ser::Error> for GameError {
     }
 }
 
+impl From<serde_json::Error> for GameError {
+    fn from(e: serde_json::Error) -> GameError {
+        let errstr = format!("JSON decode error: {}", e);
+        GameError::ResourceLoadError(errstr)
+    }
+}
+
 impl From<zip::result::ZipError> for GameError {
     fn from(e: zip::result::ZipError) -> GameError {
         let errstr = format!("Zip error: {}", e.description());
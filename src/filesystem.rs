@@ -29,6 +29,7 @@ use std::env;
 use std::fmt;
 use std::io;
 use std::path;
+use std::time::SystemTime;
 
 use app_dirs2::*;
 
@@ -233,6 +234,15 @@ impl Filesystem {
             .unwrap_or(false)
     }
 
+    /// Returns when the file at the path was last modified, or `None`
+    /// if it doesn't exist or comes from a zip file.
+    pub(crate) fn modified<P: AsRef<path::Path>>(&self, path: P) -> Option<SystemTime> {
+        self.vfs
+            .metadata(path.as_ref())
+            .ok()
+            .and_then(|m| m.modified())
+    }
+
     /// Returns a list of all files and directories in the resource directory,
     /// in no particular order.
     ///
@@ -0,0 +1,572 @@
+//! Frame-by-frame sprite animation.
+//!
+//! An `Animation` plays a sequence of frames, each of which is a
+//! rectangle of a single `Image`: a sprite sheet sliced into a grid,
+//! a page of an `Atlas`, or a sheet exported from
+//! [Aseprite](https://www.aseprite.org/) as PNG plus JSON.
+//!
+//! Animations are advanced with `update()` once per game update, which
+//! uses the frame time from the `timer` module, or with `advance()` if you
+//! keep track of time yourself.  Drawing an `Animation` draws its current
+//! frame.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path;
+use std::time::Duration;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json;
+
+use context::Context;
+use filesystem;
+use graphics::atlas::Atlas;
+use graphics::{BlendMode, DrawTransform, Drawable, Image, Rect};
+use timer;
+use GameError;
+use GameResult;
+
+/// How an `Animation` carries on once it reaches its last frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayMode {
+    /// Stop on the last frame.
+    Once,
+    /// Start over from the first frame.
+    Loop,
+    /// Play backwards to the first frame, then forwards again, and so on.
+    PingPong,
+}
+
+/// A single frame of an `Animation`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    /// The part of the animation's image to draw, in the same
+    /// normalized coordinates as `DrawParam::src`.
+    pub src: Rect,
+    /// How long the frame is shown for.  Frames with a duration
+    /// of zero are held until the animation is reset.
+    pub duration: Duration,
+}
+
+/// Which frames to play, in which order, and how far along we are.
+///
+/// Kept apart from the `Image` so it can be tested without a `Context`.
+#[derive(Debug, Clone)]
+struct Playback {
+    sequence: Vec<usize>,
+    mode: PlayMode,
+    position: usize,
+    forward: bool,
+    elapsed: Duration,
+    started: bool,
+    finished: bool,
+    events: Vec<(usize, String)>,
+    fired: Vec<String>,
+}
+
+impl Playback {
+    fn new(frames: usize) -> Self {
+        Self {
+            sequence: (0..frames).collect(),
+            mode: PlayMode::Loop,
+            position: 0,
+            forward: true,
+            elapsed: Duration::new(0, 0),
+            started: false,
+            finished: false,
+            events: vec![],
+            fired: vec![],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+        self.forward = true;
+        self.elapsed = Duration::new(0, 0);
+        self.started = false;
+        self.finished = false;
+    }
+
+    fn current(&self) -> usize {
+        self.sequence[self.position]
+    }
+
+    fn advance(&mut self, frames: &[Frame], dt: Duration) {
+        if self.sequence.is_empty() {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            self.enter();
+        }
+        if self.finished {
+            return;
+        }
+        self.elapsed += dt;
+        loop {
+            let duration = frames[self.current()].duration;
+            if duration == Duration::new(0, 0) || self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+            self.step();
+            if self.finished {
+                self.elapsed = Duration::new(0, 0);
+                break;
+            }
+        }
+    }
+
+    /// Moves on to the next frame of the sequence.
+    fn step(&mut self) {
+        let last = self.sequence.len() - 1;
+        match self.mode {
+            PlayMode::Once => {
+                if self.position == last {
+                    self.finished = true;
+                    return;
+                }
+                self.position += 1;
+            }
+            PlayMode::Loop => {
+                self.position = if self.position == last {
+                    0
+                } else {
+                    self.position + 1
+                };
+            }
+            PlayMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.forward && self.position == last {
+                    self.forward = false;
+                } else if !self.forward && self.position == 0 {
+                    self.forward = true;
+                }
+                if self.forward {
+                    self.position += 1;
+                } else {
+                    self.position -= 1;
+                }
+            }
+        }
+        self.enter();
+    }
+
+    /// Fires the events of the frame we just arrived on.
+    fn enter(&mut self) {
+        let frame = self.current();
+        for &(event_frame, ref name) in &self.events {
+            if event_frame == frame {
+                self.fired.push(name.clone());
+            }
+        }
+    }
+}
+
+/// A named sub-sequence of an `Animation`'s frames, such as
+/// "walk" or "jump", like Aseprite's tags.
+#[derive(Debug, Clone)]
+struct Tag {
+    sequence: Vec<usize>,
+    ping_pong: bool,
+}
+
+/// A sprite animation played from the frames of a single `Image`.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    image: Image,
+    frames: Vec<Frame>,
+    tags: HashMap<String, Tag>,
+    playback: Playback,
+}
+
+impl Animation {
+    /// Creates a new `Animation` playing the given frames of the image
+    /// in order, looping.
+    pub fn new(image: Image, frames: Vec<Frame>) -> Self {
+        let playback = Playback::new(frames.len());
+        Self {
+            image,
+            frames,
+            tags: HashMap::new(),
+            playback,
+        }
+    }
+
+    /// Creates a new `Animation` by slicing the image into a grid of
+    /// `columns` by `rows` equally sized frames, which are played
+    /// row by row and all shown for `frame_duration`.
+    pub fn from_grid(image: Image, columns: u16, rows: u16, frame_duration: Duration) -> Self {
+        let (w, h) = (1.0 / f32::from(columns), 1.0 / f32::from(rows));
+        let mut frames = Vec::with_capacity(columns as usize * rows as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                frames.push(Frame {
+                    src: Rect::new(f32::from(column) * w, f32::from(row) * h, w, h),
+                    duration: frame_duration,
+                });
+            }
+        }
+        Self::new(image, frames)
+    }
+
+    /// Creates a new `Animation` from the named regions of an `Atlas`,
+    /// which all have to be on the same page, showing each for
+    /// `frame_duration`.
+    pub fn from_atlas(atlas: &Atlas, names: &[&str], frame_duration: Duration) -> GameResult<Self> {
+        let mut page = None;
+        let mut frames = Vec::with_capacity(names.len());
+        for name in names {
+            let region = atlas.get(name).ok_or_else(|| {
+                GameError::ResourceLoadError(format!("Atlas has no sprite named {:?}", name))
+            })?;
+            if *page.get_or_insert(region.page) != region.page {
+                let msg = format!(
+                    "Animation frames have to be on one atlas page, but {:?} is on page {}",
+                    name, region.page
+                );
+                return Err(GameError::ResourceLoadError(msg));
+            }
+            frames.push(Frame {
+                src: region.src,
+                duration: frame_duration,
+            });
+        }
+        let image = match page.and_then(|page| atlas.page(page)) {
+            Some(image) => image.clone(),
+            None => {
+                let msg = "Cannot make an animation without frames".to_owned();
+                return Err(GameError::ResourceLoadError(msg));
+            }
+        };
+        Ok(Self::new(image, frames))
+    }
+
+    /// Loads an animation from a JSON sprite sheet exported by Aseprite,
+    /// along with the image it refers to.  Both the "Hash" and "Array"
+    /// JSON layouts are supported.
+    ///
+    /// Frame tags become tags of the `Animation` that can be played
+    /// with `play_tag()`; until then all frames are played in order.
+    pub fn from_aseprite_json<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let sheet: AsepriteSheet = {
+            let mut s = String::new();
+            let mut file = filesystem::open(ctx, path)?;
+            let _ = file.read_to_string(&mut s)?;
+            serde_json::from_str(&s)?
+        };
+        let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+        let image = Image::new(ctx, dir.join(&sheet.meta.image))?;
+        Ok(sheet.into_animation(image))
+    }
+
+    /// Advances the animation by the time since the last update,
+    /// as reported by `timer::get_delta()`.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::get_delta(ctx));
+    }
+
+    /// Advances the animation by the given amount of time.
+    pub fn advance(&mut self, dt: Duration) {
+        self.playback.advance(&self.frames, dt);
+    }
+
+    /// Goes back to the start of the current sequence.
+    pub fn reset(&mut self) {
+        self.playback.reset();
+    }
+
+    /// Plays the frames of the tag with the given name from the start,
+    /// in ping-pong mode if the tag asks for it and looping otherwise.
+    pub fn play_tag(&mut self, name: &str) -> GameResult {
+        let tag = self.tags.get(name).ok_or_else(|| {
+            GameError::ResourceLoadError(format!("Animation has no tag named {:?}", name))
+        })?;
+        self.playback.sequence = tag.sequence.clone();
+        self.playback.mode = if tag.ping_pong {
+            PlayMode::PingPong
+        } else {
+            PlayMode::Loop
+        };
+        self.playback.reset();
+        Ok(())
+    }
+
+    /// Returns an iterator over the names of the animation's tags.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    /// Gets the play mode.
+    pub fn mode(&self) -> PlayMode {
+        self.playback.mode
+    }
+
+    /// Sets the play mode.
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.playback.mode = mode;
+        self.playback.finished = false;
+    }
+
+    /// Adds an event with the given name that is reported by
+    /// `drain_events()` whenever the animation arrives at the given frame.
+    pub fn add_event(&mut self, frame: usize, name: &str) {
+        self.playback.events.push((frame, name.to_owned()));
+    }
+
+    /// Returns the names of all the events reached since
+    /// the last call, in order.
+    pub fn drain_events(&mut self) -> Vec<String> {
+        self.playback.fired.drain(..).collect()
+    }
+
+    /// Returns the index of the frame currently shown.
+    pub fn current_frame(&self) -> usize {
+        if self.playback.sequence.is_empty() {
+            0
+        } else {
+            self.playback.current()
+        }
+    }
+
+    /// Returns whether a `PlayMode::Once` animation has reached its end.
+    pub fn is_finished(&self) -> bool {
+        self.playback.finished
+    }
+
+    /// Returns all the frames of the animation.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the image the frames are taken from.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+impl Drawable for Animation {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        let mut param = param.into();
+        if let Some(frame) = self.frames.get(self.current_frame()) {
+            param.src = frame.src;
+            self.image.draw(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.image.set_blend_mode(mode);
+    }
+
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.image.get_blend_mode()
+    }
+}
+
+/// The parts of an Aseprite JSON export we care about.
+#[derive(Debug, Deserialize)]
+struct AsepriteSheet {
+    frames: AsepriteFrames,
+    meta: AsepriteMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct AsepriteMeta {
+    image: String,
+    size: AsepriteSize,
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<AsepriteTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AsepriteSize {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct AsepriteRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct AsepriteFrame {
+    frame: AsepriteRect,
+    /// In milliseconds.
+    duration: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct AsepriteTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+/// The frames of a sheet, which Aseprite writes either as an array
+/// or as an object keyed by file name.  The order matters either way.
+#[derive(Debug)]
+struct AsepriteFrames(Vec<AsepriteFrame>);
+
+impl<'de> Deserialize<'de> for AsepriteFrames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = AsepriteFrames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array or map of frames")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut frames = vec![];
+                while let Some(frame) = seq.next_element()? {
+                    frames.push(frame);
+                }
+                Ok(AsepriteFrames(frames))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut frames = vec![];
+                while let Some((_, frame)) = map.next_entry::<String, AsepriteFrame>()? {
+                    frames.push(frame);
+                }
+                Ok(AsepriteFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_any(FramesVisitor)
+    }
+}
+
+impl AsepriteSheet {
+    fn into_animation(self, image: Image) -> Animation {
+        let (w, h) = (self.meta.size.w, self.meta.size.h);
+        let frames = (self.frames.0)
+            .iter()
+            .map(|f| Frame {
+                src: Rect::new(f.frame.x / w, f.frame.y / h, f.frame.w / w, f.frame.h / h),
+                duration: Duration::from_millis(f.duration),
+            })
+            .collect::<Vec<_>>();
+        let count = frames.len();
+        let mut animation = Animation::new(image, frames);
+        for tag in self.meta.frame_tags {
+            if tag.from >= count || tag.from > tag.to {
+                continue;
+            }
+            let last = tag.to.min(count - 1);
+            let mut sequence: Vec<usize> = (tag.from..last + 1).collect();
+            if tag.direction == "reverse" {
+                sequence.reverse();
+            }
+            let tag_data = Tag {
+                sequence,
+                ping_pong: tag.direction == "pingpong",
+            };
+            let _ = animation.tags.insert(tag.name, tag_data);
+        }
+        animation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(durations: &[u64]) -> Vec<Frame> {
+        durations
+            .iter()
+            .map(|&ms| Frame {
+                src: Rect::one(),
+                duration: Duration::from_millis(ms),
+            })
+            .collect()
+    }
+
+    fn positions(playback: &mut Playback, frames: &[Frame], steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                playback.advance(frames, Duration::from_millis(10));
+                playback.current()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn play_modes() {
+        let frames = frames(&[10, 10, 10]);
+        let mut playback = Playback::new(3);
+        assert_eq!(positions(&mut playback, &frames, 5), vec![1, 2, 0, 1, 2]);
+
+        let mut playback = Playback::new(3);
+        playback.mode = PlayMode::Once;
+        assert_eq!(positions(&mut playback, &frames, 4), vec![1, 2, 2, 2]);
+        assert!(playback.finished);
+
+        let mut playback = Playback::new(3);
+        playback.mode = PlayMode::PingPong;
+        assert_eq!(
+            positions(&mut playback, &frames, 6),
+            vec![1, 2, 1, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn per_frame_durations_and_events() {
+        let frames = frames(&[10, 30, 10]);
+        let mut playback = Playback::new(3);
+        playback.events.push((0, "start".to_owned()));
+        playback.events.push((1, "step".to_owned()));
+        assert_eq!(positions(&mut playback, &frames, 5), vec![1, 1, 1, 2, 0]);
+        assert_eq!(playback.fired, vec!["start", "step", "start"]);
+
+        // A long frame time skips frames but still reports them.
+        playback.fired.clear();
+        playback.advance(&frames, Duration::from_millis(50));
+        assert_eq!(playback.current(), 0);
+        assert_eq!(playback.fired, vec!["step", "start"]);
+    }
+
+    #[test]
+    fn aseprite_frames() {
+        let hash = r#"{
+            "frames": {
+                "run 1.ase": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+                "run 0.ase": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 }, "duration": 50 }
+            },
+            "meta": {
+                "image": "run.png",
+                "size": { "w": 32, "h": 16 },
+                "frameTags": [ { "name": "back", "from": 0, "to": 1, "direction": "reverse" } ]
+            }
+        }"#;
+        let sheet: AsepriteSheet = serde_json::from_str(hash).unwrap();
+        assert_eq!(sheet.frames.0.len(), 2);
+        assert_eq!(sheet.frames.0[0].duration, 100);
+        assert_eq!(sheet.frames.0[1].frame.x, 16.0);
+        assert_eq!(sheet.meta.frame_tags[0].direction, "reverse");
+
+        let array = r#"{
+            "frames": [ { "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "duration": 20 } ],
+            "meta": { "image": "a.png", "size": { "w": 8, "h": 8 } }
+        }"#;
+        let sheet: AsepriteSheet = serde_json::from_str(array).unwrap();
+        assert_eq!(sheet.frames.0.len(), 1);
+        assert!(sheet.meta.frame_tags.is_empty());
+    }
+}
@@ -0,0 +1,442 @@
+//! A texture atlas packs many small images into a few large
+//! texture pages, so that sprites which would otherwise each need
+//! their own `Image` can share one.  Combined with a `SpriteBatch`
+//! this lets you draw hundreds of different sprites with a single
+//! draw call.
+//!
+//! Atlases are put together with an `AtlasBuilder`, either at load
+//! time or ahead of time.  A built atlas can be written out as PNG
+//! pages plus a TOML sidecar file describing where each sprite
+//! ended up, which `Atlas::load()` reads back in.  The sidecar
+//! looks like this:
+//!
+//! ```toml
+//! [[pages]]
+//! image = "sprites-0.png"
+//! width = 512
+//! height = 512
+//!
+//! [[sprites]]
+//! name = "player"
+//! page = 0
+//! x = 0
+//! y = 0
+//! w = 32
+//! h = 48
+//! ```
+//!
+//! Page image paths are relative to the directory the sidecar is in.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path;
+use std::u16;
+
+use image;
+use toml;
+
+use context::Context;
+use filesystem;
+use graphics::{DrawParam, Image, Rect};
+use GameError;
+use GameResult;
+
+/// Where a named sprite lives in an `Atlas`.
+///
+/// Use `src` as the `DrawParam::src` when drawing the page
+/// image (or a `SpriteBatch` made from it) to draw just this sprite.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// The index of the page the sprite was packed into.
+    pub page: usize,
+    /// The sprite's rectangle on the page, in the normalized
+    /// `[0.0, 1.0]` texture coordinates `DrawParam::src` takes.
+    pub src: Rect,
+    /// The sprite's width in pixels.
+    pub width: u16,
+    /// The sprite's height in pixels.
+    pub height: u16,
+}
+
+impl AtlasRegion {
+    /// Returns a `DrawParam` with its `src` set to this region.
+    pub fn param(&self) -> DrawParam {
+        DrawParam::new().src(self.src)
+    }
+}
+
+/// A set of texture pages with named sprites packed into them.
+///
+/// Cloning an `Atlas` is fairly cheap, since the pages are
+/// just `Image`s.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pages: Vec<Image>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    /// Loads an atlas from the given TOML sidecar file and the page
+    /// images it refers to.
+    pub fn load<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let layout = {
+            let mut s = String::new();
+            let mut file = filesystem::open(ctx, path)?;
+            let _ = file.read_to_string(&mut s)?;
+            toml::from_str::<AtlasFile>(&s)?
+        };
+        let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+        let mut pages = Vec::with_capacity(layout.pages.len());
+        for page in &layout.pages {
+            pages.push(Image::new(ctx, dir.join(&page.image))?);
+        }
+
+        let mut regions = HashMap::with_capacity(layout.sprites.len());
+        for sprite in layout.sprites {
+            let (page_width, page_height) = match pages.get(sprite.page) {
+                Some(page) => (page.width(), page.height()),
+                None => {
+                    let msg = format!(
+                        "Sprite {:?} in atlas {:?} is on page {}, which does not exist",
+                        sprite.name, path, sprite.page
+                    );
+                    return Err(GameError::ResourceLoadError(msg));
+                }
+            };
+            let region = sprite.region(page_width, page_height);
+            let _ = regions.insert(sprite.name, region);
+        }
+        Ok(Self { pages, regions })
+    }
+
+    /// Returns the region of the sprite with the given name, if any.
+    pub fn get(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    /// Returns the page image with the given index, if any.
+    pub fn page(&self, index: usize) -> Option<&Image> {
+        self.pages.get(index)
+    }
+
+    /// Returns all the page images of the atlas.
+    pub fn pages(&self) -> &[Image] {
+        &self.pages
+    }
+
+    /// Returns an iterator over the names of all the sprites in the atlas.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(String::as_str)
+    }
+}
+
+/// An image waiting to be packed by an `AtlasBuilder`.
+#[derive(Debug, Clone)]
+struct PendingSprite {
+    name: String,
+    width: u16,
+    height: u16,
+    rgba: Vec<u8>,
+}
+
+/// Packs images into an `Atlas`.
+///
+/// Sprites are sorted by height and laid out in rows ("shelves"),
+/// starting a new page whenever the current one is full.
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    page_width: u16,
+    page_height: u16,
+    padding: u16,
+    sprites: Vec<PendingSprite>,
+}
+
+impl AtlasBuilder {
+    /// Creates a new `AtlasBuilder` producing pages of the given size.
+    pub fn new(page_width: u16, page_height: u16) -> Self {
+        Self {
+            page_width,
+            page_height,
+            padding: 1,
+            sprites: vec![],
+        }
+    }
+
+    /// Sets the number of empty pixels left between sprites,
+    /// which keeps filtering from bleeding neighbouring sprites
+    /// into each other.  Defaults to 1.
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Adds a sprite from a buffer of `u8` RGBA values, laid out
+    /// like in `Image::from_rgba8()`.
+    pub fn add_rgba8(&mut self, name: &str, width: u16, height: u16, rgba: &[u8]) -> GameResult {
+        if rgba.len() != width as usize * height as usize * 4 {
+            let msg = format!(
+                "Sprite {:?} is {}x{} but has {} bytes of RGBA data",
+                name,
+                width,
+                height,
+                rgba.len()
+            );
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        if width > self.page_width || height > self.page_height {
+            let msg = format!(
+                "Sprite {:?} is {}x{}, which does not fit on a {}x{} atlas page",
+                name, width, height, self.page_width, self.page_height
+            );
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        if self.sprites.iter().any(|sprite| sprite.name == name) {
+            let msg = format!("Atlas already has a sprite named {:?}", name);
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        self.sprites.push(PendingSprite {
+            name: name.to_owned(),
+            width,
+            height,
+            rgba: rgba.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Adds a sprite from the image file at the given path.
+    pub fn add_image<P: AsRef<path::Path>>(
+        &mut self,
+        ctx: &mut Context,
+        name: &str,
+        path: P,
+    ) -> GameResult {
+        let img = {
+            let mut buf = Vec::new();
+            let mut reader = filesystem::open(ctx, path)?;
+            let _ = reader.read_to_end(&mut buf)?;
+            image::load_from_memory(&buf)?.to_rgba()
+        };
+        let (width, height) = img.dimensions();
+        if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+            let msg = format!(
+                "Sprite {:?} is {}x{}, which is too big for an atlas",
+                name, width, height
+            );
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        self.add_rgba8(name, width as u16, height as u16, &img)
+    }
+
+    /// Packs the sprites added so far and uploads the pages
+    /// to the graphics card.
+    pub fn build(&self, ctx: &mut Context) -> GameResult<Atlas> {
+        let (pixels, layout) = self.pack();
+        let mut pages = Vec::with_capacity(pixels.len());
+        for page in &pixels {
+            pages.push(Image::from_rgba8(
+                ctx,
+                self.page_width,
+                self.page_height,
+                page,
+            )?);
+        }
+        let regions = layout
+            .into_iter()
+            .map(|sprite| {
+                let region = sprite.region(self.page_width, self.page_height);
+                (sprite.name, region)
+            })
+            .collect();
+        Ok(Atlas { pages, regions })
+    }
+
+    /// Packs the sprites added so far and writes the atlas out as a
+    /// TOML sidecar file at the given path, with PNG pages next to it
+    /// named after the sidecar, for instance `sprites-0.png` for
+    /// `sprites.toml`.
+    ///
+    /// See the `filesystem` module docs for where exactly
+    /// the files will end up.
+    pub fn save<P: AsRef<path::Path>>(&self, ctx: &mut Context, path: P) -> GameResult {
+        let path = path.as_ref();
+        let stem = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("atlas");
+        let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+        let (pixels, sprites) = self.pack();
+
+        let mut pages = Vec::with_capacity(pixels.len());
+        for (i, page) in pixels.iter().enumerate() {
+            let image = format!("{}-{}.png", stem, i);
+            let f = filesystem::create(ctx, dir.join(&image))?;
+            let writer = &mut io::BufWriter::new(f);
+            image::png::PNGEncoder::new(writer).encode(
+                page,
+                u32::from(self.page_width),
+                u32::from(self.page_height),
+                image::ColorType::RGBA(8),
+            )?;
+            pages.push(PageFile {
+                image,
+                width: self.page_width,
+                height: self.page_height,
+            });
+        }
+
+        let s = toml::to_vec(&AtlasFile { pages, sprites })?;
+        let mut file = filesystem::create(ctx, path)?;
+        file.write_all(&s)?;
+        Ok(())
+    }
+
+    /// Lays the sprites out on as many pages as they need, returning
+    /// the RGBA pixels of each page and where each sprite went.
+    fn pack(&self) -> (Vec<Vec<u8>>, Vec<SpriteFile>) {
+        let page_width = u32::from(self.page_width);
+        let page_height = u32::from(self.page_height);
+        let padding = u32::from(self.padding);
+
+        let mut order: Vec<&PendingSprite> = self.sprites.iter().collect();
+        order.sort_by_key(|sprite| cmp::Reverse((sprite.height, sprite.width)));
+
+        let mut pages: Vec<Vec<u8>> = vec![];
+        let mut layout = Vec::with_capacity(order.len());
+        // The shelf cursor of the last page: x, y and the shelf's height.
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        for sprite in order {
+            let (w, h) = (u32::from(sprite.width), u32::from(sprite.height));
+            if x + w > page_width {
+                x = 0;
+                y += shelf + padding;
+                shelf = 0;
+            }
+            if pages.is_empty() || y + h > page_height {
+                pages.push(vec![0; page_width as usize * page_height as usize * 4]);
+                x = 0;
+                y = 0;
+                shelf = 0;
+            }
+            let page = pages.last_mut().expect("Atlas has no pages; should never happen");
+            let row_len = w as usize * 4;
+            for row in 0..h as usize {
+                let start = ((y as usize + row) * page_width as usize + x as usize) * 4;
+                page[start..start + row_len]
+                    .copy_from_slice(&sprite.rgba[row * row_len..(row + 1) * row_len]);
+            }
+            layout.push(SpriteFile {
+                name: sprite.name.clone(),
+                page: pages.len() - 1,
+                x: x as u16,
+                y: y as u16,
+                w: sprite.width,
+                h: sprite.height,
+            });
+            x += w + padding;
+            shelf = shelf.max(h);
+        }
+        (pages, layout)
+    }
+}
+
+/// The TOML sidecar format of an atlas.
+#[derive(Debug, Serialize, Deserialize)]
+struct AtlasFile {
+    pages: Vec<PageFile>,
+    sprites: Vec<SpriteFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PageFile {
+    image: String,
+    width: u16,
+    height: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SpriteFile {
+    name: String,
+    page: usize,
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+}
+
+impl SpriteFile {
+    fn region(&self, page_width: u16, page_height: u16) -> AtlasRegion {
+        let (page_width, page_height) = (f32::from(page_width), f32::from(page_height));
+        AtlasRegion {
+            page: self.page,
+            src: Rect::new(
+                f32::from(self.x) / page_width,
+                f32::from(self.y) / page_height,
+                f32::from(self.w) / page_width,
+                f32::from(self.h) / page_height,
+            ),
+            width: self.w,
+            height: self.h,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(builder: &mut AtlasBuilder, name: &str, w: u16, h: u16, value: u8) {
+        let rgba = vec![value; w as usize * h as usize * 4];
+        builder.add_rgba8(name, w, h, &rgba).unwrap();
+    }
+
+    #[test]
+    fn packing() {
+        let mut builder = AtlasBuilder::new(8, 8).padding(0);
+        solid(&mut builder, "big", 8, 4, 1);
+        solid(&mut builder, "a", 4, 4, 2);
+        solid(&mut builder, "b", 4, 4, 3);
+        solid(&mut builder, "c", 2, 2, 4);
+        assert!(builder.add_rgba8("huge", 9, 1, &[0; 36]).is_err());
+        assert!(builder.add_rgba8("a", 1, 1, &[0; 4]).is_err());
+        assert!(builder.add_rgba8("short", 2, 2, &[0; 4]).is_err());
+
+        let (pages, layout) = builder.pack();
+        assert_eq!(pages.len(), 2);
+        let find = |name: &str| layout.iter().find(|s| s.name == name).unwrap();
+        assert_eq!((find("big").page, find("big").x, find("big").y), (0, 0, 0));
+        assert_eq!((find("a").page, find("a").x, find("a").y), (0, 0, 4));
+        assert_eq!((find("b").page, find("b").x, find("b").y), (0, 4, 4));
+        assert_eq!((find("c").page, find("c").x, find("c").y), (1, 0, 0));
+
+        // Bottom-right pixel of "b" and top-left pixel of "a"
+        assert_eq!(pages[0][(7 * 8 + 7) * 4], 3);
+        assert_eq!(pages[0][(4 * 8) * 4], 2);
+
+        let region = find("b").region(8, 8);
+        assert_eq!(region.src, Rect::new(0.5, 0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn sidecar_round_trip() {
+        let mut builder = AtlasBuilder::new(16, 16);
+        solid(&mut builder, "a", 4, 3, 255);
+        solid(&mut builder, "b", 2, 5, 255);
+        let (_, sprites) = builder.pack();
+        let file = AtlasFile {
+            pages: vec![PageFile {
+                image: "atlas-0.png".to_owned(),
+                width: 16,
+                height: 16,
+            }],
+            sprites,
+        };
+        let s = toml::to_string(&file).unwrap();
+        let read: AtlasFile = toml::from_str(&s).unwrap();
+        assert_eq!(read.pages[0].image, "atlas-0.png");
+        let b = read.sprites.iter().find(|s| s.name == "b").unwrap();
+        assert_eq!((b.x, b.y, b.w, b.h), (0, 0, 2, 5));
+        let a = read.sprites.iter().find(|s| s.name == "a").unwrap();
+        assert_eq!((a.x, a.y, a.w, a.h), (3, 0, 4, 3));
+    }
+}
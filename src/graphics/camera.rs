@@ -0,0 +1,353 @@
+use std::f32;
+use std::time::Duration;
+
+use graphics::*;
+use input::mouse;
+use timer;
+
+type Vec3 = na::Vector3<f32>;
+
+/// A 2D camera looking at a point of the game world.
+///
+/// The camera shows the world around its `position` in its viewport,
+/// a rectangle of the screen in screen coordinates (see
+/// `set_screen_coordinates()`), scaled by its zoom and rotated by its
+/// rotation.  Drawing between `push()` and `pop()` goes through the
+/// camera:
+///
+/// ```rust,ignore
+/// camera.push(ctx)?;
+/// graphics::draw(ctx, &world, DrawParam::new())?;
+/// camera.pop(ctx)?;
+/// // HUD drawn in screen coordinates
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera2D {
+    position: Point2,
+    zoom: f32,
+    rotation: f32,
+    viewport: Rect,
+    bounds: Option<Rect>,
+    shake_intensity: f32,
+    shake_duration: Duration,
+    shake_remaining: Duration,
+    shake_time: f32,
+}
+
+impl Camera2D {
+    /// Creates a new camera showing its viewport unchanged: it looks at
+    /// the middle of the viewport with a zoom of 1 and no rotation.
+    pub fn new(viewport: Rect) -> Self {
+        Self {
+            position: Point2::new(viewport.x + viewport.w / 2.0, viewport.y + viewport.h / 2.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport,
+            bounds: None,
+            shake_intensity: 0.0,
+            shake_duration: Duration::new(0, 0),
+            shake_remaining: Duration::new(0, 0),
+            shake_time: 0.0,
+        }
+    }
+
+    /// Creates a new camera covering the whole screen, as set by
+    /// `set_screen_coordinates()`.
+    pub fn for_screen(ctx: &Context) -> Self {
+        Self::new(get_screen_coordinates(ctx))
+    }
+
+    /// Gets the point of the world shown in the middle of the viewport.
+    pub fn position(&self) -> mint::Point2<f32> {
+        self.position.into()
+    }
+
+    /// Sets the point of the world shown in the middle of the viewport,
+    /// keeping the view within the camera's bounds if it has any.
+    pub fn set_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.position = Point2::from(position.into());
+        self.clamp_to_bounds();
+    }
+
+    /// Gets the zoom factor.  Above 1.0 things look bigger.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the zoom factor.  Above 1.0 things look bigger.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+        self.clamp_to_bounds();
+    }
+
+    /// Gets the rotation of the view, in radians.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Sets the rotation of the view, in radians.  Rotating the camera
+    /// clockwise makes the world appear to turn counter-clockwise.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// Gets the part of the screen the camera's view is shown in.
+    pub fn viewport(&self) -> Rect {
+        self.viewport
+    }
+
+    /// Sets the part of the screen the camera's view is shown in,
+    /// in screen coordinates.
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+        self.clamp_to_bounds();
+    }
+
+    /// Gets the area of the world the view is kept in, if any.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// Sets an area of the world, such as the extent of a level, that the
+    /// view is kept in.  If the view is larger than the bounds it is
+    /// centered on them instead.  Rotation is not taken into account.
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+        self.clamp_to_bounds();
+    }
+
+    /// Moves the camera towards the given point, covering more of the
+    /// distance the higher `speed` is: after one second it has covered
+    /// all but `e^-speed` of it.  Call this once per update with the
+    /// frame time, for instance from `timer::get_delta()`.
+    pub fn follow<P>(&mut self, target: P, speed: f32, dt: Duration)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let target = Point2::from(target.into());
+        let t = 1.0 - (-speed * timer::duration_to_f64(dt) as f32).exp();
+        self.position += (target - self.position) * t;
+        self.clamp_to_bounds();
+    }
+
+    /// Shakes the view by up to `intensity` world units, fading out
+    /// over the given duration.  Shaking is advanced by `update()`.
+    pub fn shake(&mut self, intensity: f32, duration: Duration) {
+        self.shake_intensity = intensity;
+        self.shake_duration = duration;
+        self.shake_remaining = duration;
+    }
+
+    /// Advances the screen shake by the time since the last update,
+    /// as reported by `timer::get_delta()`.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::get_delta(ctx));
+    }
+
+    /// Advances the screen shake by the given amount of time.
+    pub fn advance(&mut self, dt: Duration) {
+        self.shake_time += timer::duration_to_f64(dt) as f32;
+        self.shake_remaining = if self.shake_remaining > dt {
+            self.shake_remaining - dt
+        } else {
+            Duration::new(0, 0)
+        };
+    }
+
+    /// The current offset of the view caused by shaking.
+    fn shake_offset(&self) -> Vector2 {
+        if self.shake_remaining == Duration::new(0, 0) {
+            return Vector2::new(0.0, 0.0);
+        }
+        let fade = (timer::duration_to_f64(self.shake_remaining)
+            / timer::duration_to_f64(self.shake_duration)) as f32;
+        // A couple of sines at unrelated frequencies look random enough.
+        let t = self.shake_time;
+        let x = (t * 71.0).sin() * 0.6 + (t * 113.0 + 1.7).sin() * 0.4;
+        let y = (t * 83.0 + 0.5).sin() * 0.6 + (t * 127.0 + 2.9).sin() * 0.4;
+        Vector2::new(x, y) * (self.shake_intensity * fade)
+    }
+
+    /// Returns the transform from world to screen coordinates.
+    pub fn matrix(&self) -> Matrix4 {
+        let center = self.viewport_center();
+        let eye = self.position + self.shake_offset();
+        Matrix4::new_translation(&Vec3::new(center.x, center.y, 0.0))
+            * Matrix4::new_rotation(Vec3::z() * -self.rotation)
+            * Matrix4::new_scaling(self.zoom)
+            * Matrix4::new_translation(&Vec3::new(-eye.x, -eye.y, 0.0))
+    }
+
+    /// Converts a point in screen coordinates to the point of the
+    /// world the camera shows there.
+    ///
+    /// Screen coordinates are the ones set by `set_screen_coordinates()`,
+    /// which are the same as window pixels, and thus the positions
+    /// returned by `mouse::get_position()`, unless you changed them.
+    /// See `mouse_position()` for that case.
+    pub fn screen_to_world<P>(&self, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let inverse = self.matrix()
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        transform_point(&inverse, Point2::from(point.into())).into()
+    }
+
+    /// Converts a point of the world to where the camera shows it
+    /// on the screen, in screen coordinates.
+    pub fn world_to_screen<P>(&self, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        transform_point(&self.matrix(), Point2::from(point.into())).into()
+    }
+
+    /// Returns the point of the world under the mouse cursor.
+    pub fn mouse_position(&self, ctx: &Context) -> mint::Point2<f32> {
+        let mouse = mouse::get_position(ctx);
+        let screen = get_screen_coordinates(ctx);
+        let (width, height) = get_drawable_size(ctx);
+        let point = Point2::new(
+            screen.x + mouse.x / width as f32 * screen.w,
+            screen.y + mouse.y / height as f32 * screen.h,
+        );
+        self.screen_to_world(point)
+    }
+
+    /// Returns the smallest rectangle of the world containing everything
+    /// the camera shows, such as for `tilemap::Map::set_view()`.
+    pub fn visible_rect(&self) -> Rect {
+        let v = self.viewport;
+        let corners = [
+            self.screen_to_world(Point2::new(v.x, v.y)),
+            self.screen_to_world(Point2::new(v.x + v.w, v.y)),
+            self.screen_to_world(Point2::new(v.x, v.y + v.h)),
+            self.screen_to_world(Point2::new(v.x + v.w, v.y + v.h)),
+        ];
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        for corner in &corners {
+            min_x = min_x.min(corner.x);
+            min_y = min_y.min(corner.y);
+            max_x = max_x.max(corner.x);
+            max_y = max_y.max(corner.y);
+        }
+        Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Pushes the camera's transform onto the transform stack and
+    /// applies it, so that everything drawn until `pop()` is drawn
+    /// through the camera.
+    pub fn push(&self, ctx: &mut Context) -> GameResult {
+        let current = get_transform(ctx);
+        push_transform(ctx, Some(self.matrix() * current));
+        apply_transformations(ctx)
+    }
+
+    /// Pops the transform pushed by `push()` and applies the one below.
+    pub fn pop(&self, ctx: &mut Context) -> GameResult {
+        pop_transform(ctx);
+        apply_transformations(ctx)
+    }
+
+    fn viewport_center(&self) -> Point2 {
+        Point2::new(
+            self.viewport.x + self.viewport.w / 2.0,
+            self.viewport.y + self.viewport.h / 2.0,
+        )
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let half_w = (self.viewport.w / self.zoom / 2.0).abs();
+        let half_h = (self.viewport.h / self.zoom / 2.0).abs();
+        self.position.x = clamp_axis(self.position.x, bounds.x, bounds.w, half_w);
+        self.position.y = clamp_axis(self.position.y, bounds.y, bounds.h, half_h);
+    }
+}
+
+fn transform_point(matrix: &Matrix4, point: Point2) -> Point2 {
+    let transformed = matrix * na::Vector4::new(point.x, point.y, 0.0, 1.0);
+    Point2::new(transformed.x, transformed.y)
+}
+
+/// Clamps a coordinate so that `half` either side of it stays within
+/// `start..start + len`, or centers it if that is impossible.
+fn clamp_axis(value: f32, start: f32, len: f32, half: f32) -> f32 {
+    if len <= half * 2.0 {
+        start + len / 2.0
+    } else if value - half < start {
+        start + half
+    } else if value + half > start + len {
+        start + len - half
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: mint::Point2<f32>, x: f32, y: f32) {
+        assert!(
+            (a.x - x).abs() < 1e-3 && (a.y - y).abs() < 1e-3,
+            "expected ({}, {}), got ({}, {})",
+            x,
+            y,
+            a.x,
+            a.y
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        let mut camera = Camera2D::new(Rect::new(0.0, 0.0, 800.0, 600.0));
+        assert_near(camera.world_to_screen(Point2::new(10.0, 20.0)), 10.0, 20.0);
+
+        camera.set_position(Point2::new(100.0, 100.0));
+        camera.set_zoom(2.0);
+        assert_near(camera.world_to_screen(Point2::new(100.0, 100.0)), 400.0, 300.0);
+        assert_near(camera.world_to_screen(Point2::new(110.0, 100.0)), 420.0, 300.0);
+        assert_near(camera.screen_to_world(Point2::new(420.0, 300.0)), 110.0, 100.0);
+
+        camera.set_rotation(f32::consts::FRAC_PI_2);
+        let screen = camera.world_to_screen(Point2::new(110.0, 100.0));
+        assert_near(camera.screen_to_world(screen), 110.0, 100.0);
+
+        let visible = camera.visible_rect();
+        assert!((visible.w - 300.0).abs() < 1e-3 && (visible.h - 400.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bounds_and_follow() {
+        let mut camera = Camera2D::new(Rect::new(0.0, 0.0, 100.0, 100.0));
+        camera.set_bounds(Some(Rect::new(0.0, 0.0, 1000.0, 80.0)));
+        camera.set_position(Point2::new(-50.0, 500.0));
+        assert_near(camera.position(), 50.0, 40.0);
+
+        camera.follow(Point2::new(250.0, 40.0), 1.0, Duration::from_secs(1));
+        let moved = camera.position().x;
+        let expected = 250.0 - 200.0 * (-1.0f32).exp();
+        assert!((moved - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn shake_fades_out() {
+        let mut camera = Camera2D::new(Rect::new(0.0, 0.0, 100.0, 100.0));
+        camera.shake(5.0, Duration::from_millis(100));
+        camera.advance(Duration::from_millis(10));
+        let offset = camera.shake_offset();
+        assert!(offset.norm() > 0.0 && offset.norm() <= 5.0);
+        camera.advance(Duration::from_millis(100));
+        assert_eq!(camera.shake_offset(), Vector2::new(0.0, 0.0));
+    }
+}
@@ -2,8 +2,8 @@
 //! module from graphics...
 
 use gfx::format::Swizzle;
-use gfx::handle::RawRenderTargetView;
-use gfx::memory::{Bind, Usage};
+use gfx::handle::{RawDepthStencilView, RawRenderTargetView};
+use gfx::memory::{Bind, Typed, Usage};
 use gfx::texture::{AaMode, Kind};
 use gfx::Factory;
 
@@ -21,6 +21,7 @@ where
     Spec: BackendSpec,
 {
     target: RawRenderTargetView<Spec::Resources>,
+    depth: RawDepthStencilView<Spec::Resources>,
     image: Image,
     debug_id: DebugId,
 }
@@ -55,6 +56,7 @@ impl Canvas {
         let kind = Kind::D2(width, height, aa);
         let levels = 1;
         let color_format = ctx.gfx_context.color_format();
+        let depth_format = ctx.gfx_context.depth_format();
         let factory = &mut ctx.gfx_context.factory;
         let texture_create_info = gfx::texture::Info {
             kind: kind,
@@ -78,8 +80,24 @@ impl Canvas {
             layer: None,
         };
         let target = factory.view_texture_as_render_target_raw(&tex, render_desc)?;
+        // A matching depth-stencil buffer, for stencil masking.
+        let depth_create_info = gfx::texture::Info {
+            kind,
+            levels,
+            format: depth_format.0,
+            bind: Bind::DEPTH_STENCIL,
+            usage: Usage::Data,
+        };
+        let depth_tex = factory.create_texture_raw(depth_create_info, Some(depth_format.1), None)?;
+        let depth_desc = gfx::texture::DepthStencilDesc {
+            level: 0,
+            layer: None,
+            flags: gfx::texture::DepthStencilFlags::empty(),
+        };
+        let depth = factory.view_texture_as_depth_stencil_raw(&depth_tex, depth_desc)?;
         Ok(Canvas {
             target,
+            depth,
             image: Image {
                 texture: resource,
                 texture_handle: tex,
@@ -107,6 +125,26 @@ impl Canvas {
         &self.image
     }
 
+    /// Get the filter mode for the canvas's image.
+    pub fn get_filter(&self) -> FilterMode {
+        self.image.get_filter()
+    }
+
+    /// Set the filter mode for the canvas's image.
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        self.image.set_filter(mode)
+    }
+
+    /// Gets the canvas's image's `WrapMode` along the X and Y axes.
+    pub fn get_wrap(&self) -> (WrapMode, WrapMode) {
+        self.image.get_wrap()
+    }
+
+    /// Sets the canvas's image's `WrapMode` along the X and Y axes.
+    pub fn set_wrap(&mut self, wrap_x: WrapMode, wrap_y: WrapMode) {
+        self.image.set_wrap(wrap_x, wrap_y)
+    }
+
     /// Destroys the Canvas and returns the `Image` it contains.
     pub fn into_inner(self) -> Image {
         // This texture is created with different settings
@@ -142,14 +180,20 @@ impl Drawable for Canvas {
 
 /// Set the canvas to render to. Specifying `Option::None` will cause all
 /// rendering to be done directly to the screen.
+///
+/// Each canvas has its own stencil buffer, which is used instead of the
+/// screen's while it is set.
 pub fn set_canvas(ctx: &mut Context, target: Option<&Canvas>) {
     match target {
         Some(surface) => {
             surface.debug_id.assert(ctx);
             ctx.gfx_context.data.out = surface.target.clone();
+            ctx.gfx_context.data.stencil.0 = Typed::new(surface.depth.clone());
         }
         None => {
             ctx.gfx_context.data.out = ctx.gfx_context.screen_render_target.clone();
+            ctx.gfx_context.data.stencil.0 = Typed::new(ctx.gfx_context.depth_view.clone());
         }
     };
+    ctx.gfx_context.update_scissor();
 }
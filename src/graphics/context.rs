@@ -1,6 +1,9 @@
 use std::cell::RefCell;
+use std::mem;
 use std::rc::Rc;
+use std::u16;
 
+use gfx::memory::Typed;
 use gfx::traits::FactoryExt;
 use gfx::Factory;
 use gfx_glyph::{GlyphBrush, GlyphBrushBuilder};
@@ -13,6 +16,14 @@ use graphics::*;
 
 use GameResult;
 
+/// The number of vertices and indices the buffers for shapes drawn by
+/// `queue_shapes()` start out with.
+const SHAPE_BUFFER_SIZE: usize = 1024;
+
+/// The most vertices a batch of shapes can have, since its indices are
+/// 16 bits.
+const SHAPE_BATCH_LIMIT: usize = u16::MAX as usize + 1;
+
 /// A structure that contains graphics state.
 /// For instance,
 /// window info, DPI, rendering pipeline state, etc.
@@ -42,7 +53,6 @@ where
     pub(crate) factory: Box<B::Factory>,
     pub(crate) encoder: gfx::Encoder<B::Resources, B::CommandBuffer>,
     pub(crate) screen_render_target: gfx::handle::RawRenderTargetView<B::Resources>,
-    #[allow(dead_code)]
     pub(crate) depth_view: gfx::handle::RawDepthStencilView<B::Resources>,
 
     pub(crate) data: pipe::Data<B::Resources>,
@@ -55,8 +65,61 @@ where
     default_shader: ShaderId,
     pub(crate) current_shader: Rc<RefCell<Option<ShaderId>>>,
     pub(crate) shaders: Vec<Box<dyn ShaderHandle<B>>>,
+    pub(crate) shader_watches: Vec<ShaderWatch>,
+    pub(crate) hot_reload_shaders: bool,
 
     pub(crate) glyph_brush: GlyphBrush<'static, B::Resources, B::Factory>,
+
+    batch_key: Option<BatchKey<B>>,
+    batch: Vec<InstanceProperties>,
+    shape_batch: MeshBuilder,
+    /// Where each call to `queue_shapes()` builds its shapes before they
+    /// are added to `shape_batch`.
+    shape_scratch: MeshBuilder,
+    shape_vertex_buffer: gfx::handle::Buffer<B::Resources, Vertex>,
+    shape_index_buffer: gfx::handle::Buffer<B::Resources, u16>,
+
+    pub(crate) scissor: Option<Rect>,
+    pub(crate) scissor_stack: Vec<Option<Rect>>,
+    pub(crate) stencil_mode: StencilMode,
+
+    pub(crate) debug_queue: debug::DebugQueue,
+}
+
+/// The state that all quads or shapes in an automatic batch have in
+/// common.  One that differs in any of these starts a new batch.
+struct BatchKey<B>
+where
+    B: BackendSpec,
+{
+    /// Whether the batch holds shapes from `queue_shapes()` rather
+    /// than quads.
+    shapes: bool,
+    texture: gfx::handle::RawShaderResourceView<B::Resources>,
+    sampler_info: texture::SamplerInfo,
+    shader: ShaderId,
+    blend_mode: BlendMode,
+    target: gfx::handle::RawRenderTargetView<B::Resources>,
+    depth: gfx::handle::DepthStencilView<B::Resources, gfx::format::DepthStencil>,
+    scissor: gfx::Rect,
+    stencil: StencilMode,
+}
+
+impl<B> PartialEq for BatchKey<B>
+where
+    B: BackendSpec,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.shapes == other.shapes
+            && self.texture == other.texture
+            && self.sampler_info == other.sampler_info
+            && self.shader == other.shader
+            && self.blend_mode == other.blend_mode
+            && self.target == other.target
+            && self.depth == other.depth
+            && self.scissor == other.scissor
+            && self.stencil == other.stencil
+    }
 }
 
 impl<B> fmt::Debug for GraphicsContextGeneric<B>
@@ -221,6 +284,11 @@ where
 
         quad_slice.instances = Some((1, 0));
 
+        let shape_vertex_buffer =
+            create_dynamic_buffer(&mut factory, gfx::buffer::Role::Vertex, SHAPE_BUFFER_SIZE)?;
+        let shape_index_buffer =
+            create_dynamic_buffer(&mut factory, gfx::buffer::Role::Index, SHAPE_BUFFER_SIZE)?;
+
         let globals_buffer = factory.create_constant_buffer(1);
         let mut samplers: SamplerCache<B> = SamplerCache::new();
         let sampler_info =
@@ -243,6 +311,8 @@ where
             tex: (typed_thingy, sampler),
             rect_instance_properties: rect_inst_props,
             globals: globals_buffer,
+            scissor: full_target_rect(&screen_render_target),
+            stencil: (Typed::new(depth_view.clone()), (0, 0)),
             out: screen_render_target.clone(),
         };
 
@@ -288,8 +358,23 @@ where
             default_shader: shader.shader_id(),
             current_shader: Rc::new(RefCell::new(None)),
             shaders: vec![draw],
+            shader_watches: Vec::new(),
+            hot_reload_shaders: false,
 
             glyph_brush,
+
+            batch_key: None,
+            batch: Vec::new(),
+            shape_batch: MeshBuilder::new(),
+            shape_scratch: MeshBuilder::new(),
+            shape_vertex_buffer,
+            shape_index_buffer,
+
+            scissor: None,
+            scissor_stack: Vec::new(),
+            stencil_mode: StencilMode::Off,
+
+            debug_queue: debug::DebugQueue::new(),
         };
         gfx.set_window_mode(window_mode)?;
 
@@ -311,6 +396,7 @@ where
     /// Sends the current value of the graphics context's shader globals
     /// to the graphics card.
     pub(crate) fn update_globals(&mut self) -> GameResult {
+        self.flush_batch()?;
         self.encoder
             .update_buffer(&self.data.globals, &[self.shader_globals], 0)?;
         Ok(())
@@ -368,6 +454,7 @@ where
     /// Converts the given `DrawParam` into an `InstanceProperties` object and
     /// sends it to the graphics card at the front of the instance buffer.
     pub(crate) fn update_instance_properties(&mut self, draw_params: DrawTransform) -> GameResult {
+        self.flush_batch()?;
         // This clone is cheap since draw_params is Copy
         // TODO: Clean up
         let mut new_draw_params = draw_params;
@@ -378,27 +465,205 @@ where
         Ok(())
     }
 
+    /// Queues a textured quad to be drawn with the current shader,
+    /// render target and either the given or the current blend mode.
+    ///
+    /// Consecutive quads that share all of that state are drawn together
+    /// with a single instanced draw call the next time the batch is
+    /// flushed; see `flush_batch()`.
+    pub(crate) fn queue_quad(
+        &mut self,
+        texture: gfx::handle::RawShaderResourceView<B::Resources>,
+        sampler_info: texture::SamplerInfo,
+        blend_mode: Option<BlendMode>,
+        draw_params: DrawTransform,
+    ) -> GameResult {
+        let key = self.batch_key(false, texture, sampler_info, blend_mode);
+        if self.batch_key.as_ref() != Some(&key) {
+            self.flush_batch()?;
+            self.batch_key = Some(key);
+        }
+        let properties = draw_params.to_instance_properties(self.srgb);
+        self.batch.push(properties);
+        Ok(())
+    }
+
+    /// Queues untextured shapes added to a `MeshBuilder` by `build` to
+    /// be drawn in the given color with the current transform, shader,
+    /// blend mode and render target.
+    ///
+    /// Like quads, consecutive shapes that share all of that state are
+    /// drawn with a single draw call out of buffers that are reused from
+    /// frame to frame.
+    ///
+    /// Everything `build` adds has to fit in one batch, so callers
+    /// adding lots of shapes should add them one call at a time.
+    pub(crate) fn queue_shapes<F>(&mut self, color: Color, build: F) -> GameResult
+    where
+        F: FnOnce(&mut MeshBuilder),
+    {
+        // The shapes are built on their own first, so we know whether
+        // they fit in the batch before their indices are offset into it.
+        let _ = self.shape_scratch.clear();
+        build(self.shape_scratch.color(color));
+        if self.srgb {
+            // Like the vertices of a `Mesh`, since the batch is drawn
+            // with a plain white instance color.
+            let _ = self.shape_scratch.make_linear();
+        }
+        let added = self.shape_scratch.vertices().len();
+        if added > SHAPE_BATCH_LIMIT {
+            warn!(
+                "Shapes with {} vertices are too big to draw; the most is {}",
+                added, SHAPE_BATCH_LIMIT
+            );
+            return Ok(());
+        }
+
+        let texture = self.white_image.texture.clone();
+        let sampler_info = self.white_image.sampler_info;
+        let key = self.batch_key(true, texture, sampler_info, None);
+        let full = self.shape_batch.vertices().len() + added > SHAPE_BATCH_LIMIT;
+        if full || self.batch_key.as_ref() != Some(&key) {
+            self.flush_batch()?;
+            self.batch_key = Some(key);
+        }
+        let _ = self.shape_batch
+            .raw(self.shape_scratch.vertices(), self.shape_scratch.indices());
+        Ok(())
+    }
+
+    /// The batch key for drawing with the current state.
+    fn batch_key(
+        &self,
+        shapes: bool,
+        texture: gfx::handle::RawShaderResourceView<B::Resources>,
+        sampler_info: texture::SamplerInfo,
+        blend_mode: Option<BlendMode>,
+    ) -> BatchKey<B> {
+        BatchKey {
+            shapes,
+            texture,
+            sampler_info,
+            shader: self.current_shader_id(),
+            blend_mode: blend_mode.unwrap_or_else(|| self.get_blend_mode()),
+            target: self.data.out.clone(),
+            depth: self.data.stencil.0.clone(),
+            scissor: self.data.scissor,
+            stencil: self.stencil_mode,
+        }
+    }
+
+    /// Draws any quads or shapes queued by `queue_quad()` or
+    /// `queue_shapes()`.
+    ///
+    /// Everything that draws by other means, changes the shader globals
+    /// or otherwise depends on the order of commands in the encoder must
+    /// call this first.  The batch remembers its own shader, blend mode,
+    /// render target, scissor rectangle and stencil mode, so changing those
+    /// does not need to flush eagerly.
+    pub(crate) fn flush_batch(&mut self) -> GameResult {
+        let key = match self.batch_key.take() {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        if key.shapes && self.shape_batch.indices().is_empty() {
+            let _ = self.shape_batch.clear();
+            return Ok(());
+        }
+        let slice = if key.shapes {
+            // Shapes are drawn as a single instance without a
+            // transform or color of its own.
+            let properties = DrawTransform::from(DrawParam::new()).to_instance_properties(self.srgb);
+            self.encoder
+                .update_buffer(&self.data.rect_instance_properties, &[properties], 0)?;
+            update_dynamic_buffer(
+                &mut *self.factory,
+                &mut self.encoder,
+                &mut self.shape_vertex_buffer,
+                gfx::buffer::Role::Vertex,
+                self.shape_batch.vertices(),
+            )?;
+            update_dynamic_buffer(
+                &mut *self.factory,
+                &mut self.encoder,
+                &mut self.shape_index_buffer,
+                gfx::buffer::Role::Index,
+                self.shape_batch.indices(),
+            )?;
+            let slice = gfx::Slice {
+                start: 0,
+                end: self.shape_batch.indices().len() as u32,
+                base_vertex: 0,
+                instances: None,
+                buffer: gfx::IndexBuffer::Index16(self.shape_index_buffer.clone()),
+            };
+            let _ = self.shape_batch.clear();
+            self.data.vbuf = self.shape_vertex_buffer.clone();
+            slice
+        } else {
+            update_dynamic_buffer(
+                &mut *self.factory,
+                &mut self.encoder,
+                &mut self.data.rect_instance_properties,
+                gfx::buffer::Role::Vertex,
+                &self.batch,
+            )?;
+            let mut slice = self.quad_slice.clone();
+            slice.instances = Some((self.batch.len() as u32, 0));
+            self.batch.clear();
+            self.data.vbuf = self.quad_vertex_buffer.clone();
+            slice
+        };
+
+        let sampler = self.samplers
+            .get_or_insert(key.sampler_info, self.factory.as_mut());
+        let typed_thingy = self.backend_spec.raw_to_typed_shader_resource(key.texture);
+        self.data.tex = (typed_thingy, sampler);
+
+        let shader_handle = &mut self.shaders[key.shader];
+        let previous_mode = shader_handle.get_blend_mode();
+        shader_handle.set_blend_mode(key.blend_mode)?;
+        shader_handle.set_stencil_mode(&mut *self.factory, key.stencil)?;
+        let value = key.stencil.value();
+        let previous_target = mem::replace(&mut self.data.out, key.target);
+        let previous_stencil = mem::replace(&mut self.data.stencil, (key.depth, (value, value)));
+        let previous_scissor = mem::replace(&mut self.data.scissor, key.scissor);
+        let result = shader_handle.draw(&mut self.encoder, &slice, &self.data);
+        self.data.out = previous_target;
+        self.data.stencil = previous_stencil;
+        self.data.scissor = previous_scissor;
+        shader_handle.set_blend_mode(previous_mode)?;
+        result
+    }
+
+    /// Returns the id of the shader that draws are currently made with.
+    fn current_shader_id(&self) -> ShaderId {
+        (*self.current_shader.borrow()).unwrap_or(self.default_shader)
+    }
+
     /// Draws with the current encoder, slice, and pixel shader. Prefer calling
     /// this method from `Drawables` so that the pixel shader gets used
     pub(crate) fn draw(&mut self, slice: Option<&gfx::Slice<B::Resources>>) -> GameResult {
         let slice = slice.unwrap_or(&self.quad_slice);
-        let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
-        let shader_handle = &self.shaders[id];
+        let id = self.current_shader_id();
+        let shader_handle = &mut self.shaders[id];
 
+        shader_handle.set_stencil_mode(&mut *self.factory, self.stencil_mode)?;
         shader_handle.draw(&mut self.encoder, slice, &self.data)?;
         Ok(())
     }
 
     /// Sets the blend mode of the active shader
     pub(crate) fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
-        let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
+        let id = self.current_shader_id();
         let shader_handle = &mut self.shaders[id];
         shader_handle.set_blend_mode(mode)
     }
 
     /// Gets the current blend mode of the active shader
     pub(crate) fn get_blend_mode(&self) -> BlendMode {
-        let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
+        let id = self.current_shader_id();
         let shader_handle = &self.shaders[id];
         shader_handle.get_blend_mode()
     }
@@ -415,6 +680,34 @@ where
                 .append_nonuniform_scaling(&Vec3::new(1.0, -1.0, 1.0));
     }
 
+    /// Sets the scissor rectangle, in screen coordinates, that drawing
+    /// is restricted to.  `None` allows drawing to the whole target.
+    pub(crate) fn set_scissor(&mut self, rect: Option<Rect>) {
+        self.scissor = rect;
+        self.update_scissor();
+    }
+
+    /// Recalculates the scissor rectangle in render target pixels.
+    ///
+    /// Call this whenever the scissor rectangle, the screen coordinates
+    /// or the render target change.
+    pub(crate) fn update_scissor(&mut self) {
+        self.data.scissor = match self.scissor {
+            Some(rect) => {
+                let (width, height, _, _) = self.data.out.get_dimensions();
+                scissor_pixels(rect, self.screen_rect, width, height)
+            }
+            None => full_target_rect(&self.data.out),
+        };
+    }
+
+    /// Sets how drawing interacts with the stencil buffer.
+    pub(crate) fn set_stencil(&mut self, mode: StencilMode) {
+        let value = mode.value();
+        self.stencil_mode = mode;
+        self.data.stencil.1 = (value, value);
+    }
+
     /// Sets the raw projection matrix to the given Matrix.
     ///
     /// Call `update_globals()` to apply after calling this.
@@ -504,8 +797,15 @@ where
             self.depth_format(),
             &self.window,
         ) {
+            if self.data.out == self.screen_render_target {
+                self.data.out = cv.clone();
+            }
+            if *self.data.stencil.0.raw() == self.depth_view {
+                self.data.stencil.0 = Typed::new(dv.clone());
+            }
             self.screen_render_target = cv;
             self.depth_view = dv;
+            self.update_scissor();
         }
     }
 
@@ -553,3 +853,65 @@ where
         (physical.x as f32, physical.y as f32)
     }
 }
+
+/// Returns a scissor rectangle covering the whole render target.
+fn full_target_rect<R>(target: &gfx::handle::RawRenderTargetView<R>) -> gfx::Rect
+where
+    R: gfx::Resources,
+{
+    let (w, h, _, _) = target.get_dimensions();
+    gfx::Rect { x: 0, y: 0, w, h }
+}
+
+/// Converts a scissor rectangle in screen coordinates to pixels of a
+/// render target `width` by `height` pixels big, clipped to it.
+///
+/// The projection always maps the screen coordinates onto the whole
+/// render target, whatever its size, so this also takes care of the
+/// hidpi factor.  The result is in OpenGL's window coordinates, which
+/// start at the bottom-left, since gfx passes it straight on to
+/// `glScissor()`.  Canvases are drawn with the same projection as the
+/// window, so they are stored bottom-up too and need the same flip.
+pub(crate) fn scissor_pixels(rect: Rect, screen: Rect, width: u16, height: u16) -> gfx::Rect {
+    let to_pixels = |coord: f32, start: f32, size: f32, pixels: u16| {
+        let pixel = ((coord - start) / size * f32::from(pixels)).round();
+        pixel.max(0.0).min(f32::from(pixels)) as u16
+    };
+    let x1 = to_pixels(rect.left(), screen.x, screen.w, width);
+    let x2 = to_pixels(rect.right(), screen.x, screen.w, width);
+    let y1 = to_pixels(rect.top(), screen.y, screen.h, height);
+    let y2 = to_pixels(rect.bottom(), screen.y, screen.h, height);
+    let (left, right) = (x1.min(x2), x1.max(x2));
+    let (top, bottom) = (y1.min(y2), y1.max(y2));
+    gfx::Rect {
+        x: left,
+        y: height - bottom,
+        w: right - left,
+        h: bottom - top,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ContextBuilder;
+
+    #[test]
+    fn queued_shapes_are_linear() {
+        let (ctx, _) = &mut ContextBuilder::new("unittest", "unittest")
+            .build()
+            .unwrap();
+        let gfx = &mut ctx.gfx_context;
+        assert!(gfx.srgb);
+        let color = Color::new(0.5, 0.25, 1.0, 0.5);
+        gfx.queue_shapes(color, |mb| {
+            let _ = mb.line(&[Point2::new(0.0, 0.0), Point2::new(10.0, 0.0)], 1.0);
+        })
+        .unwrap();
+        let expected: [f32; 4] = LinearColor::from(color).into();
+        assert!(!gfx.shape_batch.vertices().is_empty());
+        for vertex in gfx.shape_batch.vertices() {
+            assert_eq!(vertex.color, expected);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Immediate-mode drawing of lines, arrows, shapes and labels for
+//! debugging, such as collision shapes or the paths an AI follows.
+//!
+//! The functions here can be called from anywhere you have a `Context`,
+//! including `update()`.  They only queue a `Gizmo`, and everything
+//! queued is drawn together at the end of the frame, with the shapes
+//! batched into a single draw call:
+//!
+//! ```rust,ignore
+//! // In update():
+//! graphics::debug::circle(ctx, enemy.pos, enemy.radius, graphics::RED);
+//! graphics::debug::arrow(ctx, enemy.pos, enemy.target, graphics::YELLOW);
+//! graphics::debug::queue(ctx, Gizmo::Cross(hit, 4.0), graphics::WHITE, 2.0);
+//!
+//! // In draw():
+//! camera.push(ctx)?;
+//! graphics::draw(ctx, &world, DrawParam::new())?;
+//! graphics::debug::draw(ctx)?;
+//! camera.pop(ctx)?;
+//! ```
+//!
+//! Gizmos are in world coordinates: they are drawn with the transform
+//! that is current when `draw()` is called, so calling it between a
+//! camera's `push()` and `pop()` puts them in the camera's view.  If
+//! `draw()` isn't called during a frame, `graphics::present()` draws
+//! them instead, with whatever transform is current then.
+//!
+//! Each gizmo is drawn for one frame, unless it's queued with a
+//! lifetime, in which case it's drawn every frame until that many
+//! seconds have passed.
+
+use std::mem;
+
+use context::Context;
+use graphics::{self, Color, DrawMode, DrawTransform, Point2, Rect, Text, Vector2};
+use mint;
+use GameResult;
+
+/// The flattening tolerance circles are drawn with.
+const TOLERANCE: f32 = 0.1;
+
+/// Something to draw for debugging.  All positions and sizes are in
+/// world coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gizmo {
+    /// A line between two points.
+    Line(mint::Point2<f32>, mint::Point2<f32>),
+    /// A line between two points with an arrow head at the second one.
+    Arrow(mint::Point2<f32>, mint::Point2<f32>),
+    /// The outline of a rectangle.
+    Rect(Rect),
+    /// The outline of a circle with the given center and radius.
+    Circle(mint::Point2<f32>, f32),
+    /// An X at the given point, reaching the given distance from it
+    /// along both axes.
+    Cross(mint::Point2<f32>, f32),
+    /// A text label with its top left corner at the given point.
+    Text(mint::Point2<f32>, String),
+}
+
+#[derive(Debug, Clone)]
+struct QueuedGizmo {
+    gizmo: Gizmo,
+    color: Color,
+    /// Seconds left to draw it for after the current frame.
+    remaining: f32,
+}
+
+/// The gizmos queued to be drawn, which the `GraphicsContext` holds on
+/// to between frames.
+#[derive(Debug)]
+pub(crate) struct DebugQueue {
+    gizmos: Vec<QueuedGizmo>,
+    line_width: f32,
+    /// Whether `draw()` was called this frame, so `present()` doesn't
+    /// need to.
+    drawn: bool,
+}
+
+impl DebugQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            gizmos: vec![],
+            line_width: 1.0,
+            drawn: false,
+        }
+    }
+
+    fn push(&mut self, gizmo: Gizmo, color: Color, lifetime: f32) {
+        self.gizmos.push(QueuedGizmo {
+            gizmo,
+            color,
+            remaining: lifetime,
+        });
+    }
+
+    /// Ages every gizmo by the length of the frame that just ended,
+    /// dropping the ones whose lifetime is over.
+    fn end_frame(&mut self, dt: f32) {
+        for queued in &mut self.gizmos {
+            queued.remaining -= dt;
+        }
+        self.gizmos.retain(|queued| queued.remaining > 0.0);
+        self.drawn = false;
+    }
+}
+
+/// Queues a gizmo to be drawn in the given color, for the given number
+/// of seconds.  A lifetime of 0 draws it for the current frame only.
+pub fn queue(ctx: &mut Context, gizmo: Gizmo, color: Color, lifetime: f32) {
+    ctx.gfx_context.debug_queue.push(gizmo, color, lifetime);
+}
+
+/// Queues a line between two points, for the current frame.
+pub fn line<P>(ctx: &mut Context, from: P, to: P, color: Color)
+where
+    P: Into<mint::Point2<f32>>,
+{
+    queue(ctx, Gizmo::Line(from.into(), to.into()), color, 0.0);
+}
+
+/// Queues an arrow pointing from one point to another, for the current
+/// frame.
+pub fn arrow<P>(ctx: &mut Context, from: P, to: P, color: Color)
+where
+    P: Into<mint::Point2<f32>>,
+{
+    queue(ctx, Gizmo::Arrow(from.into(), to.into()), color, 0.0);
+}
+
+/// Queues the outline of a rectangle, for the current frame.
+pub fn rect(ctx: &mut Context, rect: Rect, color: Color) {
+    queue(ctx, Gizmo::Rect(rect), color, 0.0);
+}
+
+/// Queues the outline of a circle, for the current frame.
+pub fn circle<P>(ctx: &mut Context, center: P, radius: f32, color: Color)
+where
+    P: Into<mint::Point2<f32>>,
+{
+    queue(ctx, Gizmo::Circle(center.into(), radius), color, 0.0);
+}
+
+/// Queues an X marking a point, for the current frame.
+pub fn cross<P>(ctx: &mut Context, center: P, size: f32, color: Color)
+where
+    P: Into<mint::Point2<f32>>,
+{
+    queue(ctx, Gizmo::Cross(center.into(), size), color, 0.0);
+}
+
+/// Queues a text label, for the current frame.
+pub fn text<P, S>(ctx: &mut Context, position: P, text: S, color: Color)
+where
+    P: Into<mint::Point2<f32>>,
+    S: Into<String>,
+{
+    queue(ctx, Gizmo::Text(position.into(), text.into()), color, 0.0);
+}
+
+/// Gets the width lines are drawn with, in world coordinates.
+pub fn get_line_width(ctx: &Context) -> f32 {
+    ctx.gfx_context.debug_queue.line_width
+}
+
+/// Sets the width lines are drawn with, in world coordinates.  The
+/// default is 1.0.
+pub fn set_line_width(ctx: &mut Context, width: f32) {
+    ctx.gfx_context.debug_queue.line_width = width;
+}
+
+/// Removes every queued gizmo, including ones whose lifetime isn't
+/// over yet.
+pub fn clear(ctx: &mut Context) {
+    ctx.gfx_context.debug_queue.gizmos.clear();
+}
+
+/// Draws every queued gizmo with the current transform.
+///
+/// Gizmos are only removed from the queue at the end of the frame, so
+/// this may be called more than once, for instance once for each
+/// camera in split screen.
+pub fn draw(ctx: &mut Context) -> GameResult {
+    ctx.gfx_context.debug_queue.drawn = true;
+    if ctx.gfx_context.debug_queue.gizmos.is_empty() {
+        return Ok(());
+    }
+    let gizmos = mem::replace(&mut ctx.gfx_context.debug_queue.gizmos, vec![]);
+    let result = draw_gizmos(ctx, &gizmos);
+    // Anything queued while drawing comes after what was there.
+    let queued = mem::replace(&mut ctx.gfx_context.debug_queue.gizmos, gizmos);
+    ctx.gfx_context.debug_queue.gizmos.extend(queued);
+    result
+}
+
+/// Draws the gizmos if `draw()` wasn't called this frame, then ages
+/// them.  Called by `graphics::present()`.
+pub(crate) fn end_frame(ctx: &mut Context, dt: f32) -> GameResult {
+    let result = if ctx.gfx_context.debug_queue.drawn {
+        Ok(())
+    } else {
+        draw(ctx)
+    };
+    ctx.gfx_context.debug_queue.end_frame(dt);
+    result
+}
+
+fn draw_gizmos(ctx: &mut Context, gizmos: &[QueuedGizmo]) -> GameResult {
+    let width = ctx.gfx_context.debug_queue.line_width;
+    let mode = DrawMode::Line(width);
+    let mut labels = false;
+    for queued in gizmos {
+        ctx.gfx_context.queue_shapes(queued.color, |mb| {
+            let _ = match queued.gizmo {
+                Gizmo::Line(from, to) => mb.line(&[from, to], width),
+                Gizmo::Arrow(from, to) => {
+                    let _ = mb.line(&[from, to], width);
+                    match arrow_head(from.into(), to.into(), width) {
+                        Some(head) => mb.line(&head, width),
+                        None => mb,
+                    }
+                }
+                Gizmo::Rect(rect) => mb.rectangle(mode, rect),
+                Gizmo::Circle(center, radius) => mb.circle(mode, center, radius, TOLERANCE),
+                Gizmo::Cross(center, size) => {
+                    let center = Point2::from(center);
+                    let (a, b) = (Vector2::new(size, size), Vector2::new(size, -size));
+                    let _ = mb.line(&[center - a, center + a], width);
+                    mb.line(&[center - b, center + b], width)
+                }
+                Gizmo::Text(..) => mb,
+            };
+        })?;
+        if let Gizmo::Text(position, ref text) = queued.gizmo {
+            graphics::queue_text(ctx, &Text::new(text.as_str()), position, Some(queued.color));
+            labels = true;
+        }
+    }
+    if labels {
+        let transform = graphics::get_transform(ctx);
+        graphics::draw_queued_text(
+            ctx,
+            DrawTransform {
+                matrix: transform,
+                ..Default::default()
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// The three points of the head of an arrow, or `None` if the arrow
+/// has no length and so no direction to point in.  The head is six line
+/// widths long, but no more than a third of the arrow.
+fn arrow_head(from: Point2, to: Point2, width: f32) -> Option<[Point2; 3]> {
+    let length = (to - from).norm();
+    if length <= 0.0 {
+        return None;
+    }
+    let direction = (to - from) / length;
+    let size = (length / 3.0).min(width * 6.0);
+    let back = -direction * size;
+    let side = Vector2::new(-direction.y, direction.x) * size * 0.5;
+    Some([to + back + side, to, to + back - side])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrow_heads() {
+        let head = arrow_head(Point2::new(0.0, 0.0), Point2::new(30.0, 0.0), 1.0).unwrap();
+        assert_eq!(
+            head,
+            [
+                Point2::new(24.0, 3.0),
+                Point2::new(30.0, 0.0),
+                Point2::new(24.0, -3.0),
+            ]
+        );
+        // Short arrows get a smaller head.
+        let head = arrow_head(Point2::new(0.0, 0.0), Point2::new(0.0, 9.0), 1.0).unwrap();
+        assert_eq!(head[0], Point2::new(-1.5, 6.0));
+        assert!(arrow_head(Point2::new(1.0, 1.0), Point2::new(1.0, 1.0), 1.0).is_none());
+    }
+
+    #[test]
+    fn lifetimes() {
+        let mut queue = DebugQueue::new();
+        let line = Gizmo::Line(
+            mint::Point2 { x: 0.0, y: 0.0 },
+            mint::Point2 { x: 1.0, y: 1.0 },
+        );
+        queue.push(line.clone(), graphics::WHITE, 0.0);
+        queue.push(line, graphics::WHITE, 0.5);
+        queue.drawn = true;
+
+        queue.end_frame(0.1);
+        assert_eq!(queue.gizmos.len(), 1);
+        assert!(!queue.drawn);
+        queue.end_frame(0.3);
+        assert_eq!(queue.gizmos.len(), 1);
+        queue.end_frame(0.1);
+        assert!(queue.gizmos.is_empty());
+    }
+}
@@ -0,0 +1,459 @@
+//! A tiny GLSL scanner used to check user shaders against what ggez
+//! feeds them before handing them to the driver, and to turn driver
+//! compile logs into errors that point at the offending source line.
+//!
+//! This is not a GLSL parser; it only looks at top-level declarations
+//! (`in`, `uniform` and uniform blocks) and so can be fooled by
+//! preprocessor tricks.  Anything it doesn't understand is left for
+//! the driver to complain about.
+
+use error::{GameError, GameResult};
+
+/// The vertex attributes ggez provides, from `Vertex` and
+/// `InstanceProperties`, with their GLSL types.
+const ATTRIBUTES: &[(&str, &str)] = &[
+    ("a_Pos", "vec2"),
+    ("a_Uv", "vec2"),
+    ("a_VertColor", "vec4"),
+    ("a_Src", "vec4"),
+    ("a_TCol1", "vec4"),
+    ("a_TCol2", "vec4"),
+    ("a_TCol3", "vec4"),
+    ("a_TCol4", "vec4"),
+    ("a_Color", "vec4"),
+];
+
+/// The uniform block holding ggez's projection and view transform.
+const GLOBALS: &str = "Globals";
+
+/// A shader stage, for error messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Vertex,
+    Pixel,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Vertex => "vertex",
+            Stage::Pixel => "pixel",
+        }
+    }
+}
+
+/// A top-level declaration found in a shader.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Declaration {
+    /// `in <ty> <name>;`
+    Input { ty: String, name: String, line: usize },
+    /// `uniform <ty> <name>;`
+    Uniform { ty: String, name: String, line: usize },
+    /// `uniform <name> { <members> };`
+    Block {
+        name: String,
+        members: Vec<(String, String)>,
+        line: usize,
+    },
+}
+
+/// Replaces comments with spaces, keeping line breaks so that line
+/// numbers still match the original source.
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().cloned()) {
+            ('/', Some('/')) => {
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    let _ = chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                let _ = chars.next();
+                let mut last = ' ';
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && next == '/' {
+                        break;
+                    }
+                    last = next;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Splits source into `(line, token)` pairs, treating `{`, `}`, `;`,
+/// `(`, `)` and `,` as tokens of their own.  Preprocessor lines are
+/// skipped.
+fn tokens(source: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    for (index, line) in strip_comments(source).lines().enumerate() {
+        if line.trim().starts_with('#') {
+            continue;
+        }
+        let mut word = String::new();
+        for c in line.chars() {
+            if c.is_alphanumeric() || c == '_' || c == '.' || c == '[' || c == ']' {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                tokens.push((index + 1, word.clone()));
+                word.clear();
+            }
+            if "{};(),".contains(c) {
+                tokens.push((index + 1, c.to_string()));
+            }
+        }
+        if !word.is_empty() {
+            tokens.push((index + 1, word));
+        }
+    }
+    tokens
+}
+
+/// Finds the `in`, `uniform` and uniform block declarations at the top
+/// level of the given source.
+pub(crate) fn declarations(source: &str) -> Vec<Declaration> {
+    const QUALIFIERS: &[&str] = &[
+        "flat",
+        "smooth",
+        "noperspective",
+        "centroid",
+        "lowp",
+        "mediump",
+        "highp",
+    ];
+    let tokens = tokens(source);
+    let mut declarations = Vec::new();
+    let mut depth = 0;
+    // The tokens of the statement being read.
+    let mut statement: Vec<&(usize, String)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        i += 1;
+        match token.1.as_str() {
+            "{" if depth == 0 && is_block(&statement) => {
+                let mut members = Vec::new();
+                let mut member: Vec<&str> = Vec::new();
+                while i < tokens.len() && tokens[i].1 != "}" {
+                    if tokens[i].1 == ";" {
+                        if member.len() >= 2 {
+                            let name = member[member.len() - 1];
+                            let ty = member[member.len() - 2];
+                            members.push((ty.to_string(), name.to_string()));
+                        }
+                        member.clear();
+                    } else {
+                        member.push(&tokens[i].1);
+                    }
+                    i += 1;
+                }
+                let line = statement[0].0;
+                let name = statement[statement.len() - 1].1.clone();
+                declarations.push(Declaration::Block {
+                    name,
+                    members,
+                    line,
+                });
+                // Skip the closing brace and anything up to the `;`.
+                while i < tokens.len() && tokens[i].1 != ";" {
+                    i += 1;
+                }
+                i += 1;
+                statement.clear();
+            }
+            "{" => {
+                depth += 1;
+                statement.clear();
+            }
+            "}" => {
+                depth -= 1;
+                statement.clear();
+            }
+            ";" if depth == 0 => {
+                let words: Vec<&(usize, String)> = skip_layout(&statement)
+                    .into_iter()
+                    .filter(|t| !QUALIFIERS.contains(&t.1.as_str()))
+                    .collect();
+                if words.len() == 3 {
+                    let line = words[0].0;
+                    let ty = words[1].1.clone();
+                    let name = words[2].1.clone();
+                    match words[0].1.as_str() {
+                        "in" => declarations.push(Declaration::Input { ty, name, line }),
+                        "uniform" => declarations.push(Declaration::Uniform { ty, name, line }),
+                        _ => (),
+                    }
+                }
+                statement.clear();
+            }
+            ";" => statement.clear(),
+            _ if depth == 0 => statement.push(token),
+            _ => (),
+        }
+    }
+    declarations
+}
+
+/// Drops a leading `layout (...)` qualifier.
+fn skip_layout<'a>(statement: &[&'a (usize, String)]) -> Vec<&'a (usize, String)> {
+    if statement.first().map(|t| t.1.as_str()) != Some("layout") {
+        return statement.to_vec();
+    }
+    match statement.iter().position(|t| t.1 == ")") {
+        Some(end) => statement[end + 1..].to_vec(),
+        None => statement.to_vec(),
+    }
+}
+
+/// Whether the statement so far is the head of a uniform block.
+fn is_block(statement: &[&(usize, String)]) -> bool {
+    let words = skip_layout(statement);
+    words.len() == 2 && words[0].1 == "uniform"
+}
+
+/// Makes an error pointing at a line of the given source.
+fn source_error(name: &str, stage: Stage, source: &str, line: usize, message: &str) -> GameError {
+    let text = source.lines().nth(line - 1).unwrap_or("").trim();
+    GameError::ShaderError(format!(
+        "shader `{}`, {} stage, line {}: {}\n    {}",
+        name,
+        stage.name(),
+        line,
+        message,
+        text
+    ))
+}
+
+/// Checks that the declarations of one stage match what ggez provides:
+/// vertex attributes from `Vertex` and `InstanceProperties`, the
+/// `Globals` block and the block for the shader's constants, which is
+/// called `const_name`.
+fn validate_stage(name: &str, stage: Stage, source: &str, const_name: &str) -> GameResult {
+    for declaration in declarations(source) {
+        match declaration {
+            Declaration::Input { ty, name: attr, line } => {
+                if stage != Stage::Vertex {
+                    continue;
+                }
+                match ATTRIBUTES.iter().find(|a| a.0 == attr) {
+                    Some(&(_, expected)) if expected != ty => {
+                        let message = format!(
+                            "attribute `{}` is a `{}`, but ggez provides a `{}`",
+                            attr, ty, expected
+                        );
+                        return Err(source_error(name, stage, source, line, &message));
+                    }
+                    Some(_) => (),
+                    None => {
+                        let names: Vec<&str> = ATTRIBUTES.iter().map(|a| a.0).collect();
+                        let message = format!(
+                            "unknown vertex attribute `{}`, ggez provides {}",
+                            attr,
+                            names.join(", ")
+                        );
+                        return Err(source_error(name, stage, source, line, &message));
+                    }
+                }
+            }
+            Declaration::Uniform { ty, name: uniform, line } => {
+                if !ty.starts_with("sampler") {
+                    let message = format!(
+                        "uniform `{}` is not in a uniform block; put it in the `{}` block \
+                         to set it from the shader constants",
+                        uniform, const_name
+                    );
+                    return Err(source_error(name, stage, source, line, &message));
+                }
+            }
+            Declaration::Block {
+                name: block,
+                members,
+                line,
+            } => {
+                if block == GLOBALS {
+                    let has_mvp = members.iter().any(|m| m.0 == "mat4" && m.1 == "u_MVP");
+                    if members.len() != 1 || !has_mvp {
+                        let message = "the `Globals` block must hold exactly `mat4 u_MVP;`";
+                        return Err(source_error(name, stage, source, line, message));
+                    }
+                } else if block != const_name {
+                    let message = format!(
+                        "unknown uniform block `{}`, expected `{}` or `{}`",
+                        block, GLOBALS, const_name
+                    );
+                    return Err(source_error(name, stage, source, line, &message));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks a vertex and pixel shader pair before compiling them.
+pub(crate) fn validate(name: &str, vertex: &[u8], pixel: &[u8]) -> GameResult {
+    let vertex = as_str(name, Stage::Vertex, vertex)?;
+    let pixel = as_str(name, Stage::Pixel, pixel)?;
+    validate_stage(name, Stage::Vertex, vertex, name)?;
+    let blocks = declarations(vertex);
+    let has_globals = blocks.iter().any(|d| match *d {
+        Declaration::Block { ref name, .. } => name == GLOBALS,
+        _ => false,
+    });
+    if !has_globals {
+        warn!(
+            "Vertex stage of shader `{}` has no `{}` block and will ignore the \
+             screen coordinates and transform",
+            name, GLOBALS
+        );
+    }
+    validate_stage(name, Stage::Pixel, pixel, name)
+}
+
+fn as_str<'a>(name: &str, stage: Stage, source: &'a [u8]) -> GameResult<&'a str> {
+    ::std::str::from_utf8(source).map_err(|e| {
+        GameError::ShaderError(format!(
+            "shader `{}`, {} stage: source is not valid UTF-8 ({})",
+            name,
+            stage.name(),
+            e
+        ))
+    })
+}
+
+/// Finds the source line a line of a driver's compile log is about.
+/// Drivers disagree on the format; this knows `0:12(5): error`
+/// (Mesa), `ERROR: 0:12: ...` (AMD, Intel and others) and
+/// `0(12) : error` (Nvidia).
+fn log_line_number(log_line: &str) -> Option<usize> {
+    let digits = |s: &str| -> Option<usize> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        s[..end].parse().ok()
+    };
+    let mut rest = log_line;
+    while let Some(pos) = rest.find('0') {
+        let after = &rest[pos + 1..];
+        let starts_token = pos == 0 || !rest[..pos].ends_with(|c: char| c.is_alphanumeric());
+        let separator = after.chars().next();
+        if starts_token && (separator == Some(':') || separator == Some('(')) {
+            if let Some(n) = digits(&after[1..]) {
+                return Some(n);
+            }
+        }
+        rest = after;
+    }
+    None
+}
+
+/// Turns a driver's compile log for one stage into an error quoting
+/// the source lines it complains about.
+pub(crate) fn compile_error(name: &str, stage: Stage, source: &[u8], log: &str) -> GameError {
+    let source = String::from_utf8_lossy(source);
+    let lines: Vec<&str> = source.lines().collect();
+    let mut message = format!("shader `{}`, {} stage failed to compile:", name, stage.name());
+    for log_line in log.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        message.push_str("\n  ");
+        match log_line_number(log_line) {
+            Some(line) if line >= 1 && line <= lines.len() => {
+                message.push_str(&format!(
+                    "line {}: {}\n    {}",
+                    line,
+                    log_line,
+                    lines[line - 1].trim()
+                ));
+            }
+            _ => message.push_str(log_line),
+        }
+    }
+    GameError::ShaderError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX: &str = include_str!("shader/basic_150.glslv");
+    const PIXEL: &str = include_str!("shader/basic_150.glslf");
+
+    #[test]
+    fn glsl_declarations() {
+        let source = "#version 150 core\n\
+                      // in vec2 a_Commented;\n\
+                      layout (location = 0) in vec2 a_Pos;\n\
+                      flat in vec4 a_Color; /* in vec3 a_Hidden; */\n\
+                      uniform sampler2D t_Texture;\n\
+                      layout (std140) uniform Globals {\n\
+                          mat4 u_MVP;\n\
+                      };\n\
+                      void main() { vec4 in_body; }\n";
+        let found = declarations(source);
+        assert_eq!(
+            found,
+            vec![
+                Declaration::Input {
+                    ty: "vec2".into(),
+                    name: "a_Pos".into(),
+                    line: 3,
+                },
+                Declaration::Input {
+                    ty: "vec4".into(),
+                    name: "a_Color".into(),
+                    line: 4,
+                },
+                Declaration::Uniform {
+                    ty: "sampler2D".into(),
+                    name: "t_Texture".into(),
+                    line: 5,
+                },
+                Declaration::Block {
+                    name: "Globals".into(),
+                    members: vec![("mat4".into(), "u_MVP".into())],
+                    line: 6,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn glsl_validation() {
+        assert!(validate("Consts", VERTEX.as_bytes(), PIXEL.as_bytes()).is_ok());
+
+        let wrong_type = VERTEX.replace("in vec2 a_Pos", "in vec3 a_Pos");
+        let error = validate("Consts", wrong_type.as_bytes(), PIXEL.as_bytes()).unwrap_err();
+        let message = format!("{}", error);
+        assert!(message.contains("shader `Consts`, vertex stage, line 3"));
+        assert!(message.contains("in vec3 a_Pos;"));
+
+        let pixel = format!("{}\nuniform float u_Time;\n", PIXEL);
+        assert!(validate("Consts", VERTEX.as_bytes(), pixel.as_bytes()).is_err());
+        let pixel = format!("{}\nlayout (std140) uniform Other {{ float u_Time; }};\n", PIXEL);
+        assert!(validate("Consts", VERTEX.as_bytes(), pixel.as_bytes()).is_err());
+        let pixel = format!("{}\nlayout (std140) uniform Consts {{ float u_Time; }};\n", PIXEL);
+        assert!(validate("Consts", VERTEX.as_bytes(), pixel.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn glsl_compile_logs() {
+        assert_eq!(log_line_number("0:12(5): error: `x' undeclared"), Some(12));
+        assert_eq!(log_line_number("ERROR: 0:7: 'x' : undeclared identifier"), Some(7));
+        assert_eq!(log_line_number("0(21) : error C1008: undefined variable"), Some(21));
+        assert_eq!(log_line_number("error: linking failed"), None);
+
+        let error = compile_error("Consts", Stage::Pixel, b"a\nb\nbroken();\n", "0:3(1): error: x");
+        let message = format!("{}", error);
+        assert!(message.contains("pixel stage"));
+        assert!(message.contains("line 3: 0:3(1): error: x\n    broken();"));
+    }
+}
@@ -12,7 +12,7 @@ use GameError;
 use GameResult;
 
 /// Generic in-GPU-memory image data available to be drawn on the screen.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ImageGeneric<B>
 where
     B: BackendSpec,
@@ -337,30 +337,12 @@ impl Drawable for Image {
         );
         let new_param = param.mul(Matrix4::new_nonuniform_scaling(&real_scale));
 
-        gfx.update_instance_properties(new_param)?;
-        let sampler = gfx.samplers
-            .get_or_insert(self.sampler_info, gfx.factory.as_mut());
-        gfx.data.vbuf = gfx.quad_vertex_buffer.clone();
-        let typed_thingy = gfx.backend_spec
-            .raw_to_typed_shader_resource(self.texture.clone());
-        gfx.data.tex = (typed_thingy, sampler);
-        let previous_mode: Option<BlendMode> = if let Some(mode) = self.blend_mode {
-            let current_mode = gfx.get_blend_mode();
-            if current_mode != mode {
-                gfx.set_blend_mode(mode)?;
-                Some(current_mode)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        gfx.draw(None)?;
-        if let Some(mode) = previous_mode {
-            gfx.set_blend_mode(mode)?;
-        }
-        Ok(())
+        gfx.queue_quad(
+            self.texture.clone(),
+            self.sampler_info,
+            self.blend_mode,
+            new_param,
+        )
     }
 
     fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
@@ -1,7 +1,18 @@
+use std::borrow::Cow;
+use std::f32;
+use std::mem;
+use std::u16;
+
 use context::DebugId;
-use gfx::traits::FactoryExt;
+use gfx::buffer::Role;
+use gfx::handle::Buffer;
+use gfx::memory::{Bind, Usage};
+use gfx::traits::{FactoryExt, Pod};
 use graphics::*;
 use lyon;
+use lyon::path::builder::{FlatPathBuilder, PathBuilder as LyonPathBuilder};
+use lyon::path::iterator::PathIterator;
+use lyon::path::{FlattenedEvent, PathEvent};
 use lyon::tessellation as t;
 
 pub use self::t::{FillOptions, FillRule, LineCap, LineJoin, StrokeOptions};
@@ -57,12 +68,22 @@ pub use self::t::{FillOptions, FillRule, LineCap, LineJoin, StrokeOptions};
 #[derive(Debug, Clone)]
 pub struct MeshBuilder {
     buffer: t::geometry_builder::VertexBuffers<Vertex>,
+    color: Color,
+    uv_rect: Option<Rect>,
+    texture: Option<Image>,
+    dashes: Vec<f32>,
+    dash_offset: f32,
 }
 
 impl Default for MeshBuilder {
     fn default() -> Self {
         Self {
             buffer: t::VertexBuffers::new(),
+            color: WHITE,
+            uv_rect: None,
+            texture: None,
+            dashes: vec![],
+            dash_offset: 0.0,
         }
     }
 }
@@ -73,6 +94,116 @@ impl MeshBuilder {
         Self::default()
     }
 
+    /// Sets the vertex color of the shapes added after this.  It is
+    /// multiplied with the texture and the color the mesh is drawn
+    /// with.  The default is white.
+    pub fn color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    /// Maps the texture onto the shapes added after this by their
+    /// position: the corners of `rect`, in the same coordinates as the
+    /// shapes, get the corners of the texture.  Outside of `rect` the
+    /// texture repeats or clamps according to its `WrapMode`.
+    ///
+    /// `None`, the default, gives every vertex the UV coordinates
+    /// `(0, 0)`, as does a `rect` of zero width or height along that
+    /// axis.
+    pub fn uv_rect(&mut self, rect: Option<Rect>) -> &mut Self {
+        self.uv_rect = rect;
+        self
+    }
+
+    /// Sets the texture the mesh is drawn with.  Without one, meshes
+    /// are drawn in their vertex colors only.
+    pub fn texture(&mut self, texture: Image) -> &mut Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    /// Dashes the outlines of the shapes added after this: `pattern`
+    /// alternates the lengths of the dashes and of the gaps between
+    /// them, starting `offset` into the pattern, like SVG's
+    /// `stroke-dasharray`.  A pattern of odd length is repeated once
+    /// to make it even.
+    ///
+    /// An empty pattern, the default, draws solid outlines, as does a
+    /// pattern with negative lengths or a total length of zero.
+    /// Filled shapes are not affected.
+    pub fn dashes(&mut self, pattern: &[f32], offset: f32) -> &mut Self {
+        self.dashes.clear();
+        let total: f32 = pattern.iter().sum();
+        if pattern.iter().all(|&len| len >= 0.0) && total > 0.0 {
+            self.dashes.extend_from_slice(pattern);
+            if pattern.len() % 2 == 1 {
+                self.dashes.extend_from_slice(pattern);
+            }
+        }
+        self.dash_offset = offset;
+        self
+    }
+
+    /// Adds raw vertices and the indices of their triangles, which
+    /// refer to `verts` alone.  The vertices keep their own colors and
+    /// UVs.
+    ///
+    /// Panics if an index is out of bounds, or if the builder would end
+    /// up with more vertices than 16-bit indices can refer to.
+    pub fn raw<V>(&mut self, verts: &[V], indices: &[u16]) -> &mut Self
+    where
+        V: Into<Vertex> + Clone,
+    {
+        assert!(indices.iter().all(|&i| (i as usize) < verts.len()));
+        assert!(
+            self.buffer.vertices.len() + verts.len() <= usize::from(u16::MAX) + 1,
+            "A mesh can have at most {} vertices",
+            usize::from(u16::MAX) + 1
+        );
+        let offset = self.buffer.vertices.len() as u16;
+        self.buffer
+            .vertices
+            .extend(verts.iter().cloned().map(Into::into));
+        self.buffer
+            .indices
+            .extend(indices.iter().map(|i| i + offset));
+        self
+    }
+
+    /// Removes all geometry, keeping the color, UV mapping, texture and
+    /// dashes, so the builder can be reused.
+    pub fn clear(&mut self) -> &mut Self {
+        self.buffer.vertices.clear();
+        self.buffer.indices.clear();
+        self
+    }
+
+    /// The vertices built so far.
+    pub(crate) fn vertices(&self) -> &[Vertex] {
+        &self.buffer.vertices
+    }
+
+    /// Makes the colors of the vertices built so far linear, for
+    /// drawing them on an sRGB surface.
+    pub(crate) fn make_linear(&mut self) -> &mut Self {
+        for vertex in &mut self.buffer.vertices {
+            *vertex = linear_vertex(*vertex);
+        }
+        self
+    }
+
+    /// The indices of the triangles built so far.
+    pub(crate) fn indices(&self) -> &[u16] {
+        &self.buffer.indices
+    }
+
+    fn vertex_builder(&self) -> VertexBuilder {
+        VertexBuilder {
+            color: self.color.into(),
+            uv_rect: self.uv_rect,
+        }
+    }
+
     /// Create a new mesh for a line of one or more connected segments.
     pub fn line<P>(&mut self, points: &[P], width: f32) -> &mut Self
     where
@@ -88,15 +219,19 @@ impl MeshBuilder {
     where
         P: Into<mint::Point2<f32>>,
     {
+        let point = point.into();
+        if self.is_dashed(mode) {
+            return self.ellipse(mode, point, radius, radius, tolerance);
+        }
         {
-            let point = point.into();
+            let vertex_builder = self.vertex_builder();
             let buffers = &mut self.buffer;
             match mode {
                 DrawMode::Fill => {
                     // These builders have to be in separate match arms 'cause they're actually
                     // different types; one is GeometryBuilder<StrokeVertex> and the other is
                     // GeometryBuilder<FillVertex>
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let fill_options = t::FillOptions::default().with_tolerance(tolerance);
                     let _ = t::basic_shapes::fill_circle(
                         t::math::point(point.x, point.y),
@@ -106,7 +241,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::Line(line_width) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let options = t::StrokeOptions::default()
                         .with_line_width(line_width)
                         .with_tolerance(tolerance);
@@ -118,7 +253,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::CustomFill(fill_options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::fill_circle(
                         t::math::point(point.x, point.y),
                         radius,
@@ -127,7 +262,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::CustomLine(options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::stroke_circle(
                         t::math::point(point.x, point.y),
                         radius,
@@ -154,12 +289,26 @@ impl MeshBuilder {
     where
         P: Into<mint::Point2<f32>>,
     {
+        let point = point.into();
+        if self.is_dashed(mode) {
+            let center = t::math::point(point.x, point.y);
+            let mut path = lyon::path::default::Path::builder();
+            path.move_to(center + t::math::vector(radius1, 0.0));
+            path.arc(
+                center,
+                t::math::vector(radius1, radius2),
+                t::math::Angle::radians(2.0 * f32::consts::PI),
+                t::math::Angle::radians(0.0),
+            );
+            path.close();
+            return self.path_inner(mode, &path.build(), Some(tolerance));
+        }
         {
+            let vertex_builder = self.vertex_builder();
             let buffers = &mut self.buffer;
-            let point = point.into();
             match mode {
                 DrawMode::Fill => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let fill_options = t::FillOptions::default().with_tolerance(tolerance);
                     let _ = t::basic_shapes::fill_ellipse(
                         t::math::point(point.x, point.y),
@@ -170,7 +319,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::Line(line_width) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let options = t::StrokeOptions::default()
                         .with_line_width(line_width)
                         .with_tolerance(tolerance);
@@ -183,7 +332,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::CustomFill(fill_options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::fill_ellipse(
                         t::math::point(point.x, point.y),
                         t::math::vector(radius1, radius2),
@@ -193,7 +342,7 @@ impl MeshBuilder {
                     );
                 }
                 DrawMode::CustomLine(options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::stroke_ellipse(
                         t::math::point(point.x, point.y),
                         t::math::vector(radius1, radius2),
@@ -227,8 +376,25 @@ impl MeshBuilder {
     where
         P: Into<mint::Point2<f32>> + Clone,
     {
+        assert!(points.len() > 1);
+        if self.is_dashed(mode) {
+            let mut path = lyon::path::default::Path::builder();
+            for (i, point) in points.iter().cloned().enumerate() {
+                let point: mint::Point2<f32> = point.into();
+                let point = t::math::point(point.x, point.y);
+                if i == 0 {
+                    path.move_to(point);
+                } else {
+                    path.line_to(point);
+                }
+            }
+            if is_closed {
+                path.close();
+            }
+            return self.path_inner(mode, &path.build(), None);
+        }
         {
-            assert!(points.len() > 1);
+            let vertex_builder = self.vertex_builder();
             let buffers = &mut self.buffer;
             let points = points.into_iter().cloned().map(|p| {
                 let mint_point: mint::Point2<f32> = p.into();
@@ -236,7 +402,7 @@ impl MeshBuilder {
             });
             match mode {
                 DrawMode::Fill => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let tessellator = &mut t::FillTessellator::new();
                     let options = t::FillOptions::default();
                     // TODO: Removing this expect would be rather nice.
@@ -244,19 +410,19 @@ impl MeshBuilder {
                         .expect("Could not fill polygon?");
                 }
                 DrawMode::Line(width) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let options = t::StrokeOptions::default().with_line_width(width);
                     let _ = t::basic_shapes::stroke_polyline(points, is_closed, &options, builder);
                 }
                 DrawMode::CustomFill(options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let tessellator = &mut t::FillTessellator::new();
                     // TODO: Removing this expect would be rather nice.
                     let _ = t::basic_shapes::fill_polyline(points, tessellator, &options, builder)
                         .expect("Could not fill polygon?");
                 }
                 DrawMode::CustomLine(options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::stroke_polyline(points, is_closed, &options, builder);
                 }
             };
@@ -266,7 +432,17 @@ impl MeshBuilder {
 
     /// Create a new mesh for a rectangle.
     pub fn rectangle(&mut self, mode: DrawMode, bounds: Rect) -> &mut Self {
+        if self.is_dashed(mode) {
+            let corners = [
+                bounds.point(),
+                Point2::new(bounds.right(), bounds.top()),
+                Point2::new(bounds.right(), bounds.bottom()),
+                Point2::new(bounds.left(), bounds.bottom()),
+            ];
+            return self.polygon(mode, &corners);
+        }
         {
+            let vertex_builder = self.vertex_builder();
             let buffers = &mut self.buffer;
             let rect = t::math::rect(bounds.x, bounds.y, bounds.w, bounds.h);
             match mode {
@@ -274,21 +450,21 @@ impl MeshBuilder {
                     // These builders have to be in separate match arms 'cause they're actually
                     // different types; one is GeometryBuilder<StrokeVertex> and the other is
                     // GeometryBuilder<FillVertex>
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let fill_options = t::FillOptions::default();
                     let _ = t::basic_shapes::fill_rectangle(&rect, &fill_options, builder);
                 }
                 DrawMode::Line(line_width) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let options = t::StrokeOptions::default().with_line_width(line_width);
                     let _ = t::basic_shapes::stroke_rectangle(&rect, &options, builder);
                 }
                 DrawMode::CustomFill(fill_options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::fill_rectangle(&rect, &fill_options, builder);
                 }
                 DrawMode::CustomLine(options) => {
-                    let builder = &mut t::BuffersBuilder::new(buffers, VertexBuilder);
+                    let builder = &mut t::BuffersBuilder::new(buffers, vertex_builder);
                     let _ = t::basic_shapes::stroke_rectangle(&rect, &options, builder);
                 }
             };
@@ -325,8 +501,9 @@ impl MeshBuilder {
                     // nicer, so we'll just live with it.
                 .collect::<Vec<_>>();
             let tris = tris.chunks(3);
+            let vertex_builder = self.vertex_builder();
             let builder: &mut t::BuffersBuilder<_, _, _> =
-                &mut t::BuffersBuilder::new(&mut self.buffer, VertexBuilder);
+                &mut t::BuffersBuilder::new(&mut self.buffer, vertex_builder);
             use lyon::tessellation::GeometryBuilder;
             builder.begin_geometry();
             for tri in tris {
@@ -345,39 +522,491 @@ impl MeshBuilder {
         self
     }
 
+    /// Create a new mesh for a path of lines and curves, which the
+    /// `build` closure draws into the given `PathBuilder`.
+    ///
+    /// ```rust,ignore
+    /// let mesh = MeshBuilder::new()
+    ///     .path(DrawMode::Line(2.0), 0.1, |path| {
+    ///         path.move_to(Point2::new(0.0, 0.0))
+    ///             .quadratic_to(Point2::new(50.0, -40.0), Point2::new(100.0, 0.0))
+    ///             .line_to(Point2::new(100.0, 50.0))
+    ///             .close();
+    ///     })
+    ///     .build(ctx)?;
+    /// ```
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn path<F>(&mut self, mode: DrawMode, tolerance: f32, build: F) -> &mut Self
+    where
+        F: FnOnce(&mut PathBuilder),
+    {
+        let mut builder = PathBuilder::new();
+        build(&mut builder);
+        let path = builder.build();
+        self.path_inner(mode, &path, Some(tolerance))
+    }
+
+    /// Create a new mesh for a rectangle with corners rounded to the
+    /// given radius, which is clamped to half the shorter side.
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn rounded_rectangle(
+        &mut self,
+        mode: DrawMode,
+        bounds: Rect,
+        radius: f32,
+        tolerance: f32,
+    ) -> &mut Self {
+        let radius = radius.min(bounds.w.abs() / 2.0).min(bounds.h.abs() / 2.0);
+        if radius <= 0.0 {
+            return self.rectangle(mode, bounds);
+        }
+        let (left, top) = (bounds.left(), bounds.top());
+        let (right, bottom) = (bounds.right(), bounds.bottom());
+        let quarter = f32::consts::FRAC_PI_2;
+        self.path(mode, tolerance, |path| {
+            let _ = path
+                .move_to(Point2::new(left + radius, top))
+                .line_to(Point2::new(right - radius, top))
+                .arc(Point2::new(right - radius, top + radius), quarter)
+                .line_to(Point2::new(right, bottom - radius))
+                .arc(Point2::new(right - radius, bottom - radius), quarter)
+                .line_to(Point2::new(left + radius, bottom))
+                .arc(Point2::new(left + radius, bottom - radius), quarter)
+                .line_to(Point2::new(left, top + radius))
+                .arc(Point2::new(left + radius, top + radius), quarter)
+                .close();
+        })
+    }
+
+    /// Create a new mesh for an arc of a circle, from `start_angle` to
+    /// `end_angle` in radians.  Angles start at the positive X axis and
+    /// grow clockwise on screen.  Filled, the arc is closed with a
+    /// straight line between its ends.
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn arc<P>(
+        &mut self,
+        mode: DrawMode,
+        center: P,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        tolerance: f32,
+    ) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let center = center.into();
+        let start = Point2::new(
+            center.x + radius * start_angle.cos(),
+            center.y + radius * start_angle.sin(),
+        );
+        let filled = match mode {
+            DrawMode::Fill | DrawMode::CustomFill(_) => true,
+            DrawMode::Line(_) | DrawMode::CustomLine(_) => false,
+        };
+        self.path(mode, tolerance, |path| {
+            let _ = path.move_to(start).arc(center, end_angle - start_angle);
+            if filled {
+                let _ = path.close();
+            }
+        })
+    }
+
+    /// Create a new mesh for a pie slice: an arc of a circle from
+    /// `start_angle` to `end_angle` in radians, closed by lines to its
+    /// center.  Angles are measured as in `arc()`.
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn pie<P>(
+        &mut self,
+        mode: DrawMode,
+        center: P,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        tolerance: f32,
+    ) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let center = center.into();
+        let start = Point2::new(
+            center.x + radius * start_angle.cos(),
+            center.y + radius * start_angle.sin(),
+        );
+        self.path(mode, tolerance, |path| {
+            let _ = path
+                .move_to(center)
+                .line_to(start)
+                .arc(center, end_angle - start_angle)
+                .close();
+        })
+    }
+
+    /// Whether outlines drawn with `mode` get dashed.
+    fn is_dashed(&self, mode: DrawMode) -> bool {
+        match mode {
+            DrawMode::Line(_) | DrawMode::CustomLine(_) => !self.dashes.is_empty(),
+            DrawMode::Fill | DrawMode::CustomFill(_) => false,
+        }
+    }
+
+    /// Fills or strokes a lyon path in the given color and dashes,
+    /// leaving the builder's own color and dashes as they were.
+    pub(crate) fn styled_path(
+        &mut self,
+        mode: DrawMode,
+        path: &lyon::path::default::Path,
+        tolerance: f32,
+        color: Color,
+        dashes: &[f32],
+        dash_offset: f32,
+    ) -> &mut Self {
+        let color = mem::replace(&mut self.color, color);
+        let pattern = mem::replace(&mut self.dashes, vec![]);
+        let offset = self.dash_offset;
+        let _ = self
+            .dashes(dashes, dash_offset)
+            .path_inner(mode, path, Some(tolerance));
+        self.color = color;
+        self.dashes = pattern;
+        self.dash_offset = offset;
+        self
+    }
+
+    /// Fills or strokes a lyon path.  `tolerance` overrides the one in
+    /// the draw mode's options, if given.
+    fn path_inner(
+        &mut self,
+        mode: DrawMode,
+        path: &lyon::path::default::Path,
+        tolerance: Option<f32>,
+    ) -> &mut Self {
+        match mode {
+            DrawMode::Fill => self.fill_path(path, t::FillOptions::default(), tolerance),
+            DrawMode::CustomFill(options) => self.fill_path(path, options, tolerance),
+            DrawMode::Line(width) => {
+                let options = t::StrokeOptions::default().with_line_width(width);
+                self.stroke_path(path, options, tolerance)
+            }
+            DrawMode::CustomLine(options) => self.stroke_path(path, options, tolerance),
+        }
+    }
+
+    fn fill_path(
+        &mut self,
+        path: &lyon::path::default::Path,
+        options: t::FillOptions,
+        tolerance: Option<f32>,
+    ) -> &mut Self {
+        {
+            let options = match tolerance {
+                Some(tolerance) => options.with_tolerance(tolerance),
+                None => options,
+            };
+            let vertex_builder = self.vertex_builder();
+            let builder = &mut t::BuffersBuilder::new(&mut self.buffer, vertex_builder);
+            let _ = t::FillTessellator::new().tessellate_path(path.path_iter(), &options, builder);
+        }
+        self
+    }
+
+    fn stroke_path(
+        &mut self,
+        path: &lyon::path::default::Path,
+        options: t::StrokeOptions,
+        tolerance: Option<f32>,
+    ) -> &mut Self {
+        {
+            let options = match tolerance {
+                Some(tolerance) => options.with_tolerance(tolerance),
+                None => options,
+            };
+            let vertex_builder = self.vertex_builder();
+            if self.dashes.is_empty() {
+                let builder = &mut t::BuffersBuilder::new(&mut self.buffer, vertex_builder);
+                let _ = t::StrokeTessellator::new().tessellate_path(
+                    path.path_iter(),
+                    &options,
+                    builder,
+                );
+            } else {
+                let dashes = dash_polylines(path, options.tolerance, &self.dashes, self.dash_offset);
+                for dash in dashes {
+                    let builder = &mut t::BuffersBuilder::new(&mut self.buffer, vertex_builder);
+                    let _ =
+                        t::basic_shapes::stroke_polyline(dash.into_iter(), false, &options, builder);
+                }
+            }
+        }
+        self
+    }
+
     /// Takes the accumulated geometry and load it into GPU memory,
     /// creating a single `Mesh`.
     pub fn build(&self, ctx: &mut Context) -> GameResult<Mesh> {
+        let vertices = linear_vertices(ctx, &self.buffer.vertices);
         let (vbuf, slice) = ctx.gfx_context
             .factory
-            .create_vertex_buffer_with_slice(&self.buffer.vertices[..], &self.buffer.indices[..]);
+            .create_vertex_buffer_with_slice(&vertices[..], &self.buffer.indices[..]);
 
         Ok(Mesh {
             buffer: vbuf,
             slice,
             blend_mode: None,
+            texture: self.texture.clone(),
             debug_id: DebugId::get(ctx),
         })
     }
 }
 
-struct VertexBuilder;
+/// A path of lines and curves, drawn into by the closure given to
+/// `MeshBuilder::path()`.
+///
+/// A path is made of sub-paths, each starting with `move_to()`.
+/// Filled, every sub-path is closed; stroked, only those ending in
+/// `close()` are.
+#[derive(Debug, Clone)]
+pub struct PathBuilder {
+    events: Vec<PathEvent>,
+    first: t::math::Point,
+    current: t::math::Point,
+}
 
-impl t::VertexConstructor<t::FillVertex, Vertex> for VertexBuilder {
-    fn new_vertex(&mut self, vertex: t::FillVertex) -> Vertex {
+impl PathBuilder {
+    fn new() -> Self {
+        PathBuilder {
+            events: vec![],
+            first: t::math::point(0.0, 0.0),
+            current: t::math::point(0.0, 0.0),
+        }
+    }
+
+    /// Starts a new sub-path at the given point.
+    pub fn move_to<P>(&mut self, to: P) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let to = lyon_point(to);
+        self.first = to;
+        self.current = to;
+        self.events.push(PathEvent::MoveTo(to));
+        self
+    }
+
+    /// Adds a straight line from the current point to the given one.
+    pub fn line_to<P>(&mut self, to: P) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let to = lyon_point(to);
+        self.current = to;
+        self.events.push(PathEvent::LineTo(to));
+        self
+    }
+
+    /// Adds a quadratic Bezier curve from the current point to `to`,
+    /// bent towards the control point `ctrl`.
+    pub fn quadratic_to<P>(&mut self, ctrl: P, to: P) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let to = lyon_point(to);
+        self.current = to;
+        self.events.push(PathEvent::QuadraticTo(lyon_point(ctrl), to));
+        self
+    }
+
+    /// Adds a cubic Bezier curve from the current point to `to`, with
+    /// the control points `ctrl1` and `ctrl2`.
+    pub fn cubic_to<P>(&mut self, ctrl1: P, ctrl2: P, to: P) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let to = lyon_point(to);
+        self.current = to;
+        self.events.push(PathEvent::CubicTo(
+            lyon_point(ctrl1),
+            lyon_point(ctrl2),
+            to,
+        ));
+        self
+    }
+
+    /// Adds an arc of a circle around `center`, starting at the current
+    /// point and sweeping `sweep_angle` radians, clockwise on screen
+    /// for positive angles.  The arc ends at the new current point.
+    pub fn arc<P>(&mut self, center: P, sweep_angle: f32) -> &mut Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let center = lyon_point(center);
+        let from = self.current - center;
+        let radius = from.length();
+        let (sin, cos) = sweep_angle.sin_cos();
+        self.current = center + t::math::vector(
+            from.x * cos - from.y * sin,
+            from.x * sin + from.y * cos,
+        );
+        self.events.push(PathEvent::Arc(
+            center,
+            t::math::vector(radius, radius),
+            t::math::Angle::radians(sweep_angle),
+            t::math::Angle::radians(0.0),
+        ));
+        self
+    }
+
+    /// Closes the current sub-path with a straight line back to its
+    /// start.
+    pub fn close(&mut self) -> &mut Self {
+        self.current = self.first;
+        self.events.push(PathEvent::Close);
+        self
+    }
+
+    fn build(&self) -> lyon::path::default::Path {
+        let mut builder = lyon::path::default::Path::builder();
+        for &event in &self.events {
+            builder.path_event(event);
+        }
+        builder.build()
+    }
+}
+
+fn lyon_point<P>(point: P) -> t::math::Point
+where
+    P: Into<mint::Point2<f32>>,
+{
+    let point = point.into();
+    t::math::point(point.x, point.y)
+}
+
+/// Splits a path into the polylines of its dashes, walking each
+/// sub-path through the dash pattern from the start.
+fn dash_polylines(
+    path: &lyon::path::default::Path,
+    tolerance: f32,
+    pattern: &[f32],
+    offset: f32,
+) -> Vec<Vec<t::math::Point>> {
+    let mut sub_paths: Vec<Vec<t::math::Point>> = vec![];
+    for event in path.path_iter().flattened(tolerance) {
+        match event {
+            FlattenedEvent::MoveTo(to) => sub_paths.push(vec![to]),
+            FlattenedEvent::LineTo(to) => match sub_paths.last_mut() {
+                Some(points) => points.push(to),
+                None => sub_paths.push(vec![to]),
+            },
+            FlattenedEvent::Close => {
+                if let Some(points) = sub_paths.last_mut() {
+                    let first = points[0];
+                    points.push(first);
+                }
+            }
+        }
+    }
+
+    let total: f32 = pattern.iter().sum();
+    let mut dashes = vec![];
+    for points in sub_paths {
+        // Find where in the pattern the offset starts us off.
+        let mut index = 0;
+        let mut remaining = pattern[0];
+        let mut skip = offset % total;
+        if skip < 0.0 {
+            skip += total;
+        }
+        while skip > remaining {
+            skip -= remaining;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= skip;
+
+        let mut dash = vec![];
+        if index % 2 == 0 {
+            dash.push(points[0]);
+        }
+        for segment in points.windows(2) {
+            let (mut from, to) = (segment[0], segment[1]);
+            let mut length = (to - from).length();
+            // Nothing to dash, and with a 0 in the pattern it would
+            // divide 0 by 0 below.
+            if length <= 0.0 {
+                continue;
+            }
+            while length >= remaining {
+                let split = if length > 0.0 {
+                    from.lerp(to, remaining / length)
+                } else {
+                    to
+                };
+                dash.push(split);
+                if index % 2 == 0 {
+                    dashes.push(dash);
+                    dash = vec![];
+                }
+                from = split;
+                length -= remaining;
+                index = (index + 1) % pattern.len();
+                remaining = pattern[index];
+            }
+            remaining -= length;
+            if index % 2 == 0 {
+                dash.push(to);
+            }
+        }
+        if index % 2 == 0 && dash.len() > 1 {
+            dashes.push(dash);
+        }
+    }
+    dashes
+}
+
+/// Turns lyon's vertices into ours, with the builder's current color
+/// and UV mapping.
+#[derive(Debug, Clone, Copy)]
+struct VertexBuilder {
+    color: [f32; 4],
+    uv_rect: Option<Rect>,
+}
+
+impl VertexBuilder {
+    fn vertex(&self, x: f32, y: f32) -> Vertex {
+        // A rectangle with no size along an axis has nothing to map
+        // onto it, so it maps to 0 rather than dividing by zero.
+        let along = |coord: f32, start: f32, size: f32| {
+            if size == 0.0 {
+                0.0
+            } else {
+                (coord - start) / size
+            }
+        };
+        let uv = match self.uv_rect {
+            Some(rect) => [along(x, rect.x, rect.w), along(y, rect.y, rect.h)],
+            None => [0.0, 0.0],
+        };
         Vertex {
-            pos: [vertex.position.x, vertex.position.y],
-            uv: [0.0, 0.0],
+            pos: [x, y],
+            uv,
+            color: self.color,
         }
     }
 }
 
+impl t::VertexConstructor<t::FillVertex, Vertex> for VertexBuilder {
+    fn new_vertex(&mut self, vertex: t::FillVertex) -> Vertex {
+        self.vertex(vertex.position.x, vertex.position.y)
+    }
+}
+
 impl t::VertexConstructor<t::StrokeVertex, Vertex> for VertexBuilder {
     fn new_vertex(&mut self, vertex: t::StrokeVertex) -> Vertex {
-        Vertex {
-            pos: [vertex.position.x, vertex.position.y],
-            uv: [0.0, 0.0],
-        }
+        self.vertex(vertex.position.x, vertex.position.y)
     }
 }
 
@@ -390,6 +1019,7 @@ pub struct Mesh {
     buffer: gfx::handle::Buffer<gfx_device_gl::Resources, Vertex>,
     slice: gfx::Slice<gfx_device_gl::Resources>,
     blend_mode: Option<BlendMode>,
+    texture: Option<Image>,
     debug_id: DebugId,
 }
 
@@ -475,7 +1105,8 @@ impl Mesh {
     }
 
     /// Creates a `Mesh` from a raw list of triangles defined from points
-    /// and indices, with the given UV texture coordinates.
+    /// and indices, with the given UV texture coordinates and colors.
+    /// Use `set_texture()` to give it a texture.
     ///
     /// This is the most primitive mesh-creation method, but allows you full
     /// control over the tesselation and texturing.
@@ -488,6 +1119,7 @@ impl Mesh {
         V: Into<Vertex> + Clone,
     {
         let verts: Vec<Vertex> = verts.iter().cloned().map(|v| v.into()).collect();
+        let verts = linear_vertices(ctx, &verts);
         let (vbuf, slice) = ctx.gfx_context
             .factory
             .create_vertex_buffer_with_slice(&verts[..], indices);
@@ -495,9 +1127,39 @@ impl Mesh {
             buffer: vbuf,
             slice,
             blend_mode: None,
+            texture: None,
             debug_id: DebugId::get(ctx),
         }
     }
+
+    /// Gets the texture the mesh is drawn with, if any.
+    pub fn get_texture(&self) -> Option<&Image> {
+        self.texture.as_ref()
+    }
+
+    /// Sets the texture the mesh is drawn with.  `None` draws it in
+    /// its vertex colors only.
+    pub fn set_texture(&mut self, texture: Option<Image>) {
+        self.texture = texture;
+    }
+
+    /// Draws the given number of instances of the mesh, whose
+    /// properties are already in the instance buffer, with the current
+    /// transform.
+    pub(crate) fn draw_instances(&self, ctx: &mut Context, instances: u32) -> GameResult {
+        self.debug_id.assert(ctx);
+        let mut slice = self.slice.clone();
+        slice.instances = Some((instances, 0));
+        let gfx = &mut ctx.gfx_context;
+        gfx.data.vbuf = self.buffer.clone();
+        let image = self.texture.as_ref().unwrap_or(&gfx.white_image);
+        let sampler = gfx.samplers
+            .get_or_insert(image.sampler_info, gfx.factory.as_mut());
+        let typed_thingy = gfx.backend_spec
+            .raw_to_typed_shader_resource(image.texture.clone());
+        gfx.data.tex = (typed_thingy, sampler);
+        gfx.draw(Some(&slice))
+    }
 }
 
 impl Drawable for Mesh {
@@ -505,21 +1167,155 @@ impl Drawable for Mesh {
     where
         D: Into<DrawTransform>,
     {
-        let param = param.into();
         self.debug_id.assert(ctx);
-        let gfx = &mut ctx.gfx_context;
-        gfx.update_instance_properties(param)?;
+        draw_vertices(
+            ctx,
+            &self.buffer,
+            &self.slice,
+            self.texture.as_ref(),
+            param.into(),
+        )
+    }
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.blend_mode = mode;
+    }
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.blend_mode
+    }
+}
 
-        gfx.data.vbuf = self.buffer.clone();
-        let texture = gfx.white_image.texture.clone();
+/// A mesh whose vertices and indices can be replaced after it is
+/// created, for geometry that changes often, such as terrain being
+/// dug into or a trail following the mouse.
+///
+/// Its GPU buffers are only reallocated when new data doesn't fit in
+/// them, and they never shrink, so a `DynamicMesh` that is updated
+/// every frame soon stops allocating altogether.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicMesh {
+    buffer: Buffer<gfx_device_gl::Resources, Vertex>,
+    index_buffer: Buffer<gfx_device_gl::Resources, u16>,
+    slice: gfx::Slice<gfx_device_gl::Resources>,
+    blend_mode: Option<BlendMode>,
+    texture: Option<Image>,
+    debug_id: DebugId,
+}
 
-        let typed_thingy = gfx.backend_spec.raw_to_typed_shader_resource(texture);
-        gfx.data.tex.0 = typed_thingy;
+impl DynamicMesh {
+    /// Creates an empty mesh with room for the given numbers of
+    /// vertices and indices.
+    pub fn new(
+        ctx: &mut Context,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> GameResult<DynamicMesh> {
+        let factory = &mut *ctx.gfx_context.factory;
+        let buffer = create_dynamic_buffer(factory, Role::Vertex, vertex_capacity)?;
+        let index_buffer = create_dynamic_buffer(factory, Role::Index, index_capacity)?;
+        let slice = gfx::Slice {
+            start: 0,
+            end: 0,
+            base_vertex: 0,
+            instances: None,
+            buffer: gfx::IndexBuffer::Index16(index_buffer.clone()),
+        };
+        Ok(DynamicMesh {
+            buffer,
+            index_buffer,
+            slice,
+            blend_mode: None,
+            texture: None,
+            debug_id: DebugId::get(ctx),
+        })
+    }
 
-        gfx.draw(Some(&self.slice))?;
+    /// Creates a mesh holding the geometry and texture of the given
+    /// `MeshBuilder`.
+    pub fn from_builder(ctx: &mut Context, builder: &MeshBuilder) -> GameResult<DynamicMesh> {
+        let mut mesh = DynamicMesh::new(ctx, builder.vertices().len(), builder.indices().len())?;
+        mesh.update(ctx, builder)?;
+        Ok(mesh)
+    }
 
+    /// Replaces the vertices of the mesh.  The current indices must
+    /// stay in bounds of the new vertices; update them first if not.
+    pub fn update_vertices<V>(&mut self, ctx: &mut Context, verts: &[V]) -> GameResult
+    where
+        V: Into<Vertex> + Clone,
+    {
+        self.debug_id.assert(ctx);
+        let verts: Vec<Vertex> = verts.iter().cloned().map(|v| v.into()).collect();
+        let verts = linear_vertices(ctx, &verts);
+        let gfx = &mut ctx.gfx_context;
+        // Queued draws must still see the old vertices.
+        gfx.flush_batch()?;
+        update_dynamic_buffer(
+            &mut *gfx.factory,
+            &mut gfx.encoder,
+            &mut self.buffer,
+            Role::Vertex,
+            &verts,
+        )
+    }
+
+    /// Replaces the indices of the mesh's triangles, drawing all of
+    /// them from now on.
+    pub fn update_indices(&mut self, ctx: &mut Context, indices: &[u16]) -> GameResult {
+        self.debug_id.assert(ctx);
+        let gfx = &mut ctx.gfx_context;
+        gfx.flush_batch()?;
+        update_dynamic_buffer(
+            &mut *gfx.factory,
+            &mut gfx.encoder,
+            &mut self.index_buffer,
+            Role::Index,
+            indices,
+        )?;
+        self.slice.end = indices.len() as u32;
+        self.slice.buffer = gfx::IndexBuffer::Index16(self.index_buffer.clone());
+        Ok(())
+    }
+
+    /// Replaces the geometry of the mesh with that of the given
+    /// `MeshBuilder`, and its texture with the builder's if it has one.
+    pub fn update(&mut self, ctx: &mut Context, builder: &MeshBuilder) -> GameResult {
+        self.update_vertices(ctx, builder.vertices())?;
+        self.update_indices(ctx, builder.indices())?;
+        if builder.texture.is_some() {
+            self.texture = builder.texture.clone();
+        }
         Ok(())
     }
+
+    /// Gets the texture the mesh is drawn with, if any.
+    pub fn get_texture(&self) -> Option<&Image> {
+        self.texture.as_ref()
+    }
+
+    /// Sets the texture the mesh is drawn with.  `None` draws it in
+    /// its vertex colors only.
+    pub fn set_texture(&mut self, texture: Option<Image>) {
+        self.texture = texture;
+    }
+}
+
+impl Drawable for DynamicMesh {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        self.debug_id.assert(ctx);
+        if self.slice.end == 0 {
+            return Ok(());
+        }
+        draw_vertices(
+            ctx,
+            &self.buffer,
+            &self.slice,
+            self.texture.as_ref(),
+            param.into(),
+        )
+    }
     fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
         self.blend_mode = mode;
     }
@@ -527,3 +1323,235 @@ impl Drawable for Mesh {
         self.blend_mode
     }
 }
+
+/// Draws indexed triangles with the given texture, or none.
+fn draw_vertices(
+    ctx: &mut Context,
+    buffer: &Buffer<gfx_device_gl::Resources, Vertex>,
+    slice: &gfx::Slice<gfx_device_gl::Resources>,
+    texture: Option<&Image>,
+    param: DrawTransform,
+) -> GameResult {
+    let gfx = &mut ctx.gfx_context;
+    gfx.update_instance_properties(param)?;
+
+    gfx.data.vbuf = buffer.clone();
+    let image = texture.unwrap_or(&gfx.white_image);
+    let sampler = gfx.samplers
+        .get_or_insert(image.sampler_info, gfx.factory.as_mut());
+    let typed_thingy = gfx.backend_spec
+        .raw_to_typed_shader_resource(image.texture.clone());
+    gfx.data.tex = (typed_thingy, sampler);
+
+    gfx.draw(Some(slice))
+}
+
+/// Vertex colors are given like any other `Color`, so on an sRGB
+/// surface they have to be made linear like `DrawParam` colors are.
+fn linear_vertices<'a>(ctx: &Context, verts: &'a [Vertex]) -> Cow<'a, [Vertex]> {
+    if !ctx.gfx_context.is_srgb() {
+        return Cow::Borrowed(verts);
+    }
+    Cow::Owned(verts.iter().cloned().map(linear_vertex).collect())
+}
+
+fn linear_vertex(vertex: Vertex) -> Vertex {
+    let color: types::LinearColor = Color::from(vertex.color).into();
+    Vertex {
+        color: color.into(),
+        ..vertex
+    }
+}
+
+/// Creates a buffer that can be updated with `update_dynamic_buffer()`.
+pub(crate) fn create_dynamic_buffer<R, F, T>(
+    factory: &mut F,
+    role: Role,
+    len: usize,
+) -> GameResult<Buffer<R, T>>
+where
+    R: gfx::Resources,
+    F: gfx::Factory<R>,
+    T: Pod,
+{
+    // Zero-sized buffers aren't allowed.
+    let buffer = factory.create_buffer(len.max(1), role, Usage::Dynamic, Bind::TRANSFER_DST)?;
+    Ok(buffer)
+}
+
+/// Uploads `data` to the start of a dynamic buffer, first replacing
+/// the buffer with a bigger one if it doesn't fit.
+pub(crate) fn update_dynamic_buffer<R, C, F, T>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    buffer: &mut Buffer<R, T>,
+    role: Role,
+    data: &[T],
+) -> GameResult
+where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    F: gfx::Factory<R>,
+    T: Pod,
+{
+    if buffer.len() < data.len() {
+        *buffer = create_dynamic_buffer(factory, role, data.len().next_power_of_two())?;
+    }
+    if !data.is_empty() {
+        encoder.update_buffer(buffer, data, 0)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_builder_vertices() {
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+        let mut mb = MeshBuilder::new();
+        let _ = mb
+            .color(red)
+            .uv_rect(Some(Rect::new(10.0, 10.0, 20.0, 40.0)))
+            .rectangle(DrawMode::Fill, Rect::new(10.0, 10.0, 20.0, 40.0));
+        let filled = mb.buffer.vertices.len();
+        assert!(filled >= 4);
+        for vertex in &mb.buffer.vertices {
+            assert_eq!(vertex.color, [1.0, 0.0, 0.0, 1.0]);
+            assert_eq!(vertex.uv[0], (vertex.pos[0] - 10.0) / 20.0);
+            assert_eq!(vertex.uv[1], (vertex.pos[1] - 10.0) / 40.0);
+        }
+
+        let corner = Vertex {
+            pos: [0.0, 0.0],
+            uv: [0.5, 0.5],
+            color: [0.0, 1.0, 0.0, 1.0],
+        };
+        let _ = mb.raw(&[corner, corner, corner], &[0, 1, 2]);
+        assert_eq!(mb.buffer.vertices[filled], corner);
+        let offset = filled as u16;
+        assert_eq!(
+            &mb.buffer.indices[mb.buffer.indices.len() - 3..],
+            &[offset, offset + 1, offset + 2]
+        );
+
+        let _ = mb.clear();
+        assert!(mb.vertices().is_empty());
+        assert!(mb.indices().is_empty());
+        assert_eq!(mb.color, red);
+
+        // A flat UV rectangle maps to 0 along its flat axis.
+        let _ = mb
+            .uv_rect(Some(Rect::new(0.0, 0.0, 0.0, 10.0)))
+            .rectangle(DrawMode::Fill, Rect::new(0.0, 0.0, 10.0, 10.0));
+        for vertex in mb.vertices() {
+            assert_eq!(vertex.uv[0], 0.0);
+            assert!(vertex.uv[1].is_finite());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn mesh_builder_raw_overflow() {
+        let corner = Vertex {
+            pos: [0.0, 0.0],
+            uv: [0.0, 0.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+        };
+        let verts = vec![corner; usize::from(u16::MAX)];
+        let mut mb = MeshBuilder::new();
+        let _ = mb.raw(&verts, &[0, 1, 2]).raw(&verts, &[0, 1, 2]);
+    }
+
+    #[test]
+    fn mesh_builder_paths() {
+        let modes = [
+            DrawMode::Fill,
+            DrawMode::Line(2.0),
+            DrawMode::CustomLine(StrokeOptions::default().with_line_width(4.0)),
+        ];
+        for &mode in &modes {
+            let mut mb = MeshBuilder::new();
+            let _ = mb.path(mode, 0.1, |path| {
+                let _ = path
+                    .move_to(Point2::new(0.0, 0.0))
+                    .line_to(Point2::new(10.0, 0.0))
+                    .quadratic_to(Point2::new(20.0, 5.0), Point2::new(10.0, 10.0))
+                    .cubic_to(
+                        Point2::new(8.0, 20.0),
+                        Point2::new(2.0, 20.0),
+                        Point2::new(0.0, 10.0),
+                    )
+                    .arc(Point2::new(0.0, 5.0), f32::consts::PI)
+                    .close();
+            });
+            assert!(!mb.indices().is_empty());
+            let _ = mb.clear();
+            let _ = mb.rounded_rectangle(mode, Rect::new(0.0, 0.0, 40.0, 20.0), 5.0, 0.1);
+            assert!(!mb.indices().is_empty());
+            let _ = mb.clear();
+            let _ = mb.arc(mode, Point2::new(0.0, 0.0), 10.0, 0.0, 2.0, 0.1);
+            assert!(!mb.indices().is_empty());
+            let _ = mb.clear();
+            let _ = mb.pie(mode, Point2::new(0.0, 0.0), 10.0, 0.0, 2.0, 0.1);
+            assert!(!mb.indices().is_empty());
+        }
+
+        let mut path = PathBuilder::new();
+        let _ = path
+            .move_to(Point2::new(10.0, 0.0))
+            .arc(Point2::new(0.0, 0.0), f32::consts::FRAC_PI_2);
+        assert!(path.current.x.abs() < 1e-5);
+        assert!((path.current.y - 10.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dash_patterns() {
+        let mut path = lyon::path::default::Path::builder();
+        path.move_to(t::math::point(0.0, 0.0));
+        path.line_to(t::math::point(10.0, 0.0));
+        path.line_to(t::math::point(10.0, 5.0));
+        let path = path.build();
+
+        let starts = |dashes: &[Vec<t::math::Point>]| {
+            dashes
+                .iter()
+                .map(|dash| (dash[0].x, dash[0].y, dash.len()))
+                .collect::<Vec<_>>()
+        };
+        let dashes = dash_polylines(&path, 0.1, &[4.0, 2.0], 0.0);
+        assert_eq!(
+            starts(&dashes),
+            vec![(0.0, 0.0, 2), (6.0, 0.0, 2), (10.0, 2.0, 2)]
+        );
+        // The offset shifts the pattern back along the path, and a dash
+        // bends around the corner as one polyline.
+        let dashes = dash_polylines(&path, 0.1, &[4.0, 2.0], 5.0);
+        assert_eq!(starts(&dashes), vec![(1.0, 0.0, 2), (7.0, 0.0, 3), (10.0, 3.0, 2)]);
+
+        // Zero lengths in the path and in the pattern.
+        let mut path = lyon::path::default::Path::builder();
+        path.move_to(t::math::point(0.0, 0.0));
+        path.line_to(t::math::point(0.0, 0.0));
+        path.line_to(t::math::point(5.0, 0.0));
+        let path = path.build();
+        let dashes = dash_polylines(&path, 0.1, &[5.0, 0.0], 0.0);
+        assert!(dashes
+            .iter()
+            .flat_map(|dash| dash.iter())
+            .all(|p| p.x.is_finite() && p.y.is_finite()));
+
+        let mut mb = MeshBuilder::new();
+        let _ = mb.dashes(&[1.0, -1.0], 0.0);
+        assert!(mb.dashes.is_empty());
+        let _ = mb.dashes(&[2.0], 0.0);
+        assert_eq!(mb.dashes, vec![2.0, 2.0]);
+        let _ = mb.line(&[Point2::new(0.0, 0.0), Point2::new(9.0, 0.0)], 1.0);
+        // Three dashes of two triangles each.
+        assert_eq!(mb.indices().len(), 3 * 6);
+        let _ = mb.clear();
+        let _ = mb.circle(DrawMode::Line(1.0), Point2::new(0.0, 0.0), 10.0, 0.1);
+        assert!(!mb.indices().is_empty());
+    }
+}
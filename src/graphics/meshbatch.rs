@@ -0,0 +1,167 @@
+//! A `MeshBatch` is a way to efficiently draw a large number of
+//! copies of the same `Mesh`, each with its own `DrawParam`.  It's
+//! useful for particles made of shapes, vector-drawn asteroid fields,
+//! and other such things that a `SpriteBatch` would need an image for.
+//!
+//! Like `SpriteBatch`, it uses instancing: the parameters of every
+//! copy are put in the instance buffer and the mesh is drawn once for
+//! all of them.
+
+use super::mesh::update_dynamic_buffer;
+use super::shader::BlendMode;
+use context::Context;
+use error;
+use gfx;
+use graphics::{self, DrawTransform};
+use GameResult;
+
+/// A `MeshBatch` draws a number of copies of the same mesh, using a single draw call.
+///
+/// Each copy is placed, scaled, rotated and colored by its own
+/// `DrawParam`, which is applied to the mesh the same way as when
+/// drawing it with `graphics::draw()`.  The batch as a whole is then
+/// drawn with the `DrawParam` given to `graphics::draw()`, whose color
+/// is ignored.
+#[derive(Debug, Clone)]
+pub struct MeshBatch {
+    mesh: graphics::Mesh,
+    instances: Vec<graphics::DrawParam>,
+    blend_mode: Option<BlendMode>,
+}
+
+/// An index of a particular instance in a `MeshBatch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshIdx(usize);
+
+impl MeshBatch {
+    /// Creates a new `MeshBatch`, drawing copies of the given mesh.
+    pub fn new(mesh: graphics::Mesh) -> Self {
+        Self {
+            mesh,
+            instances: vec![],
+            blend_mode: None,
+        }
+    }
+
+    /// Adds a new copy of the mesh to the batch.
+    ///
+    /// Returns a handle with which to modify the copy using `set()`.
+    pub fn add<P>(&mut self, param: P) -> MeshIdx
+    where
+        P: Into<graphics::DrawParam>,
+    {
+        self.instances.push(param.into());
+        MeshIdx(self.instances.len() - 1)
+    }
+
+    /// Alters a copy of the mesh in the batch to use the given draw params.
+    pub fn set<P>(&mut self, handle: MeshIdx, param: P) -> GameResult
+    where
+        P: Into<graphics::DrawParam>,
+    {
+        if handle.0 < self.instances.len() {
+            self.instances[handle.0] = param.into();
+            Ok(())
+        } else {
+            Err(error::GameError::RenderError(String::from(
+                "Provided index is out of bounds.",
+            )))
+        }
+    }
+
+    /// Returns the draw params of a copy of the mesh in the batch, if
+    /// the handle is in bounds.
+    pub fn get(&self, handle: MeshIdx) -> Option<graphics::DrawParam> {
+        self.instances.get(handle.0).cloned()
+    }
+
+    /// Returns the number of copies of the mesh in the batch.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns whether the batch has no copies of the mesh in it.
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Sends the parameters of all the copies in the batch to the
+    /// instance buffer.
+    fn flush(&self, ctx: &mut Context) -> GameResult {
+        let srgb = ctx.gfx_context.is_srgb();
+        let instances = self.instances
+            .iter()
+            .map(|param| DrawTransform::from(*param).to_instance_properties(srgb))
+            .collect::<Vec<_>>();
+
+        let gfx = &mut ctx.gfx_context;
+        update_dynamic_buffer(
+            &mut *gfx.factory,
+            &mut gfx.encoder,
+            &mut gfx.data.rect_instance_properties,
+            gfx::buffer::Role::Vertex,
+            &instances,
+        )
+    }
+
+    /// Removes all copies of the mesh from the batch.  Handles given
+    /// out before this are no longer valid.
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Unwraps and returns the contained `Mesh`
+    pub fn into_inner(self) -> graphics::Mesh {
+        self.mesh
+    }
+
+    /// Replaces the contained `Mesh`, returning the old one.
+    pub fn set_mesh(&mut self, mesh: graphics::Mesh) -> graphics::Mesh {
+        use std::mem;
+        mem::replace(&mut self.mesh, mesh)
+    }
+}
+
+impl graphics::Drawable for MeshBatch {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        if self.instances.is_empty() {
+            return Ok(());
+        }
+        let param = param.into();
+        ctx.gfx_context.flush_batch()?;
+        self.flush(ctx)?;
+        let previous_mode = {
+            let gfx = &mut ctx.gfx_context;
+            let curr_transform = gfx.get_transform();
+            gfx.push_transform(param.matrix * curr_transform);
+            gfx.calculate_transform_matrix();
+            gfx.update_globals()?;
+            match self.blend_mode {
+                Some(mode) if gfx.get_blend_mode() != mode => {
+                    let current_mode = gfx.get_blend_mode();
+                    gfx.set_blend_mode(mode)?;
+                    Some(current_mode)
+                }
+                _ => None,
+            }
+        };
+        let result = self.mesh.draw_instances(ctx, self.instances.len() as u32);
+        let gfx = &mut ctx.gfx_context;
+        if let Some(mode) = previous_mode {
+            gfx.set_blend_mode(mode)?;
+        }
+        gfx.pop_transform();
+        gfx.calculate_transform_matrix();
+        gfx.update_globals()?;
+        result
+    }
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.blend_mode = mode;
+    }
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.blend_mode
+    }
+}
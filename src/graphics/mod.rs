@@ -12,6 +12,7 @@ use std::fmt;
 use std::u16;
 
 use gfx;
+use gfx::memory::Typed;
 use gfx::texture;
 use gfx::Device;
 use gfx::Factory;
@@ -22,28 +23,43 @@ use conf;
 use conf::WindowMode;
 use context::Context;
 use context::DebugId;
+use timer;
 use GameError;
 use GameResult;
 
+mod camera;
 mod canvas;
 mod context;
 mod drawparam;
+mod glsl;
 mod image;
 mod mesh;
 mod shader;
+mod stencil;
 mod text;
 mod types;
 use mint;
 use nalgebra as na;
 
+pub mod animation;
+pub mod atlas;
+pub mod debug;
+pub mod meshbatch;
+pub mod nineslice;
+pub mod particles;
+pub mod postprocess;
 pub mod spritebatch;
+pub mod svg;
+pub mod tilemap;
 
+pub use self::camera::*;
 pub use self::canvas::*;
 pub(crate) use self::context::*;
 pub use self::drawparam::*;
 pub use self::image::*;
 pub use self::mesh::*;
 pub use self::shader::*;
+pub use self::stencil::*;
 pub use self::text::*;
 pub use self::types::*;
 
@@ -229,28 +245,35 @@ const QUAD_VERTS: [Vertex; 4] = [
     Vertex {
         pos: [0.0, 0.0],
         uv: [0.0, 0.0],
+        color: [1.0, 1.0, 1.0, 1.0],
     },
     Vertex {
         pos: [1.0, 0.0],
         uv: [1.0, 0.0],
+        color: [1.0, 1.0, 1.0, 1.0],
     },
     Vertex {
         pos: [1.0, 1.0],
         uv: [1.0, 1.0],
+        color: [1.0, 1.0, 1.0, 1.0],
     },
     Vertex {
         pos: [0.0, 1.0],
         uv: [0.0, 1.0],
+        color: [1.0, 1.0, 1.0, 1.0],
     },
 ];
 
 const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
 
 gfx_defines!{
-    /// Internal structure containing vertex data.
+    /// Structure containing vertex data: a position, texture
+    /// coordinates and a color that is multiplied with the texture and
+    /// the color of the `DrawParam`.
     vertex Vertex {
         pos: [f32; 2] = "a_Pos",
         uv: [f32; 2] = "a_Uv",
+        color: [f32; 4] = "a_VertColor",
     }
 
     /// Internal structure containing values that are different for each
@@ -280,6 +303,13 @@ gfx_defines!{
         tex: gfx::TextureSampler<[f32; 4]> = "t_Texture",
         globals: gfx::ConstantBuffer<Globals> = "Globals",
         rect_instance_properties: gfx::InstanceBuffer<InstanceProperties> = (),
+        scissor: gfx::Scissor = (),
+        // Overwritten in `shader::create_shader()` just like `out`.
+        stencil: gfx::StencilTarget<gfx::format::DepthStencil> =
+          gfx::state::Stencil::new(
+              gfx::state::Comparison::Always, 0,
+              (gfx::state::StencilOp::Keep, gfx::state::StencilOp::Keep, gfx::state::StencilOp::Keep)
+          ),
         // The default values here are overwritten by the
         // pipeline init values in `shader::create_shader()`.
         out: gfx::RawRenderTarget =
@@ -359,9 +389,13 @@ impl From<gfx::buffer::CreationError> for GameError {
 // **********************************************************************
 
 /// Clear the screen to the background color.
+/// Also resets its stencil buffer to 0.
 /// TODO: Into<Color> ?
 pub fn clear(ctx: &mut Context, color: Color) {
     let gfx = &mut ctx.gfx_context;
+    if let Err(e) = gfx.flush_batch() {
+        warn!("Could not draw queued sprites before clearing: {:?}", e);
+    }
     // SRGB BUGGO: Only convert when drawing on srgb surface?
     // I actually can't make it make any difference; fiddle more.
     let linear_color: types::LinearColor = color.into();
@@ -374,6 +408,7 @@ pub fn clear(ctx: &mut Context, color: Color) {
     let typed_render_target: gfx::handle::RenderTargetView<_, ColorFormat> =
         gfx::memory::Typed::new(gfx.data.out.clone());
     gfx.encoder.clear(&typed_render_target, linear_color.into());
+    gfx.encoder.clear_stencil(&gfx.data.stencil.0, 0);
 }
 
 /// Draws the given `Drawable` object to the screen by calling its
@@ -390,10 +425,16 @@ where
 /// Tells the graphics system to actually put everything on the screen.
 /// Call this at the end of your `EventHandler`'s `draw()` method.
 ///
-/// Unsets any active canvas.
+/// Draws any `graphics::debug` gizmos that weren't drawn this frame,
+/// and unsets any active canvas.
 pub fn present(ctx: &mut Context) -> GameResult<()> {
+    let dt = timer::duration_to_f64(timer::get_delta(ctx)) as f32;
+    debug::end_frame(ctx, dt)?;
     let gfx = &mut ctx.gfx_context;
+    gfx.flush_batch()?;
     gfx.data.out = gfx.screen_render_target.clone();
+    gfx.data.stencil.0 = Typed::new(gfx.depth_view.clone());
+    gfx.update_scissor();
     // We might want to give the user more control over when the
     // encoder gets flushed eventually, if we want them to be able
     // to do their own gfx drawing.  HOWEVER, the whole pipeline type
@@ -401,6 +442,9 @@ pub fn present(ctx: &mut Context) -> GameResult<()> {
     gfx.encoder.flush(&mut *gfx.device);
     gfx.window.swap_buffers()?;
     gfx.device.cleanup();
+    if gfx.hot_reload_shaders {
+        shader::reload_changed_shaders(ctx);
+    }
     Ok(())
 }
 
@@ -411,6 +455,9 @@ pub fn screenshot(ctx: &mut Context) -> GameResult<Image> {
     let debug_id = DebugId::get(ctx);
 
     let gfx = &mut ctx.gfx_context;
+    // Anything drawn so far has to reach the surface before we copy it.
+    gfx.flush_batch()?;
+    gfx.encoder.flush(&mut *gfx.device);
     let (w, h, _depth, aa) = gfx.data.out.get_dimensions();
     let surface_format = gfx.color_format();
     let gfx::format::Format(surface_type, channel_type) = surface_format;
@@ -476,8 +523,10 @@ pub fn screenshot(ctx: &mut Context) -> GameResult<Image> {
 
 /// Draw a circle.
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Consecutive shapes drawn with these functions are collected and drawn
+/// together, out of buffers that are reused from frame to frame, so
+/// drawing many of them is cheap.  Still, shapes that don't change
+/// are best put in a `Mesh`.
 ///
 /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
 pub fn circle<P>(
@@ -491,14 +540,14 @@ pub fn circle<P>(
 where
     P: Into<mint::Point2<f32>>,
 {
-    let m = Mesh::new_circle(ctx, mode, point, radius, tolerance)?;
-    m.draw(ctx, DrawParam::new().color(color))
+    ctx.gfx_context.queue_shapes(color, |mb| {
+        let _ = mb.circle(mode, point, radius, tolerance);
+    })
 }
 
 /// Draw an ellipse.
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Like `circle()`, this is batched with other shapes.
 ///
 /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
 pub fn ellipse<P>(
@@ -513,54 +562,57 @@ pub fn ellipse<P>(
 where
     P: Into<mint::Point2<f32>>,
 {
-    let m = Mesh::new_ellipse(ctx, mode, point, radius1, radius2, tolerance)?;
-    m.draw(ctx, DrawParam::new().color(color))
+    ctx.gfx_context.queue_shapes(color, |mb| {
+        let _ = mb.ellipse(mode, point, radius1, radius2, tolerance);
+    })
 }
 
 /// Draws a line of one or more connected segments.
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Like `circle()`, this is batched with other shapes.
 pub fn line<P>(ctx: &mut Context, color: Color, points: &[P], width: f32) -> GameResult
 where
     P: Into<mint::Point2<f32>> + Clone,
 {
-    let m = Mesh::new_line(ctx, points, width)?;
-    m.draw(ctx, DrawParam::new().color(color))
+    ctx.gfx_context.queue_shapes(color, |mb| {
+        let _ = mb.polyline(DrawMode::Line(width), points);
+    })
 }
 
 /// Draws points (as rectangles)
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Like `circle()`, this is batched with other shapes, so all the
+/// points are drawn at once.
 pub fn points<P>(ctx: &mut Context, color: Color, points: &[P], point_size: f32) -> GameResult
 where
     P: Into<mint::Point2<f32>> + Clone,
 {
-    let points = points.into_iter().cloned().map(P::into);
-    for p in points {
+    // One at a time, so the batch can be flushed in between if there
+    // are too many points for one batch.
+    for p in points.iter().cloned().map(P::into) {
         let r = Rect::new(p.x, p.y, point_size, point_size);
-        rectangle(ctx, color, DrawMode::Fill, r)?;
+        ctx.gfx_context.queue_shapes(color, |mb| {
+            let _ = mb.rectangle(DrawMode::Fill, r);
+        })?;
     }
     Ok(())
 }
 
 /// Draws a closed polygon
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Like `circle()`, this is batched with other shapes.
 pub fn polygon<P>(ctx: &mut Context, color: Color, mode: DrawMode, vertices: &[P]) -> GameResult
 where
     P: Into<mint::Point2<f32>> + Clone,
 {
-    let m = Mesh::new_polygon(ctx, mode, vertices)?;
-    m.draw(ctx, DrawParam::new().color(color))
+    ctx.gfx_context.queue_shapes(color, |mb| {
+        let _ = mb.polygon(mode, vertices);
+    })
 }
 
 /// Draws a rectangle.
 ///
-/// Allocates a new `Mesh`, draws it, and throws it away, so if you are drawing many of them
-/// you should create the `Mesh` yourself.
+/// Like `circle()`, this is batched with other shapes.
 pub fn rectangle(ctx: &mut Context, color: Color, mode: DrawMode, rect: Rect) -> GameResult {
     let x1 = rect.x;
     let x2 = rect.x + rect.w;
@@ -633,7 +685,65 @@ pub fn set_screen_coordinates(context: &mut Context, rect: Rect) -> GameResult {
     let gfx = &mut context.gfx_context;
     gfx.set_projection_rect(rect);
     gfx.calculate_transform_matrix();
-    gfx.update_globals()
+    gfx.update_globals()?;
+    gfx.update_scissor();
+    Ok(())
+}
+
+/// Restricts all drawing to the given rectangle, or allows drawing
+/// everywhere again if it is `None`.  Useful for scrolling panels
+/// and split-screen views.
+///
+/// The rectangle is in screen coordinates, as set by
+/// `set_screen_coordinates()`, and is unaffected by the current
+/// transform.  It applies to the screen as well as to canvases, and
+/// stays in effect when switching between them with `set_canvas()`.
+///
+/// `clear()` and text drawn with `draw_queued_text()` don't support
+/// scissoring, and may get clipped by whichever rectangle was last used.
+pub fn set_scissor(ctx: &mut Context, rect: Option<Rect>) {
+    ctx.gfx_context.set_scissor(rect);
+}
+
+/// Returns the rectangle drawing is currently restricted to, if any.
+pub fn get_scissor(ctx: &Context) -> Option<Rect> {
+    ctx.gfx_context.scissor
+}
+
+/// Saves the current scissor rectangle on a stack and restricts
+/// drawing to the part of it that lies within `rect`, which is
+/// in screen coordinates.  This lets nested panels clip their contents
+/// to themselves without drawing outside of their parents.
+///
+/// Call `pop_scissor()` to go back to the saved rectangle.
+pub fn push_scissor(ctx: &mut Context, rect: Rect) {
+    let gfx = &mut ctx.gfx_context;
+    let current = gfx.scissor;
+    gfx.scissor_stack.push(current);
+    let clipped = match current {
+        Some(outer) => intersect_rects(outer, rect),
+        None => rect,
+    };
+    gfx.set_scissor(Some(clipped));
+}
+
+/// Restores the scissor rectangle saved by the matching `push_scissor()`.
+/// Does nothing if the stack is empty.
+pub fn pop_scissor(ctx: &mut Context) {
+    let gfx = &mut ctx.gfx_context;
+    if let Some(rect) = gfx.scissor_stack.pop() {
+        gfx.set_scissor(rect);
+    }
+}
+
+/// Returns the overlap of two rectangles, which has a size of zero
+/// if they don't overlap.
+fn intersect_rects(a: Rect, b: Rect) -> Rect {
+    let left = a.left().min(a.right()).max(b.left().min(b.right()));
+    let right = a.left().max(a.right()).min(b.left().max(b.right()));
+    let top = a.top().min(a.bottom()).max(b.top().min(b.bottom()));
+    let bottom = a.top().max(a.bottom()).min(b.top().max(b.bottom()));
+    Rect::new(left, top, (right - left).max(0.0), (bottom - top).max(0.0))
 }
 
 /// Sets the raw projection matrix to the given homogeneous
@@ -864,6 +974,7 @@ pub fn get_encoder(
     context: &mut Context,
 ) -> &mut gfx::Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer> {
     let gfx = &mut context.gfx_context;
+    flush_batch_for_raw_access(gfx);
     &mut gfx.encoder
 }
 
@@ -872,7 +983,7 @@ pub fn get_depth_view(
     context: &mut Context,
 ) -> gfx::handle::RawDepthStencilView<gfx_device_gl::Resources> {
     let gfx = &mut context.gfx_context;
-    gfx.depth_view.clone()
+    gfx.data.stencil.0.raw().clone()
 }
 
 /// Returns the gfx-rs color target object for ggez's rendering context.
@@ -902,14 +1013,23 @@ pub fn get_gfx_objects(
     gfx::handle::RawRenderTargetView<<GlBackendSpec as BackendSpec>::Resources>,
 ) {
     let gfx = &mut context.gfx_context;
+    flush_batch_for_raw_access(gfx);
     let f = &mut gfx.factory;
     let d = gfx.device.as_mut();
     let e = &mut gfx.encoder;
-    let dv = gfx.depth_view.clone();
+    let dv = gfx.data.stencil.0.raw().clone();
     let cv = gfx.data.out.clone();
     (f, d, e, dv, cv)
 }
 
+/// Draws any automatically batched sprites before handing the encoder
+/// out, so that raw gfx-rs commands land after them.
+fn flush_batch_for_raw_access(gfx: &mut GraphicsContext) {
+    if let Err(e) = gfx.flush_batch() {
+        warn!("Could not draw queued sprites: {:?}", e);
+    }
+}
+
 /// All types that can be drawn on the screen implement the `Drawable` trait.
 pub trait Drawable {
     /// Draws the drawable onto the rendering target.
@@ -930,4 +1050,26 @@ pub trait Drawable {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scissor_rects() {
+        let screen = Rect::new(0.0, 0.0, 800.0, 600.0);
+        // A hidpi factor of 2 doubles the pixels, which OpenGL counts
+        // from the bottom.
+        let pixels = scissor_pixels(Rect::new(100.0, 50.0, 200.0, 100.0), screen, 1600, 1200);
+        assert_eq!(pixels, gfx::Rect { x: 200, y: 900, w: 400, h: 200 });
+
+        // Flipped screen coordinates, and clipping to the target.  The
+        // rectangle covers the top 100 rows of the target.
+        let flipped = Rect::new(0.0, 600.0, 800.0, -600.0);
+        let pixels = scissor_pixels(Rect::new(-100.0, 500.0, 200.0, 200.0), flipped, 800, 600);
+        assert_eq!(pixels, gfx::Rect { x: 0, y: 500, w: 100, h: 100 });
+
+        let inner = intersect_rects(screen, Rect::new(700.0, -50.0, 200.0, 100.0));
+        assert_eq!(inner, Rect::new(700.0, 0.0, 100.0, 50.0));
+        let outside = intersect_rects(screen, Rect::new(900.0, 0.0, 10.0, 10.0));
+        assert_eq!(outside.w, 0.0);
+    }
+}
@@ -0,0 +1,303 @@
+//! A `NineSlice` draws an image at any size without stretching its
+//! corners, for things like the backgrounds of UI panels and buttons.
+
+use graphics::spritebatch::SpriteBatch;
+use graphics::*;
+
+/// How the edges or the center of a `NineSlice` fill the space between
+/// its corners.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SliceMode {
+    /// Stretch the part of the image to fit.
+    Stretch,
+    /// Repeat the part of the image at its own size, cutting the last
+    /// copy short.
+    Tile,
+}
+
+/// An image split into nine parts by border insets, which can be drawn
+/// at any size without distorting its corners, such as the background
+/// of a UI panel or button.
+///
+/// The four corners are always drawn at their own size, the edges
+/// between them are stretched or tiled along their length, and the
+/// center is stretched or tiled both ways to fill the rest.  When the
+/// size is smaller than the two insets together, the corners shrink to
+/// fit.
+///
+/// ```rust,ignore
+/// let mut panel = NineSlice::new(image, 8.0, 8.0, 8.0, 8.0);
+/// panel.set_size(200.0, 120.0);
+/// graphics::draw(ctx, &panel, DrawParam::new().dest(Point2::new(20.0, 20.0)))?;
+/// ```
+///
+/// The parts are drawn with a single `SpriteBatch`, which is only
+/// rebuilt when the size or modes change.
+#[derive(Debug, Clone)]
+pub struct NineSlice {
+    batch: SpriteBatch,
+    image_size: (f32, f32),
+    insets: [f32; 4],
+    width: f32,
+    height: f32,
+    edge_mode: SliceMode,
+    center_mode: SliceMode,
+}
+
+impl NineSlice {
+    /// Creates a new `NineSlice` from an image and the sizes of its
+    /// borders in pixels, at the size of the image, with stretched
+    /// edges and center.
+    pub fn new(image: Image, left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        let (width, height) = (f32::from(image.width()), f32::from(image.height()));
+        let mut slice = Self {
+            batch: SpriteBatch::new(image),
+            image_size: (width, height),
+            insets: [left, top, right, bottom],
+            width,
+            height,
+            edge_mode: SliceMode::Stretch,
+            center_mode: SliceMode::Stretch,
+        };
+        slice.rebuild();
+        slice
+    }
+
+    /// Gets the size the image is drawn at, in pixels.
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    /// Sets the size the image is drawn at, in pixels.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        self.width = width.max(0.0);
+        self.height = height.max(0.0);
+        self.rebuild();
+    }
+
+    /// Gets how the edges and the center fill their space.
+    pub fn modes(&self) -> (SliceMode, SliceMode) {
+        (self.edge_mode, self.center_mode)
+    }
+
+    /// Sets how the edges and the center fill their space.
+    pub fn set_modes(&mut self, edges: SliceMode, center: SliceMode) {
+        self.edge_mode = edges;
+        self.center_mode = center;
+        self.rebuild();
+    }
+
+    /// Returns the image the `NineSlice` is drawn with.
+    pub fn into_inner(self) -> Image {
+        self.batch.into_inner()
+    }
+
+    /// Get the filter mode for the image.
+    pub fn get_filter(&self) -> FilterMode {
+        self.batch.get_filter()
+    }
+
+    /// Set the filter mode for the image.
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        self.batch.set_filter(mode);
+    }
+
+    fn rebuild(&mut self) {
+        let (image_width, image_height) = self.image_size;
+        self.batch.clear();
+        let slices = slices(
+            self.image_size,
+            self.insets,
+            (self.width, self.height),
+            self.edge_mode,
+            self.center_mode,
+        );
+        for (src, dest) in slices {
+            if src.w <= 0.0 || src.h <= 0.0 || dest.w <= 0.0 || dest.h <= 0.0 {
+                continue;
+            }
+            let param = DrawParam::new()
+                .src(Rect::new(
+                    src.x / image_width,
+                    src.y / image_height,
+                    src.w / image_width,
+                    src.h / image_height,
+                ))
+                .dest(dest.point())
+                .scale(Vector2::new(dest.w / src.w, dest.h / src.h));
+            let _ = self.batch.add(param);
+        }
+    }
+}
+
+impl Drawable for NineSlice {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        self.batch.draw(ctx, param)
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.batch.set_blend_mode(mode);
+    }
+
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.batch.get_blend_mode()
+    }
+}
+
+/// Splits one axis into pieces: the source length each piece shows,
+/// and where it goes.  Stretching makes a single piece, tiling repeats
+/// the source over the destination.
+fn spans(source: f32, start: f32, length: f32, mode: SliceMode) -> Vec<(f32, f32, f32)> {
+    if source <= 0.0 || length <= 0.0 {
+        return vec![];
+    }
+    match mode {
+        SliceMode::Stretch => vec![(source, start, length)],
+        SliceMode::Tile => {
+            let mut spans = vec![];
+            let mut offset = 0.0;
+            while offset < length {
+                let piece = source.min(length - offset);
+                spans.push((piece, start + offset, piece));
+                offset += source;
+            }
+            spans
+        }
+    }
+}
+
+/// Works out the source and destination rectangles, both in pixels, of
+/// all the pieces of a `NineSlice`.
+fn slices(
+    (image_width, image_height): (f32, f32),
+    [left, top, right, bottom]: [f32; 4],
+    (width, height): (f32, f32),
+    edge_mode: SliceMode,
+    center_mode: SliceMode,
+) -> Vec<(Rect, Rect)> {
+    // Shrink the corners if they don't fit.
+    let shrink_x = (width / (left + right)).min(1.0);
+    let shrink_y = (height / (top + bottom)).min(1.0);
+    let (dest_left, dest_right) = (left * shrink_x, right * shrink_x);
+    let (dest_top, dest_bottom) = (top * shrink_y, bottom * shrink_y);
+
+    // The source start, source length, destination start, destination
+    // length and edge/center mode of each column and row.
+    let columns = [
+        (0.0, left, 0.0, dest_left, None),
+        (
+            left,
+            image_width - left - right,
+            dest_left,
+            width - dest_left - dest_right,
+            Some(edge_mode),
+        ),
+        (image_width - right, right, width - dest_right, dest_right, None),
+    ];
+    let rows = [
+        (0.0, top, 0.0, dest_top, None),
+        (
+            top,
+            image_height - top - bottom,
+            dest_top,
+            height - dest_top - dest_bottom,
+            Some(edge_mode),
+        ),
+        (image_height - bottom, bottom, height - dest_bottom, dest_bottom, None),
+    ];
+
+    let mut slices = vec![];
+    for (row, &(src_y, src_h, dest_y, dest_h, row_mode)) in rows.iter().enumerate() {
+        for (column, &(src_x, src_w, dest_x, dest_w, column_mode)) in columns.iter().enumerate() {
+            // Corners are stretched to their shrunken size if need be,
+            // edges follow the edge mode along their length, and the
+            // center follows the center mode both ways.
+            let center = row == 1 && column == 1;
+            let x_mode = match (center, row_mode, column_mode) {
+                (true, _, _) => center_mode,
+                (false, None, Some(mode)) => mode,
+                _ => SliceMode::Stretch,
+            };
+            let y_mode = match (center, row_mode, column_mode) {
+                (true, _, _) => center_mode,
+                (false, Some(mode), None) => mode,
+                _ => SliceMode::Stretch,
+            };
+            for &(piece_h, y, h) in &spans(src_h, dest_y, dest_h, y_mode) {
+                for &(piece_w, x, w) in &spans(src_w, dest_x, dest_w, x_mode) {
+                    slices.push((
+                        Rect::new(src_x, src_y, piece_w, piece_h),
+                        Rect::new(x, y, w, h),
+                    ));
+                }
+            }
+        }
+    }
+    slices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiled_spans() {
+        assert_eq!(
+            spans(4.0, 10.0, 10.0, SliceMode::Tile),
+            vec![(4.0, 10.0, 4.0), (4.0, 14.0, 4.0), (2.0, 18.0, 2.0)]
+        );
+        assert_eq!(
+            spans(4.0, 10.0, 10.0, SliceMode::Stretch),
+            vec![(4.0, 10.0, 10.0)]
+        );
+        assert!(spans(0.0, 0.0, 10.0, SliceMode::Tile).is_empty());
+    }
+
+    #[test]
+    fn nine_slices() {
+        // A 12x12 image with 4 pixel borders, drawn at 20x8.
+        let slices = slices(
+            (12.0, 12.0),
+            [4.0, 4.0, 4.0, 4.0],
+            (20.0, 8.0),
+            SliceMode::Tile,
+            SliceMode::Stretch,
+        );
+        // Corners keep their size.
+        assert!(slices.contains(&(
+            Rect::new(0.0, 0.0, 4.0, 4.0),
+            Rect::new(0.0, 0.0, 4.0, 4.0)
+        )));
+        assert!(slices.contains(&(
+            Rect::new(8.0, 8.0, 4.0, 4.0),
+            Rect::new(16.0, 4.0, 4.0, 4.0)
+        )));
+        // The top edge is tiled three times across the 12 pixels
+        // between the corners.
+        let top: Vec<_> = slices
+            .iter()
+            .filter(|&&(src, _)| src.y == 0.0 && src.x == 4.0)
+            .map(|&(_, dest)| dest.x)
+            .collect();
+        assert_eq!(top, vec![4.0, 8.0, 12.0]);
+        // The rows in between have no height left, so only corners and
+        // the top and bottom edges remain.
+        assert_eq!(slices.len(), 4 + 2 * 3);
+
+        // Too small for the borders: the corners shrink.
+        let slices = super::slices(
+            (12.0, 12.0),
+            [4.0, 4.0, 4.0, 4.0],
+            (4.0, 8.0),
+            SliceMode::Stretch,
+            SliceMode::Stretch,
+        );
+        assert!(slices.contains(&(
+            Rect::new(8.0, 0.0, 4.0, 4.0),
+            Rect::new(2.0, 0.0, 2.0, 4.0)
+        )));
+    }
+}
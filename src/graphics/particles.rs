@@ -0,0 +1,622 @@
+//! Particle systems, for fire, smoke, sparks, explosions and other
+//! effects made of many small sprites.
+//!
+//! A `ParticleSystem` emits particles from an emitter shape around its
+//! position, either continuously at a given rate or in bursts.  Every
+//! particle lives for a random lifetime, moves with its own velocity
+//! under a shared acceleration, and changes color, size and rotation
+//! along curves over its lifetime.  Particles are drawn through a
+//! `SpriteBatch`, optionally with additive blending.
+//!
+//! Everything about an emitter is set in an `EmitterConfig`, which can
+//! be loaded from a TOML or JSON file with `EmitterConfig::load()`.  A
+//! TOML config looks like this, with anything that is left out taking
+//! its default value:
+//!
+//! ```toml
+//! rate = 50.0
+//! lifetime = [0.5, 1.5]
+//! speed = [40.0, 80.0]
+//! direction = -1.5708
+//! spread = 0.5
+//! acceleration = [0.0, 30.0]
+//! colors = [[1.0, 0.9, 0.2, 1.0], [1.0, 0.2, 0.0, 0.0]]
+//! sizes = [0.5, 1.0]
+//! additive = true
+//!
+//! [shape]
+//! type = "circle"
+//! radius = 8.0
+//! ```
+
+use std::f32;
+use std::io::Read;
+use std::path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mint;
+use serde_json;
+use toml;
+
+use context::Context;
+use filesystem;
+use graphics::spritebatch::SpriteBatch;
+use graphics::{BlendMode, Color, DrawParam, DrawTransform, Drawable, Image, Point2, Vector2};
+use timer;
+use GameResult;
+
+/// The area particles are emitted from, centered on the position of the
+/// `ParticleSystem`.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, SmartDefault)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum EmitterShape {
+    /// Particles all start at the emitter's position.
+    #[default]
+    Point,
+    /// Particles start along a line through the emitter's position,
+    /// from `-(x, y) / 2` to `(x, y) / 2` around it.
+    Line {
+        /// The horizontal extent of the line.
+        x: f32,
+        /// The vertical extent of the line.
+        y: f32,
+    },
+    /// Particles start inside a circle, or on its edge only.
+    Circle {
+        /// The radius of the circle.
+        radius: f32,
+        /// Whether particles start on the edge of the circle rather
+        /// than anywhere inside it.
+        #[serde(default)]
+        edge: bool,
+    },
+    /// Particles start inside a rectangle, or on its edge only.
+    Rect {
+        /// The width of the rectangle.
+        width: f32,
+        /// The height of the rectangle.
+        height: f32,
+        /// Whether particles start on the edge of the rectangle rather
+        /// than anywhere inside it.
+        #[serde(default)]
+        edge: bool,
+    },
+}
+
+/// The settings of a particle emitter.
+///
+/// Values given as `[min, max]` pairs are picked at random in that
+/// range for every particle.  The curves, `colors`, `sizes` and
+/// `rotations`, are lists of values spread evenly over a particle's
+/// lifetime and blended between; a single value stays the same the
+/// whole time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SmartDefault)]
+#[serde(default)]
+pub struct EmitterConfig {
+    /// How many particles are emitted per second while emitting.
+    #[default = r#"10.0"#]
+    pub rate: f32,
+    /// How many particles are emitted at once when the system starts.
+    pub burst: u32,
+    /// How long the system keeps emitting after it starts, in seconds,
+    /// or forever if not given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f32>,
+    /// The most particles that can be alive at once; no more are
+    /// emitted while there are this many.
+    #[default = r#"1000"#]
+    pub max_particles: usize,
+    /// How long particles live, in seconds.
+    #[default = r#"[1.0, 1.0]"#]
+    pub lifetime: [f32; 2],
+    /// How fast particles start moving, in pixels per second.
+    pub speed: [f32; 2],
+    /// The direction particles start moving in, in radians, clockwise
+    /// from the positive X axis.
+    pub direction: f32,
+    /// How far off `direction` particles may start moving, in radians,
+    /// with half of it on either side.
+    pub spread: f32,
+    /// The acceleration of all particles, such as gravity, in pixels
+    /// per second squared.
+    pub acceleration: [f32; 2],
+    /// How quickly particles slow down by themselves; 0 for not at all.
+    pub damping: f32,
+    /// The rotation particles start with, in radians.
+    pub rotation: [f32; 2],
+    /// How fast particles spin, in radians per second.
+    pub spin: [f32; 2],
+    /// The color of particles over their lifetime, as RGBA values
+    /// between 0 and 1.
+    #[default = r#"vec![[1.0, 1.0, 1.0, 1.0]]"#]
+    pub colors: Vec<[f32; 4]>,
+    /// The size of particles over their lifetime, as a scale of the
+    /// particle image.
+    #[default = r#"vec![1.0]"#]
+    pub sizes: Vec<f32>,
+    /// Rotation added to particles over their lifetime, in radians, on
+    /// top of their starting rotation and spin.
+    #[default = r#"vec![0.0]"#]
+    pub rotations: Vec<f32>,
+    /// Whether particles are drawn with `BlendMode::Add`, which makes
+    /// overlapping particles glow.
+    pub additive: bool,
+    /// Where particles are emitted.  Last, since TOML tables have to
+    /// come after plain values.
+    pub shape: EmitterShape,
+}
+
+impl EmitterConfig {
+    /// Loads an emitter config from a TOML file, or a JSON file if its
+    /// extension is `.json`.
+    pub fn load<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let mut s = String::new();
+        let mut file = filesystem::open(ctx, path)?;
+        let _ = file.read_to_string(&mut s)?;
+        if path.extension() == Some("json".as_ref()) {
+            Ok(serde_json::from_str(&s)?)
+        } else {
+            Ok(toml::from_str(&s)?)
+        }
+    }
+}
+
+/// A single live particle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Particle {
+    position: Point2,
+    velocity: Vector2,
+    rotation: f32,
+    spin: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    /// How far through its lifetime the particle is, from 0 to 1.
+    fn progress(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            (self.age / self.lifetime).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A small xorshift random number generator, good enough for
+/// scattering particles.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Mix up small seeds; the state must never be zero.
+        const MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+        let state = seed ^ MIX;
+        Rng(if state == 0 { MIX } else { state })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Returns a number in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, range: [f32; 2]) -> f32 {
+        range[0] + (range[1] - range[0]) * self.next_f32()
+    }
+}
+
+/// The particles of a system and the emitter's state.
+///
+/// Kept apart from the `SpriteBatch` so it can be tested without a
+/// `Context`.
+#[derive(Debug, Clone)]
+struct Simulation {
+    config: EmitterConfig,
+    particles: Vec<Particle>,
+    position: Point2,
+    emitting: bool,
+    /// How long the emitter has been emitting, in seconds.
+    elapsed: f32,
+    /// The fraction of a particle that is due to be emitted.
+    pending: f32,
+    rng: Rng,
+}
+
+impl Simulation {
+    fn new(config: EmitterConfig, seed: u64) -> Self {
+        Self {
+            config,
+            particles: vec![],
+            position: Point2::origin(),
+            emitting: false,
+            elapsed: 0.0,
+            pending: 0.0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn start(&mut self) {
+        self.emitting = true;
+        self.elapsed = 0.0;
+        self.pending = 0.0;
+        let burst = self.config.burst as usize;
+        self.emit(burst);
+    }
+
+    fn emit(&mut self, count: usize) {
+        let room = self.config.max_particles.saturating_sub(self.particles.len());
+        for _ in 0..count.min(room) {
+            let particle = self.spawn();
+            self.particles.push(particle);
+        }
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let rng = &mut self.rng;
+        let offset = match self.config.shape {
+            EmitterShape::Point => Vector2::new(0.0, 0.0),
+            EmitterShape::Line { x, y } => Vector2::new(x, y) * (rng.next_f32() - 0.5),
+            EmitterShape::Circle { radius, edge } => {
+                let angle = rng.next_f32() * 2.0 * f32::consts::PI;
+                // The square root spreads particles evenly over the area.
+                let distance = if edge {
+                    radius
+                } else {
+                    radius * rng.next_f32().sqrt()
+                };
+                Vector2::new(angle.cos(), angle.sin()) * distance
+            }
+            EmitterShape::Rect {
+                width,
+                height,
+                edge: false,
+            } => Vector2::new(
+                width * (rng.next_f32() - 0.5),
+                height * (rng.next_f32() - 0.5),
+            ),
+            EmitterShape::Rect {
+                width,
+                height,
+                edge: true,
+            } => {
+                // Pick a point along the perimeter, then find its side.
+                let mut along = rng.next_f32() * 2.0 * (width + height);
+                let (half_w, half_h) = (width / 2.0, height / 2.0);
+                if along < width {
+                    Vector2::new(along - half_w, -half_h)
+                } else {
+                    along -= width;
+                    if along < height {
+                        Vector2::new(half_w, along - half_h)
+                    } else {
+                        along -= height;
+                        if along < width {
+                            Vector2::new(half_w - along, half_h)
+                        } else {
+                            Vector2::new(-half_w, half_h - (along - width))
+                        }
+                    }
+                }
+            }
+        };
+        let angle = self.config.direction + self.config.spread * (rng.next_f32() - 0.5);
+        let speed = rng.range(self.config.speed);
+        Particle {
+            position: self.position + offset,
+            velocity: Vector2::new(angle.cos(), angle.sin()) * speed,
+            rotation: rng.range(self.config.rotation),
+            spin: rng.range(self.config.spin),
+            age: 0.0,
+            lifetime: rng.range(self.config.lifetime),
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        let acceleration = Vector2::new(self.config.acceleration[0], self.config.acceleration[1]);
+        let damping = 1.0 / (1.0 + self.config.damping * dt);
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity = (particle.velocity + acceleration * dt) * damping;
+            particle.position += particle.velocity * dt;
+            particle.rotation += particle.spin * dt;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        if self.emitting {
+            let mut emit_time = dt;
+            if let Some(duration) = self.config.duration {
+                emit_time = emit_time.min(duration - self.elapsed).max(0.0);
+                if self.elapsed + dt >= duration {
+                    self.emitting = false;
+                }
+            }
+            self.elapsed += dt;
+            self.pending += self.config.rate.max(0.0) * emit_time;
+            let count = self.pending.floor();
+            self.pending -= count;
+            self.emit(count as usize);
+        }
+    }
+
+    fn param(&self, particle: &Particle) -> DrawParam {
+        let t = particle.progress();
+        let color = curve(&self.config.colors, t, [1.0; 4], |a, b, t| {
+            [
+                lerp(a[0], b[0], t),
+                lerp(a[1], b[1], t),
+                lerp(a[2], b[2], t),
+                lerp(a[3], b[3], t),
+            ]
+        });
+        let size = curve(&self.config.sizes, t, 1.0, lerp);
+        let rotation = particle.rotation + curve(&self.config.rotations, t, 0.0, lerp);
+        DrawParam::new()
+            .dest(particle.position)
+            .rotation(rotation)
+            .scale(Vector2::new(size, size))
+            .offset(Point2::new(0.5, 0.5))
+            .color(Color::new(color[0], color[1], color[2], color[3]))
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Samples a curve of evenly spread values at `t` between 0 and 1.
+fn curve<T, F>(values: &[T], t: f32, default: T, lerp: F) -> T
+where
+    T: Copy,
+    F: Fn(T, T, f32) -> T,
+{
+    match values.len() {
+        0 => default,
+        1 => values[0],
+        len => {
+            let position = t.max(0.0).min(1.0) * (len - 1) as f32;
+            let index = (position.floor() as usize).min(len - 2);
+            lerp(values[index], values[index + 1], position - index as f32)
+        }
+    }
+}
+
+/// A particle emitter and the particles it emitted, drawn with a
+/// single image.  See the module documentation for how it behaves.
+///
+/// Particles are emitted and move in the coordinates the system is
+/// drawn in, so moving the system with `set_position()` leaves the
+/// particles it already emitted behind, while the `DrawParam` it is
+/// drawn with moves them all.
+#[derive(Debug, Clone)]
+pub struct ParticleSystem {
+    simulation: Simulation,
+    batch: SpriteBatch,
+}
+
+impl ParticleSystem {
+    /// Creates a new particle system drawing particles with the given
+    /// image, centered on each particle.  It doesn't emit anything
+    /// until `start()` is called.
+    pub fn new(image: Image, config: EmitterConfig) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() ^ u64::from(time.subsec_nanos()))
+            .unwrap_or(0);
+        let mut batch = SpriteBatch::new(image);
+        if config.additive {
+            batch.set_blend_mode(Some(BlendMode::Add));
+        }
+        Self {
+            simulation: Simulation::new(config, seed),
+            batch,
+        }
+    }
+
+    /// Starts emitting particles, first emitting the config's burst, if
+    /// any.  An emitter with a `duration` starts its countdown over.
+    pub fn start(&mut self) {
+        self.simulation.start();
+        self.update_batch();
+    }
+
+    /// Stops emitting particles.  The ones already emitted live on.
+    pub fn stop(&mut self) {
+        self.simulation.emitting = false;
+    }
+
+    /// Returns whether the system is emitting particles.
+    pub fn is_emitting(&self) -> bool {
+        self.simulation.emitting
+    }
+
+    /// Emits the given number of particles at once, whether the system
+    /// is emitting or not.
+    pub fn burst(&mut self, count: usize) {
+        self.simulation.emit(count);
+        self.update_batch();
+    }
+
+    /// Removes all particles.
+    pub fn clear(&mut self) {
+        self.simulation.particles.clear();
+        self.batch.clear();
+    }
+
+    /// Advances the system by the time since the last update, as
+    /// reported by `timer::get_delta()`.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::get_delta(ctx));
+    }
+
+    /// Advances the system by the given amount of time, moving and
+    /// aging particles and emitting new ones.
+    pub fn advance(&mut self, dt: Duration) {
+        self.simulation.advance(timer::duration_to_f64(dt) as f32);
+        self.update_batch();
+    }
+
+    /// Returns the number of live particles.
+    pub fn count(&self) -> usize {
+        self.simulation.particles.len()
+    }
+
+    /// Returns the position new particles are emitted around.
+    pub fn position(&self) -> Point2 {
+        self.simulation.position
+    }
+
+    /// Sets the position new particles are emitted around.
+    pub fn set_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.simulation.position = Point2::from(position.into());
+    }
+
+    /// Returns the emitter's settings.
+    pub fn config(&self) -> &EmitterConfig {
+        &self.simulation.config
+    }
+
+    /// Returns the emitter's settings for changing.  Changes apply to
+    /// particles emitted and drawn from then on, except for `additive`,
+    /// for which use `set_blend_mode()`.
+    pub fn config_mut(&mut self) -> &mut EmitterConfig {
+        &mut self.simulation.config
+    }
+
+    /// Returns the image particles are drawn with.
+    pub fn into_inner(self) -> Image {
+        self.batch.into_inner()
+    }
+
+    fn update_batch(&mut self) {
+        self.batch.clear();
+        for particle in &self.simulation.particles {
+            let _ = self.batch.add(self.simulation.param(particle));
+        }
+    }
+}
+
+impl Drawable for ParticleSystem {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        if self.simulation.particles.is_empty() {
+            return Ok(());
+        }
+        self.batch.draw(ctx, param)
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.batch.set_blend_mode(mode);
+    }
+
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.batch.get_blend_mode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs() {
+        let config: EmitterConfig = toml::from_str(
+            r#"
+            rate = 5.0
+            lifetime = [0.5, 2.0]
+            colors = [[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0]]
+            additive = true
+
+            [shape]
+            type = "circle"
+            radius = 4.0
+            "#,
+        ).unwrap();
+        assert_eq!(config.rate, 5.0);
+        assert_eq!(config.lifetime, [0.5, 2.0]);
+        assert_eq!(config.sizes, vec![1.0]);
+        assert_eq!(config.max_particles, 1000);
+        assert_eq!(
+            config.shape,
+            EmitterShape::Circle {
+                radius: 4.0,
+                edge: false,
+            }
+        );
+        assert!(config.additive);
+
+        let json = r#"{"burst": 3, "shape": {"type": "rect", "width": 2, "height": 1}}"#;
+        let config: EmitterConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.burst, 3);
+        assert_eq!(config.duration, None);
+    }
+
+    #[test]
+    fn curves() {
+        assert_eq!(curve(&[], 0.5, 7.0, lerp), 7.0);
+        assert_eq!(curve(&[2.0], 0.5, 7.0, lerp), 2.0);
+        assert_eq!(curve(&[0.0, 10.0, 0.0], 0.25, 7.0, lerp), 5.0);
+        assert_eq!(curve(&[0.0, 10.0, 0.0], 1.0, 7.0, lerp), 0.0);
+        assert_eq!(curve(&[0.0, 10.0], 2.0, 7.0, lerp), 10.0);
+    }
+
+    #[test]
+    fn emission_and_lifetimes() {
+        let config = EmitterConfig {
+            rate: 10.0,
+            burst: 5,
+            duration: Some(1.0),
+            max_particles: 12,
+            lifetime: [2.0, 2.0],
+            speed: [10.0, 10.0],
+            acceleration: [0.0, 5.0],
+            shape: EmitterShape::Rect {
+                width: 4.0,
+                height: 2.0,
+                edge: true,
+            },
+            ..EmitterConfig::default()
+        };
+        let mut sim = Simulation::new(config, 1);
+        sim.position = Point2::new(100.0, 100.0);
+        sim.start();
+        assert_eq!(sim.particles.len(), 5);
+        for particle in &sim.particles {
+            let offset = particle.position - sim.position;
+            let on_x_edge = (offset.x.abs() - 2.0).abs() < 1e-4 && offset.y.abs() <= 1.0;
+            let on_y_edge = (offset.y.abs() - 1.0).abs() < 1e-4 && offset.x.abs() <= 2.0;
+            assert!(on_x_edge || on_y_edge, "{:?} is not on the edge", offset);
+        }
+
+        // Half a second emits five more, then the cap stops it at 12.
+        sim.advance(0.5);
+        assert_eq!(sim.particles.len(), 10);
+        sim.advance(0.5);
+        assert_eq!(sim.particles.len(), 12);
+        assert!(!sim.emitting);
+        let particle = sim.particles[0];
+        assert_eq!(particle.age, 1.0);
+        assert_eq!(particle.velocity, Vector2::new(10.0, 5.0));
+
+        // The first burst dies after two seconds.
+        sim.advance(1.0);
+        assert_eq!(sim.particles.len(), 7);
+        sim.advance(5.0);
+        assert!(sim.particles.is_empty());
+    }
+}
@@ -0,0 +1,555 @@
+//! Post-processing: full-screen effects applied to a whole frame.
+//!
+//! A `PostProcess` redirects drawing into a canvas of its own between
+//! `begin()` and `end()`.  `end()` then runs a chain of `Pass`es over
+//! the frame, each reading the previous one's output from one canvas
+//! and writing into the other, and draws the result to the screen.
+//!
+//! ```rust,ignore
+//! let mut post = PostProcess::new(ctx)?;
+//! let mut bloom = BloomPass::new(ctx)?;
+//! let mut crt = CrtPass::new(ctx)?;
+//!
+//! // in draw()
+//! post.begin(ctx)?;
+//! graphics::clear(ctx, graphics::BLACK);
+//! // ...draw the game...
+//! post.end(ctx, &mut [&mut bloom, &mut crt])?;
+//! // ...draw the unaffected HUD...
+//! graphics::present(ctx)?;
+//! ```
+//!
+//! Besides the built-in passes, any `Shader` can be used as a pass by
+//! wrapping it in a `ShaderPass`, or you can implement `Pass` yourself.
+
+use std::mem;
+
+use gfx;
+
+use conf::NumSamples;
+use context::Context;
+use error::{GameError, GameResult};
+use graphics::*;
+
+gfx_defines!{
+    /// The constants of the built-in passes' shaders.
+    constant PostConsts {
+        texel: [f32; 4] = "u_Texel",
+        params: [f32; 4] = "u_Params",
+    }
+}
+
+impl PostConsts {
+    /// Constants for drawing from a source canvas with the given
+    /// parameters.
+    fn new(source: &Canvas, params: [f32; 4]) -> Self {
+        let width = f32::from(source.get_image().width());
+        let height = f32::from(source.get_image().height());
+        PostConsts {
+            texel: [1.0 / width, 1.0 / height, width, height],
+            params,
+        }
+    }
+}
+
+/// A full-screen effect in a `PostProcess` chain.
+pub trait Pass {
+    /// Draws `source` into `target`, or onto the screen if `target` is
+    /// `None`, applying the effect.  Use `set_canvas()` to select the
+    /// target and `blit()` to cover it with a canvas.
+    ///
+    /// While passes run the screen coordinates are the pixels of
+    /// `source`, which is as big as `target`, and the transform, scissor
+    /// rectangle and stencil mode are reset.  `target` has been cleared to
+    /// transparent black, the screen is left as it is.
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult;
+}
+
+/// Selects `target`, or the screen if it is `None`, and draws `source`
+/// over all of it.  The building block of `Pass`es: the image is drawn
+/// with the current shader, so using one applies it to every pixel.
+pub fn blit(ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+    set_canvas(ctx, target);
+    draw_flipped(ctx, source, WHITE)
+}
+
+/// Draws a canvas over the whole render target, given that the screen
+/// coordinates are its pixels.
+///
+/// Canvases are stored upside down, OpenGL style; drawing them upside
+/// down as well means they come out right at the end of the chain
+/// however long it is.
+fn draw_flipped(ctx: &mut Context, source: &Canvas, color: Color) -> GameResult {
+    let height = f32::from(source.get_image().height());
+    let param = DrawParam::new()
+        .dest(Point2::new(0.0, height))
+        .scale(Vector2::new(1.0, -1.0))
+        .color(color);
+    source.draw(ctx, param)
+}
+
+/// Returns a canvas as big as `source`, reusing `canvas` if it fits.
+fn scratch_canvas(ctx: &mut Context, canvas: Option<Canvas>, source: &Canvas) -> GameResult<Canvas> {
+    let (width, height) = (source.get_image().width(), source.get_image().height());
+    if let Some(canvas) = canvas {
+        if canvas.get_image().width() == width && canvas.get_image().height() == height {
+            return Ok(canvas);
+        }
+    }
+    Canvas::new(ctx, width, height, NumSamples::One)
+}
+
+/// Creates one of the built-in pass shaders.
+fn post_shader(ctx: &mut Context, pixel_source: &[u8]) -> GameResult<Shader<PostConsts>> {
+    let consts = PostConsts {
+        texel: [0.0; 4],
+        params: [0.0; 4],
+    };
+    Shader::from_u8(
+        ctx,
+        include_bytes!("shader/basic_150.glslv"),
+        pixel_source,
+        consts,
+        "PostProcess",
+        Some(&[BlendMode::Alpha, BlendMode::Add]),
+    )
+}
+
+/// Runs a chain of `Pass`es over everything drawn between `begin()`
+/// and `end()`.  See the module documentation.
+///
+/// The two canvases it draws into are as big as the window in pixels,
+/// and are recreated when the window is resized.
+#[derive(Debug)]
+pub struct PostProcess {
+    front: Canvas,
+    back: Canvas,
+}
+
+impl PostProcess {
+    /// Creates a new `PostProcess` for the current window size.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let (width, height) = Self::screen_size(ctx);
+        Ok(PostProcess {
+            front: Canvas::new(ctx, width, height, NumSamples::One)?,
+            back: Canvas::new(ctx, width, height, NumSamples::One)?,
+        })
+    }
+
+    /// The size of the screen in pixels.
+    fn screen_size(ctx: &Context) -> (u16, u16) {
+        let (width, height, _, _) = ctx.gfx_context.screen_render_target.get_dimensions();
+        (width, height)
+    }
+
+    /// Starts drawing a frame to be post-processed, by setting the
+    /// canvas to draw on.  Resizes the canvases first if the window
+    /// size changed.
+    pub fn begin(&mut self, ctx: &mut Context) -> GameResult {
+        let (width, height) = Self::screen_size(ctx);
+        let image = self.front.get_image();
+        if image.width() != width || image.height() != height {
+            *self = Self::new(ctx)?;
+        }
+        set_canvas(ctx, Some(&self.front));
+        Ok(())
+    }
+
+    /// Runs the given passes in order over the frame drawn since
+    /// `begin()`, and draws the result onto the screen.
+    ///
+    /// Drawing goes to the screen afterwards, with the screen
+    /// coordinates, transform, scissor rectangle and stencil mode
+    /// restored.
+    pub fn end(&mut self, ctx: &mut Context, passes: &mut [&mut dyn Pass]) -> GameResult {
+        let screen = get_screen_coordinates(ctx);
+        let transform = get_transform(ctx);
+        let scissor = get_scissor(ctx);
+        let stencil = get_stencil(ctx);
+
+        let image = self.front.get_image();
+        let rect = Rect::new(0.0, 0.0, f32::from(image.width()), f32::from(image.height()));
+        set_screen_coordinates(ctx, rect)?;
+        origin(ctx);
+        apply_transformations(ctx)?;
+        set_scissor(ctx, None);
+        set_stencil(ctx, StencilMode::Off);
+
+        let result = self.run(ctx, passes);
+
+        set_canvas(ctx, None);
+        set_screen_coordinates(ctx, screen)?;
+        set_transform(ctx, transform);
+        apply_transformations(ctx)?;
+        set_scissor(ctx, scissor);
+        set_stencil(ctx, stencil);
+        result
+    }
+
+    fn run(&mut self, ctx: &mut Context, passes: &mut [&mut dyn Pass]) -> GameResult {
+        let count = passes.len();
+        for (i, pass) in passes.iter_mut().enumerate() {
+            if i + 1 == count {
+                set_canvas(ctx, None);
+                return pass.apply(ctx, &self.front, None);
+            }
+            set_canvas(ctx, Some(&self.back));
+            clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+            pass.apply(ctx, &self.front, Some(&self.back))?;
+            mem::swap(&mut self.front, &mut self.back);
+        }
+        blit(ctx, &self.front, None)
+    }
+}
+
+/// A pass that draws with a `Shader`, sending it the given constants
+/// first.
+#[derive(Debug)]
+pub struct ShaderPass<C>
+where
+    C: 'static + gfx::traits::Pod + gfx::pso::buffer::Structure<gfx::shade::ConstFormat> + Clone + Copy,
+{
+    shader: Shader<C>,
+    consts: C,
+}
+
+impl<C> ShaderPass<C>
+where
+    C: 'static + gfx::traits::Pod + gfx::pso::buffer::Structure<gfx::shade::ConstFormat> + Clone + Copy,
+{
+    /// Creates a new pass drawing with the given shader.
+    pub fn new(shader: Shader<C>, consts: C) -> Self {
+        ShaderPass { shader, consts }
+    }
+
+    /// Gets the constants sent to the shader.
+    pub fn consts(&self) -> C {
+        self.consts
+    }
+
+    /// Sets the constants sent to the shader.
+    pub fn set_consts(&mut self, consts: C) {
+        self.consts = consts;
+    }
+
+    /// Gets the shader.
+    pub fn shader(&self) -> &Shader<C> {
+        &self.shader
+    }
+}
+
+impl<C> Pass for ShaderPass<C>
+where
+    C: 'static + gfx::traits::Pod + gfx::pso::buffer::Structure<gfx::shade::ConstFormat> + Clone + Copy,
+{
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+        let _lock = use_shader(ctx, &self.shader);
+        self.shader.send(ctx, self.consts)?;
+        blit(ctx, source, target)
+    }
+}
+
+/// Blurs `source` into `target` in two steps, horizontally into
+/// `scratch` and then vertically.
+fn blur(
+    ctx: &mut Context,
+    shader: &Shader<PostConsts>,
+    radius: f32,
+    source: &Canvas,
+    scratch: &Canvas,
+    target: Option<&Canvas>,
+) -> GameResult {
+    let _lock = use_shader(ctx, shader);
+    shader.send(ctx, PostConsts::new(source, [1.0, 0.0, radius, 0.0]))?;
+    set_canvas(ctx, Some(scratch));
+    clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+    blit(ctx, source, Some(scratch))?;
+    shader.send(ctx, PostConsts::new(scratch, [0.0, 1.0, radius, 0.0]))?;
+    set_canvas(ctx, target);
+    if target.is_some() {
+        clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+    }
+    blit(ctx, scratch, target)
+}
+
+/// A Gaussian blur.
+#[derive(Debug)]
+pub struct BlurPass {
+    radius: f32,
+    shader: Shader<PostConsts>,
+    scratch: Option<Canvas>,
+}
+
+impl BlurPass {
+    /// Creates a new blur spreading each pixel over `radius` pixels
+    /// in every direction.
+    pub fn new(ctx: &mut Context, radius: f32) -> GameResult<Self> {
+        Ok(BlurPass {
+            radius,
+            shader: post_shader(ctx, include_bytes!("shader/post_blur_150.glslf"))?,
+            scratch: None,
+        })
+    }
+
+    /// Gets the radius of the blur in pixels.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Sets the radius of the blur in pixels.
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius;
+    }
+}
+
+impl Pass for BlurPass {
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+        let scratch = scratch_canvas(ctx, self.scratch.take(), source)?;
+        let result = blur(ctx, &self.shader, self.radius, source, &scratch, target);
+        self.scratch = Some(scratch);
+        result
+    }
+}
+
+/// Makes bright parts of the frame glow: the parts brighter than a
+/// threshold are blurred and added back on top.
+#[derive(Debug)]
+pub struct BloomPass {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    bright_shader: Shader<PostConsts>,
+    blur_shader: Shader<PostConsts>,
+    scratch: Option<(Canvas, Canvas)>,
+}
+
+impl BloomPass {
+    /// Creates a new bloom effect with a threshold of 0.7, an
+    /// intensity of 1.0 and a radius of 8 pixels.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        Ok(BloomPass {
+            threshold: 0.7,
+            intensity: 1.0,
+            radius: 8.0,
+            bright_shader: post_shader(ctx, include_bytes!("shader/post_bright_150.glslf"))?,
+            blur_shader: post_shader(ctx, include_bytes!("shader/post_blur_150.glslf"))?,
+            scratch: None,
+        })
+    }
+
+    /// Gets the brightness, from 0.0 to 1.0, above which pixels glow.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Sets the brightness, from 0.0 to 1.0, above which pixels glow.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Gets how strongly the glow is added to the frame.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Sets how strongly the glow is added to the frame.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    /// Gets how far the glow spreads, in pixels.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Sets how far the glow spreads, in pixels.
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius;
+    }
+
+    /// Draws `source` with its bright parts blurred and added on top,
+    /// using `glow` and `scratch` for the blurring.
+    fn draw_bloom(
+        &self,
+        ctx: &mut Context,
+        source: &Canvas,
+        glow: &mut Canvas,
+        scratch: &Canvas,
+        target: Option<&Canvas>,
+    ) -> GameResult {
+        {
+            let _lock = use_shader(ctx, &self.bright_shader);
+            self.bright_shader
+                .send(ctx, PostConsts::new(source, [self.threshold, 0.0, 0.0, 0.0]))?;
+            set_canvas(ctx, Some(glow));
+            clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+            blit(ctx, source, Some(glow))?;
+        }
+        blur(ctx, &self.blur_shader, self.radius, glow, scratch, Some(glow))?;
+
+        blit(ctx, source, target)?;
+        let intensity = self.intensity;
+        glow.set_blend_mode(Some(BlendMode::Add));
+        let result = draw_flipped(ctx, glow, Color::new(intensity, intensity, intensity, 1.0));
+        glow.set_blend_mode(None);
+        result
+    }
+}
+
+impl Pass for BloomPass {
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+        let (glow, scratch) = match self.scratch.take() {
+            Some((glow, scratch)) => (Some(glow), Some(scratch)),
+            None => (None, None),
+        };
+        let mut glow = scratch_canvas(ctx, glow, source)?;
+        let scratch = scratch_canvas(ctx, scratch, source)?;
+        let result = self.draw_bloom(ctx, source, &mut glow, &scratch, target);
+        self.scratch = Some((glow, scratch));
+        result
+    }
+}
+
+/// Makes the frame look like an old CRT monitor, with a curved
+/// screen, scanlines and darkened corners.
+#[derive(Debug)]
+pub struct CrtPass {
+    curvature: f32,
+    scanlines: f32,
+    scanline_count: f32,
+    vignette: f32,
+    shader: Shader<PostConsts>,
+}
+
+impl CrtPass {
+    /// Creates a new CRT effect with a slight curvature, scanlines
+    /// every two pixels and a vignette.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        Ok(CrtPass {
+            curvature: 0.05,
+            scanlines: 0.3,
+            scanline_count: 0.0,
+            vignette: 0.5,
+            shader: post_shader(ctx, include_bytes!("shader/post_crt_150.glslf"))?,
+        })
+    }
+
+    /// Gets how much the screen bulges out, where 0.0 is flat.
+    pub fn curvature(&self) -> f32 {
+        self.curvature
+    }
+
+    /// Sets how much the screen bulges out, where 0.0 is flat.
+    pub fn set_curvature(&mut self, curvature: f32) {
+        self.curvature = curvature;
+    }
+
+    /// Gets how dark the scanlines are, from 0.0 to 1.0.
+    pub fn scanlines(&self) -> f32 {
+        self.scanlines
+    }
+
+    /// Sets how dark the scanlines are, from 0.0 to 1.0.
+    pub fn set_scanlines(&mut self, scanlines: f32) {
+        self.scanlines = scanlines;
+    }
+
+    /// Gets the number of scanlines from the top of the screen to
+    /// the bottom.  0.0 means one every two pixels.
+    pub fn scanline_count(&self) -> f32 {
+        self.scanline_count
+    }
+
+    /// Sets the number of scanlines from the top of the screen to
+    /// the bottom.  0.0 means one every two pixels.
+    pub fn set_scanline_count(&mut self, count: f32) {
+        self.scanline_count = count;
+    }
+
+    /// Gets how dark the corners are, from 0.0 to 1.0.
+    pub fn vignette(&self) -> f32 {
+        self.vignette
+    }
+
+    /// Sets how dark the corners are, from 0.0 to 1.0.
+    pub fn set_vignette(&mut self, vignette: f32) {
+        self.vignette = vignette;
+    }
+}
+
+impl Pass for CrtPass {
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+        let params = [
+            self.curvature,
+            self.scanlines,
+            self.vignette,
+            self.scanline_count,
+        ];
+        let _lock = use_shader(ctx, &self.shader);
+        self.shader.send(ctx, PostConsts::new(source, params))?;
+        blit(ctx, source, target)
+    }
+}
+
+/// Color grading with a lookup table.
+///
+/// The lookup table is an image of `size` squares of `size` by `size`
+/// pixels next to each other, such as the common 256x16 ones.  Within a
+/// square red increases to the right and green downwards, and blue
+/// increases from square to square.  Grading an unchanged lookup table
+/// in an image editor and loading it here applies the same grading to
+/// the game.
+#[derive(Debug)]
+pub struct LutPass {
+    lut: Image,
+    strength: f32,
+    shader: Shader<PostConsts>,
+}
+
+impl LutPass {
+    /// Creates a new color grading pass with the given lookup table.
+    pub fn new(ctx: &mut Context, mut lut: Image) -> GameResult<Self> {
+        if u32::from(lut.height()) * u32::from(lut.height()) != u32::from(lut.width()) {
+            return Err(GameError::ResourceLoadError(format!(
+                "A {}x{} image is not a lookup table; it must be as wide as its height squared",
+                lut.width(),
+                lut.height()
+            )));
+        }
+        lut.set_filter(FilterMode::Linear);
+        lut.set_wrap(WrapMode::Clamp, WrapMode::Clamp);
+        let shader = post_shader(ctx, include_bytes!("shader/post_lut_150.glslf"))?;
+        shader.set_texture(ctx, "t_Lut", &lut)?;
+        Ok(LutPass {
+            lut,
+            strength: 1.0,
+            shader,
+        })
+    }
+
+    /// Gets how much of the grading is applied, from 0.0 to 1.0.
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    /// Sets how much of the grading is applied, from 0.0 to 1.0.
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength;
+    }
+
+    /// Gets the lookup table.
+    pub fn lut(&self) -> &Image {
+        &self.lut
+    }
+}
+
+impl Pass for LutPass {
+    fn apply(&mut self, ctx: &mut Context, source: &Canvas, target: Option<&Canvas>) -> GameResult {
+        let size = f32::from(self.lut.height());
+        let srgb = if ctx.gfx_context.is_srgb() { 1.0 } else { 0.0 };
+        let _lock = use_shader(ctx, &self.shader);
+        self.shader
+            .send(ctx, PostConsts::new(source, [self.strength, size, srgb, 0.0]))?;
+        blit(ctx, source, target)
+    }
+}
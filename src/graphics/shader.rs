@@ -3,6 +3,7 @@
 //! examples for a taste.
 
 use gfx::format;
+use gfx::handle;
 use gfx::handle::*;
 use gfx::preset::blend;
 use gfx::pso::buffer::*;
@@ -16,12 +17,16 @@ use std::collections::HashMap;
 use std::fmt;
 use std::io::prelude::*;
 use std::marker::PhantomData;
-use std::path::Path;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::SystemTime;
 
 use context::DebugId;
 use error::*;
 use graphics;
+use graphics::glsl::{self, Stage};
+use graphics::{BackendSpec, Image, StencilMode};
 use Context;
 
 /// A type for empty shader data for shaders that do not require any additional
@@ -117,13 +122,14 @@ impl From<BlendMode> for Blend {
 /// modes is to just make multiple PSOs with respective blend modes baked in.
 /// The `PsoSet` struct is basically just a hash map for easily
 /// storing each shader set's PSOs and then retrieving them based
-/// on a `BlendMode`.
+/// on a `BlendMode`.  The stencil state is baked in the same way,
+/// so PSOs are also keyed by a `StencilMode` (without its value).
 struct PsoSet<Spec, C>
 where
     Spec: graphics::BackendSpec,
     C: Structure<ConstFormat>,
 {
-    psos: HashMap<BlendMode, PipelineState<Spec::Resources, ConstMeta<C>>>,
+    psos: HashMap<(BlendMode, StencilMode), PipelineState<Spec::Resources, ConstMeta<C>>>,
 }
 
 impl<Spec, C> PsoSet<Spec, C>
@@ -140,16 +146,26 @@ where
     pub fn insert_mode(
         &mut self,
         mode: BlendMode,
+        stencil: StencilMode,
         pso: PipelineState<Spec::Resources, ConstMeta<C>>,
     ) {
-        let _ = self.psos.insert(mode, pso);
+        let _ = self.psos.insert((mode, stencil.pipeline_key()), pso);
+    }
+
+    pub fn modes(&self) -> Vec<(BlendMode, StencilMode)> {
+        self.psos.keys().cloned().collect()
+    }
+
+    pub fn has_mode(&self, mode: BlendMode, stencil: StencilMode) -> bool {
+        self.psos.contains_key(&(mode, stencil.pipeline_key()))
     }
 
     pub fn get_mode(
         &self,
-        mode: &BlendMode,
+        mode: BlendMode,
+        stencil: StencilMode,
     ) -> GameResult<&PipelineState<Spec::Resources, ConstMeta<C>>> {
-        match self.psos.get(mode) {
+        match self.psos.get(&(mode, stencil.pipeline_key())) {
             Some(pso) => Ok(pso),
             None => Err(GameError::RenderError(
                 "Could not find a pipeline for the specified shader and BlendMode".into(),
@@ -177,6 +193,36 @@ pub struct ShaderGeneric<Spec: graphics::BackendSpec, C: Structure<ConstFormat>>
 /// with a ggez graphics context
 pub type Shader<C> = ShaderGeneric<graphics::GlBackendSpec, C>;
 
+/// Validates and compiles a vertex and pixel shader pair, turning
+/// compile errors into ones that quote the offending source lines.
+fn compile_shader_set<Spec>(
+    factory: &mut Spec::Factory,
+    name: &str,
+    vertex_source: &[u8],
+    pixel_source: &[u8],
+) -> GameResult<ShaderSet<Spec::Resources>>
+where
+    Spec: graphics::BackendSpec,
+{
+    glsl::validate(name, vertex_source, pixel_source)?;
+    match factory.create_shader_set(vertex_source, pixel_source) {
+        Ok(set) => Ok(set),
+        Err(ProgramError::Vertex(ref e)) => {
+            let log = e.to_string();
+            Err(glsl::compile_error(name, Stage::Vertex, vertex_source, &log))
+        }
+        Err(ProgramError::Pixel(ref e)) => {
+            let log = e.to_string();
+            Err(glsl::compile_error(name, Stage::Pixel, pixel_source, &log))
+        }
+        Err(ProgramError::Link(ref e)) => Err(GameError::ShaderError(format!(
+            "shader `{}` failed to link: {}",
+            name, e
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
 pub(crate) fn create_shader<C, S, Spec>(
     vertex_source: &[u8],
     pixel_source: &[u8],
@@ -203,43 +249,37 @@ where
 
     let mut psos = PsoSet::new(blend_modes.len());
     let name: String = name.into();
-    for mode in blend_modes {
-        let init = ConstInit::<C>(
-            graphics::pipe::Init {
-                out: (
-                    "Target0",
-                    color_format,
-                    ColorMask::all(),
-                    Some((*mode).into()),
-                ),
-                ..graphics::pipe::new()
-            },
-            name.clone(),
-            PhantomData,
-        );
-        let set = factory.create_shader_set(vertex_source, pixel_source)?;
-        let sample = if multisample_samples > 1 {
-            Some(MultiSample)
-        } else {
-            None
-        };
-        let rasterizer = Rasterizer {
-            front_face: FrontFace::CounterClockwise,
-            cull_face: CullFace::Nothing,
-            method: RasterMethod::Fill,
-            offset: None,
-            samples: sample,
-        };
-
-        let pso = factory.create_pipeline_state(&set, Primitive::TriangleList, rasterizer, init)?;
-        psos.insert_mode(*mode, pso);
-    }
-
-    let program = ShaderProgram {
+    let set = compile_shader_set::<Spec>(factory, &name, vertex_source, pixel_source)?;
+    let sample = if multisample_samples > 1 {
+        Some(MultiSample)
+    } else {
+        None
+    };
+    let rasterizer = Rasterizer {
+        front_face: FrontFace::CounterClockwise,
+        cull_face: CullFace::Nothing,
+        method: RasterMethod::Fill,
+        offset: None,
+        samples: sample,
+    };
+    let mut program = ShaderProgram {
         buffer: buffer.clone(),
-        psos,
+        psos: PsoSet::new(0),
         active_blend_mode: blend_modes[0],
+        active_stencil_mode: StencilMode::Off,
+        textures: HashMap::new(),
+        texture_names: Vec::new(),
+        set,
+        name,
+        rasterizer,
+        color_format,
     };
+    for mode in blend_modes {
+        let pso = program.create_pso(factory, *mode, StencilMode::Off)?;
+        program.texture_names = pso.get_meta().2.iter().map(|t| t.0.clone()).collect();
+        psos.insert_mode(*mode, StencilMode::Off, pso);
+    }
+    program.psos = psos;
     let draw: Box<dyn ShaderHandle<Spec>> = Box::new(program);
 
     let id = 0;
@@ -271,26 +311,33 @@ where
         name: S,
         blend_modes: Option<&[BlendMode]>,
     ) -> GameResult<Shader<C>> {
-        let vertex_source = {
-            let mut buf = Vec::new();
-            let mut reader = ctx.filesystem.open(vertex_path)?;
-            let _ = reader.read_to_end(&mut buf)?;
-            buf
-        };
-        let pixel_source = {
-            let mut buf = Vec::new();
-            let mut reader = ctx.filesystem.open(pixel_path)?;
-            let _ = reader.read_to_end(&mut buf)?;
-            buf
-        };
-        Shader::from_u8(
+        let paths = [
+            vertex_path.as_ref().to_path_buf(),
+            pixel_path.as_ref().to_path_buf(),
+        ];
+        let modified = [
+            ctx.filesystem.modified(&paths[0]),
+            ctx.filesystem.modified(&paths[1]),
+        ];
+        let vertex_source = read_source(ctx, &paths[0])?;
+        let pixel_source = read_source(ctx, &paths[1])?;
+        let shader = Shader::from_u8(
             ctx,
             &vertex_source,
             &pixel_source,
             consts,
             name,
             blend_modes,
-        )
+        )?;
+        // Shaders loaded from a zip file can't change.
+        if modified[0].is_some() && modified[1].is_some() {
+            ctx.gfx_context.shader_watches.push(ShaderWatch {
+                id: shader.id,
+                paths,
+                modified,
+            });
+        }
+        Ok(shader)
     }
 
     /// Create a new `Shader` directly from source given a gfx pipeline
@@ -341,11 +388,32 @@ where
 {
     /// Send data to the GPU for use with the `Shader`
     pub fn send(&self, ctx: &mut Context, consts: C) -> GameResult {
+        // Queued sprites must still see the old values.
+        ctx.gfx_context.flush_batch()?;
         ctx.gfx_context
             .encoder
             .update_buffer(&self.buffer, &[consts], 0)?;
         Ok(())
     }
+
+    /// Binds an `Image` to one of the shader's extra textures, the
+    /// `sampler2D` uniforms other than `t_Texture`, which is always the
+    /// texture being drawn.  The image is sampled with its own filter and
+    /// wrap modes.  Use `Canvas::get_image()` to bind a `Canvas`.
+    ///
+    /// Extra textures that haven't been set sample the texture being
+    /// drawn instead.
+    pub fn set_texture(&self, ctx: &mut Context, name: &str, image: &Image) -> GameResult {
+        self.debug_id.assert(ctx);
+        let gfx = &mut ctx.gfx_context;
+        // Queued sprites must still see the old texture.
+        gfx.flush_batch()?;
+        let sampler = gfx.samplers
+            .get_or_insert(image.sampler_info, gfx.factory.as_mut());
+        let view = gfx.backend_spec
+            .raw_to_typed_shader_resource(image.texture.clone());
+        gfx.shaders[self.id].set_texture(name, (view, sampler))
+    }
 }
 
 impl<Spec, C> fmt::Debug for ShaderGeneric<Spec, C>
@@ -362,6 +430,49 @@ struct ShaderProgram<Spec: graphics::BackendSpec, C: Structure<ConstFormat>> {
     buffer: Buffer<Spec::Resources, C>,
     psos: PsoSet<Spec, C>,
     active_blend_mode: BlendMode,
+    active_stencil_mode: StencilMode,
+    textures: HashMap<String, TextureBinding<Spec::Resources>>,
+    texture_names: Vec<String>,
+    set: ShaderSet<Spec::Resources>,
+    name: String,
+    rasterizer: Rasterizer,
+    color_format: format::Format,
+}
+
+impl<Spec, C> ShaderProgram<Spec, C>
+where
+    Spec: graphics::BackendSpec,
+    C: Structure<ConstFormat>,
+{
+    /// Creates a PSO for the given blend and stencil modes.
+    fn create_pso(
+        &self,
+        factory: &mut Spec::Factory,
+        mode: BlendMode,
+        stencil: StencilMode,
+    ) -> GameResult<PipelineState<Spec::Resources, ConstMeta<C>>> {
+        let color_mask = if stencil.writes() {
+            ColorMask::empty()
+        } else {
+            ColorMask::all()
+        };
+        let init = ConstInit::<C>(
+            graphics::pipe::Init {
+                out: ("Target0", self.color_format, color_mask, Some(mode.into())),
+                stencil: stencil.into(),
+                ..graphics::pipe::new()
+            },
+            self.name.clone(),
+            PhantomData,
+        );
+        let pso = factory.create_pipeline_state(
+            &self.set,
+            Primitive::TriangleList,
+            self.rasterizer,
+            init,
+        )?;
+        Ok(pso)
+    }
 }
 
 impl<Spec, C> fmt::Debug for ShaderProgram<Spec, C>
@@ -390,6 +501,26 @@ pub trait ShaderHandle<Spec: graphics::BackendSpec>: fmt::Debug {
 
     /// Gets the shader program's current blend mode
     fn get_blend_mode(&self) -> BlendMode;
+
+    /// Sets the shader program's stencil mode, creating a pipeline
+    /// for it and the current blend mode if there isn't one yet
+    fn set_stencil_mode(&mut self, factory: &mut Spec::Factory, mode: StencilMode) -> GameResult;
+
+    /// Gets the shader program's current stencil mode
+    fn get_stencil_mode(&self) -> StencilMode;
+
+    /// Binds a texture to one of the shader program's extra textures
+    fn set_texture(&mut self, name: &str, texture: TextureBinding<Spec::Resources>) -> GameResult;
+
+    /// Recompiles the shader program from new sources, keeping its
+    /// constants, textures and modes.  On error the program is left
+    /// unchanged.
+    fn reload(
+        &mut self,
+        factory: &mut Spec::Factory,
+        vertex_source: &[u8],
+        pixel_source: &[u8],
+    ) -> GameResult;
 }
 
 impl<Spec, C> ShaderHandle<Spec> for ShaderProgram<Spec, C>
@@ -403,13 +534,14 @@ where
         slice: &Slice<Spec::Resources>,
         data: &graphics::pipe::Data<Spec::Resources>,
     ) -> GameResult {
-        let pso = self.psos.get_mode(&self.active_blend_mode)?;
-        encoder.draw(slice, pso, &ConstData(data, &self.buffer));
+        let pso = self.psos
+            .get_mode(self.active_blend_mode, self.active_stencil_mode)?;
+        encoder.draw(slice, pso, &ConstData(data, &self.buffer, &self.textures));
         Ok(())
     }
 
     fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
-        let _ = self.psos.get_mode(&mode)?;
+        let _ = self.psos.get_mode(mode, StencilMode::Off)?;
         self.active_blend_mode = mode;
         Ok(())
     }
@@ -417,6 +549,58 @@ where
     fn get_blend_mode(&self) -> BlendMode {
         self.active_blend_mode
     }
+
+    fn set_stencil_mode(&mut self, factory: &mut Spec::Factory, mode: StencilMode) -> GameResult {
+        if !self.psos.has_mode(self.active_blend_mode, mode) {
+            let pso = self.create_pso(factory, self.active_blend_mode, mode)?;
+            self.psos.insert_mode(self.active_blend_mode, mode, pso);
+        }
+        self.active_stencil_mode = mode;
+        Ok(())
+    }
+
+    fn get_stencil_mode(&self) -> StencilMode {
+        self.active_stencil_mode
+    }
+
+    fn set_texture(&mut self, name: &str, texture: TextureBinding<Spec::Resources>) -> GameResult {
+        if !self.texture_names.iter().any(|n| n == name) {
+            return Err(GameError::RenderError(format!(
+                "Shader has no texture named {:?}; its textures are {:?}",
+                name, self.texture_names
+            )));
+        }
+        let _ = self.textures.insert(name.to_owned(), texture);
+        Ok(())
+    }
+
+    fn reload(
+        &mut self,
+        factory: &mut Spec::Factory,
+        vertex_source: &[u8],
+        pixel_source: &[u8],
+    ) -> GameResult {
+        let set = compile_shader_set::<Spec>(factory, &self.name, vertex_source, pixel_source)?;
+        let old_set = mem::replace(&mut self.set, set);
+        let modes = self.psos.modes();
+        let mut psos = PsoSet::new(modes.len());
+        let mut texture_names = Vec::new();
+        for (mode, stencil) in modes {
+            match self.create_pso(factory, mode, stencil) {
+                Ok(pso) => {
+                    texture_names = pso.get_meta().2.iter().map(|t| t.0.clone()).collect();
+                    psos.insert_mode(mode, stencil, pso);
+                }
+                Err(e) => {
+                    self.set = old_set;
+                    return Err(e);
+                }
+            }
+        }
+        self.psos = psos;
+        self.texture_names = texture_names;
+        Ok(())
+    }
 }
 
 /// A lock for RAII shader regions. The shader automatically gets cleared once
@@ -469,11 +653,94 @@ pub fn clear_shader(ctx: &mut Context) {
     *ctx.gfx_context.current_shader.borrow_mut() = None;
 }
 
+/// The files a `Shader` was loaded from and when they were last
+/// modified, so it can be reloaded when they change.
+#[derive(Debug)]
+pub(crate) struct ShaderWatch {
+    id: ShaderId,
+    paths: [PathBuf; 2],
+    modified: [Option<SystemTime>; 2],
+}
+
+fn read_source(ctx: &mut Context, path: &Path) -> GameResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut reader = ctx.filesystem.open(path)?;
+    let _ = reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Turns hot reloading of shaders on or off.  While it is on, shaders
+/// created with `Shader::new()` from files in a directory (not in a
+/// zip file) are recompiled whenever those files change, checked once
+/// a frame in `present()`.  The existing `Shader` handles, constants
+/// and textures keep working with the new program.  If the changed
+/// source doesn't compile, the error is logged and the old program
+/// stays in use.
+///
+/// This is meant for development, to tweak shaders without
+/// restarting the game.
+pub fn set_shader_hot_reload(ctx: &mut Context, enabled: bool) {
+    ctx.gfx_context.hot_reload_shaders = enabled;
+}
+
+/// Returns whether hot reloading of shaders is on.  See
+/// `set_shader_hot_reload()`.
+pub fn get_shader_hot_reload(ctx: &Context) -> bool {
+    ctx.gfx_context.hot_reload_shaders
+}
+
+/// Recompiles the shaders whose files changed since they were last
+/// loaded, logging errors instead of returning them.
+pub(crate) fn reload_changed_shaders(ctx: &mut Context) {
+    for i in 0..ctx.gfx_context.shader_watches.len() {
+        let (id, paths, modified) = {
+            let watch = &ctx.gfx_context.shader_watches[i];
+            let modified = [
+                ctx.filesystem.modified(&watch.paths[0]),
+                ctx.filesystem.modified(&watch.paths[1]),
+            ];
+            // Some editors briefly delete a file while saving it.
+            if modified == watch.modified || modified.iter().any(Option::is_none) {
+                continue;
+            }
+            (watch.id, watch.paths.clone(), modified)
+        };
+        ctx.gfx_context.shader_watches[i].modified = modified;
+        let result = read_source(ctx, &paths[0]).and_then(|vertex_source| {
+            let pixel_source = read_source(ctx, &paths[1])?;
+            let gfx = &mut ctx.gfx_context;
+            gfx.shaders[id].reload(&mut *gfx.factory, &vertex_source, &pixel_source)
+        });
+        match result {
+            Ok(()) => info!("Reloaded shader {:?} and {:?}", paths[0], paths[1]),
+            Err(e) => error!(
+                "Could not reload shader {:?} and {:?}, keeping the old one: {}",
+                paths[0], paths[1], e
+            ),
+        }
+    }
+}
+
+/// The texture drawn by ggez, which `pipe` binds.  Every other texture
+/// of a shader is an extra one, bound with `Shader::set_texture()`.
+const MAIN_TEXTURE: &str = "t_Texture";
+
+/// A texture view and sampler that can be bound to a texture uniform.
+pub type TextureBinding<R> = (ShaderResourceView<R, [f32; 4]>, handle::Sampler<R>);
+
 #[derive(Debug)]
-struct ConstMeta<C: Structure<ConstFormat>>(graphics::pipe::Meta, ConstantBuffer<C>);
+struct ConstMeta<C: Structure<ConstFormat>>(
+    graphics::pipe::Meta,
+    ConstantBuffer<C>,
+    Vec<(String, TextureSampler<[f32; 4]>)>,
+);
 
 #[derive(Debug)]
-struct ConstData<'a, R: Resources, C: 'a>(&'a graphics::pipe::Data<R>, &'a Buffer<R, C>);
+struct ConstData<'a, R: Resources, C: 'a>(
+    &'a graphics::pipe::Data<R>,
+    &'a Buffer<R, C>,
+    &'a HashMap<String, TextureBinding<R>>,
+);
 
 impl<'a, R, C> PipelineData<R> for ConstData<'a, R, C>
 where
@@ -491,6 +758,10 @@ where
     ) {
         self.0.bake_to(out, &meta.0, man, access);
         meta.1.bind_to(out, self.1, man, access);
+        for (name, texture_meta) in &meta.2 {
+            let texture = self.2.get(name).unwrap_or(&self.0.tex);
+            texture_meta.bind_to(out, texture, man, access);
+        }
     }
 }
 
@@ -524,48 +795,65 @@ where
             }
         }
 
+        let mut textures = Vec::new();
+        for texture in info.textures.iter().filter(|t| t.name != MAIN_TEXTURE) {
+            let name = texture.name.as_str();
+            let mut texture_meta = TextureSampler::<[f32; 4]>::new();
+            match texture_meta.link_resource_view(texture, &name) {
+                Some(Ok(d)) => desc.resource_views[texture.slot as usize] = Some(d),
+                Some(Err(_)) => return Err(InitError::ResourceView(&texture.name, None)),
+                None => (),
+            }
+            for sampler in info.samplers.iter().filter(|s| s.name == texture.name) {
+                if let Some(d) = texture_meta.link_sampler(sampler, &name) {
+                    desc.samplers[sampler.slot as usize] = Some(d);
+                }
+            }
+            textures.push((texture.name.clone(), texture_meta));
+        }
+
+        // create a local clone of the program info so that we can remove
+        // the vars we found from it, leaving the rest to `pipe`
+        let mut program_info = info.clone();
         if let Some(index) = index {
-            // create a local clone of the program info so that we can remove
-            // the var we found from the `constant_buffer`
-            let mut program_info = info.clone();
             let _ = program_info.constant_buffers.remove(index);
-
-            let meta0 = match self.0.link_to(desc, &program_info) {
-                Ok(m) => m,
-                Err(e) => {
-                    // unfortunately... the error lifetime is bound to the
-                    // lifetime of our cloned program info which is bad since it
-                    // will go out of scope at the end of the function, so lets
-                    // convert the error to one that is bound to the lifetime of
-                    // the program info that was passed in!
-                    macro_rules! fixlifetimes {
-                        ($e:ident {
-                            $( $ty:path => $a:ident, )*
-                        }) => {{
-                            match $e {
-                                $( $ty(name, _) => {
-                                    let var = info.$a.iter().find(|v| v.name == name).unwrap();
-                                    // We can do better with the error data...
-                                    return Err($ty(&var.name, None));
-                                } )*
-                            }
-                        }}
-                    }
-                    fixlifetimes!(e {
-                        InitError::VertexImport => vertex_attributes,
-                        InitError::ConstantBuffer => constant_buffers,
-                        InitError::GlobalConstant => globals,
-                        InitError::ResourceView => textures,
-                        InitError::UnorderedView => unordereds,
-                        InitError::Sampler => samplers,
-                        InitError::PixelExport => outputs,
-                    })
+        }
+        program_info.textures.retain(|t| t.name == MAIN_TEXTURE);
+        program_info.samplers.retain(|s| s.name == MAIN_TEXTURE);
+
+        let meta0 = match self.0.link_to(desc, &program_info) {
+            Ok(m) => m,
+            Err(e) => {
+                // unfortunately... the error lifetime is bound to the
+                // lifetime of our cloned program info which is bad since it
+                // will go out of scope at the end of the function, so lets
+                // convert the error to one that is bound to the lifetime of
+                // the program info that was passed in!
+                macro_rules! fixlifetimes {
+                    ($e:ident {
+                        $( $ty:path => $a:ident, )*
+                    }) => {{
+                        match $e {
+                            $( $ty(name, _) => {
+                                let var = info.$a.iter().find(|v| v.name == name).unwrap();
+                                // We can do better with the error data...
+                                return Err($ty(&var.name, None));
+                            } )*
+                        }
+                    }}
                 }
-            };
+                fixlifetimes!(e {
+                    InitError::VertexImport => vertex_attributes,
+                    InitError::ConstantBuffer => constant_buffers,
+                    InitError::GlobalConstant => globals,
+                    InitError::ResourceView => textures,
+                    InitError::UnorderedView => unordereds,
+                    InitError::Sampler => samplers,
+                    InitError::PixelExport => outputs,
+                })
+            }
+        };
 
-            Ok(ConstMeta(meta0, meta1))
-        } else {
-            Ok(ConstMeta(self.0.link_to(desc, info)?, meta1))
-        }
+        Ok(ConstMeta(meta0, meta1, textures))
     }
 }
@@ -7,13 +7,18 @@
 //! a large amount of location/position data in a buffer, then feed it
 //! to the graphics card all in one go.
 
+use std::cell::RefCell;
+use std::mem;
+
+use super::mesh::create_dynamic_buffer;
 use super::shader::BlendMode;
 use super::types::FilterMode;
 use context::Context;
 use error;
 use gfx;
-use gfx::Factory;
-use graphics::{self, BackendSpec, DrawTransform};
+use gfx::handle::Buffer;
+use gfx_device_gl;
+use graphics::{self, BackendSpec, DrawTransform, InstanceProperties};
 use GameResult;
 
 /// A `SpriteBatch` draws a number of copies of the same image, using a single draw call.
@@ -23,13 +28,29 @@ use GameResult;
 /// in Debug mode because it spends a lot of time on array bounds checking and
 /// un-optimized math; you need to build with optimizations enabled to really get the
 /// speed boost.
-#[derive(Debug, Clone)]
+///
+/// The batch keeps its sprites in a GPU buffer of its own, and only
+/// sprites that were added or changed since it was last drawn are sent
+/// to the graphics card again, so drawing a batch that doesn't change
+/// costs next to nothing.
+#[derive(Debug)]
 pub struct SpriteBatch {
     image: graphics::Image,
     sprites: Vec<graphics::DrawParam>,
     blend_mode: Option<BlendMode>,
+    /// The sprites' instance properties on the GPU, created on the
+    /// first draw and grown as needed.
+    buffer: RefCell<Option<Buffer<gfx_device_gl::Resources, InstanceProperties>>>,
+    /// The ranges of sprites that changed since the buffer was updated,
+    /// sorted and with no two of them touching.
+    dirty: RefCell<Vec<(usize, usize)>>,
 }
 
+/// The most ranges of changed sprites a batch keeps track of before it
+/// merges the closest ones, sending the unchanged sprites between them
+/// along, to keep the number of uploads per draw down.
+const MAX_DIRTY_RANGES: usize = 16;
+
 /// An index of a particular sprite in a `SpriteBatch`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SpriteIdx(usize);
@@ -45,6 +66,8 @@ impl SpriteBatch {
             image,
             sprites: vec![],
             blend_mode: None,
+            buffer: RefCell::new(None),
+            dirty: RefCell::new(vec![]),
         }
     }
 
@@ -55,13 +78,59 @@ impl SpriteBatch {
     /// TODO: Into<DrawParam> and such
     pub fn add(&mut self, param: graphics::DrawParam) -> SpriteIdx {
         self.sprites.push(param);
-        SpriteIdx(self.sprites.len() - 1)
+        let idx = self.sprites.len() - 1;
+        self.mark_dirty(idx, idx + 1);
+        SpriteIdx(idx)
     }
 
     /// Alters a sprite in the batch to use the given draw params
     pub fn set(&mut self, handle: SpriteIdx, param: graphics::DrawParam) -> GameResult {
+        self.check_bounds(handle)?;
+        self.sprites[handle.0] = param;
+        self.mark_dirty(handle.0, handle.0 + 1);
+        Ok(())
+    }
+
+    /// Removes a sprite from the batch, returning its draw params.
+    ///
+    /// The sprites after it move down by one to keep their drawing
+    /// order, so their handles now refer to the sprite before them,
+    /// and all of them have to be sent to the graphics card again.
+    /// `swap_remove()` is cheaper if the order doesn't matter.
+    pub fn remove(&mut self, handle: SpriteIdx) -> GameResult<graphics::DrawParam> {
+        self.check_bounds(handle)?;
+        let param = self.sprites.remove(handle.0);
+        let len = self.sprites.len();
+        self.mark_dirty(handle.0, len);
+        Ok(param)
+    }
+
+    /// Removes a sprite from the batch, returning its draw params, and
+    /// puts the last sprite in its place.
+    ///
+    /// The handle of the last sprite now refers to nothing, and the
+    /// handle of the removed one to what was the last sprite.
+    pub fn swap_remove(&mut self, handle: SpriteIdx) -> GameResult<graphics::DrawParam> {
+        self.check_bounds(handle)?;
+        let param = self.sprites.swap_remove(handle.0);
+        if handle.0 < self.sprites.len() {
+            self.mark_dirty(handle.0, handle.0 + 1);
+        }
+        Ok(param)
+    }
+
+    /// Returns the number of sprites in the batch.
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// Returns whether the batch has no sprites in it.
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    fn check_bounds(&self, handle: SpriteIdx) -> GameResult {
         if handle.0 < self.sprites.len() {
-            self.sprites[handle.0] = param;
             Ok(())
         } else {
             Err(error::GameError::RenderError(String::from(
@@ -70,55 +139,72 @@ impl SpriteBatch {
         }
     }
 
-    /// Immediately sends all data in the batch to the graphics card.
+    /// Adds the sprites from `start` up to `end` to the ones that have to
+    /// be sent to the graphics card on the next draw.
+    fn mark_dirty(&self, start: usize, end: usize) {
+        add_dirty_range(&mut self.dirty.borrow_mut(), start, end);
+    }
+
+    /// Immediately sends the sprites that changed since the last draw
+    /// to the graphics card, reallocating the batch's buffer first if
+    /// they no longer fit in it.
     ///
     /// Generally just calling `graphics::draw()` on the `SpriteBatch`
-    /// will do this automaticassertally.
+    /// will do this automatically.
     fn flush(&self, ctx: &mut Context, image: &graphics::Image) -> GameResult {
-        // TODO: Can we clean up now?
-        // This is a little awkward but this is the right place
-        // to do whatever transformations need to happen to DrawParam's.
-        // We have a Context, and *everything* must pass through this
-        // function to be drawn, so.
-        // Though we do awkwardly have to allocate a new vector.
-        let new_sprites = self.sprites
-            .iter()
-            .map(|param| {
-                // Copy old params
-                let mut new_param = *param;
-                let src_width = param.src.w;
-                let src_height = param.src.h;
-                let real_scale = graphics::Vector2::new(
-                    src_width * param.scale.x * image.width as f32,
-                    src_height * param.scale.y * image.height as f32,
-                );
-                new_param.scale = real_scale;
-                // If we have no color, our color is white.
-                // This is fine because coloring the whole spritebatch is possible
-                // with graphics::set_color(); this just inherits from that.
-                new_param.color = new_param.color;
-                let primitive_param = graphics::DrawTransform::from(new_param);
-                primitive_param.to_instance_properties(ctx.gfx_context.is_srgb())
-            })
-            .collect::<Vec<_>>();
-
         let gfx = &mut ctx.gfx_context;
-        if gfx.data.rect_instance_properties.len() < self.sprites.len() {
-            gfx.data.rect_instance_properties = gfx.factory.create_buffer(
-                self.sprites.len(),
+        let mut buffer = self.buffer.borrow_mut();
+        let capacity = buffer.as_ref().map_or(0, |buffer| buffer.len());
+        let dirty = mem::replace(&mut *self.dirty.borrow_mut(), vec![]);
+        let dirty = if buffer.is_none() || capacity < self.sprites.len() {
+            // Grow geometrically, so adding sprites one by one doesn't
+            // reallocate every frame.
+            let len = self.sprites.len().next_power_of_two();
+            *buffer = Some(create_dynamic_buffer(
+                &mut *gfx.factory,
                 gfx::buffer::Role::Vertex,
-                gfx::memory::Usage::Dynamic,
-                gfx::memory::Bind::TRANSFER_DST,
-            )?;
+                len,
+            )?);
+            vec![(0, self.sprites.len())]
+        } else {
+            dirty
+        };
+        let buffer = buffer.as_ref().expect("Buffer was just created; should never happen");
+
+        // This is the right place to do whatever transformations need
+        // to happen to DrawParam's, since everything must pass through
+        // here to be drawn.
+        let srgb = gfx.is_srgb();
+        for (start, end) in dirty {
+            let end = end.min(self.sprites.len());
+            if start >= end {
+                continue;
+            }
+            let new_sprites = self.sprites[start..end]
+                .iter()
+                .map(|param| {
+                    // Copy old params
+                    let mut new_param = *param;
+                    let src_width = param.src.w;
+                    let src_height = param.src.h;
+                    let real_scale = graphics::Vector2::new(
+                        src_width * param.scale.x * image.width as f32,
+                        src_height * param.scale.y * image.height as f32,
+                    );
+                    new_param.scale = real_scale;
+                    let primitive_param = graphics::DrawTransform::from(new_param);
+                    primitive_param.to_instance_properties(srgb)
+                })
+                .collect::<Vec<_>>();
+            gfx.encoder.update_buffer(buffer, &new_sprites[..], start)?;
         }
-        gfx.encoder
-            .update_buffer(&gfx.data.rect_instance_properties, &new_sprites[..], 0)?;
         Ok(())
     }
 
     /// Removes all data from the sprite batch.
     pub fn clear(&mut self) {
         self.sprites.clear();
+        self.dirty.borrow_mut().clear();
     }
 
     /// Unwraps and returns the contained `Image`
@@ -128,7 +214,9 @@ impl SpriteBatch {
 
     /// Replaces the contained `Image`, returning the old one.
     pub fn set_image(&mut self, image: graphics::Image) -> graphics::Image {
-        use std::mem;
+        // Sprites are scaled by the size of the image.
+        let len = self.sprites.len();
+        self.mark_dirty(0, len);
         mem::replace(&mut self.image, image)
     }
 
@@ -143,14 +231,59 @@ impl SpriteBatch {
     }
 }
 
+/// Adds the range from `start` up to `end` to a sorted list of ranges,
+/// merging it with the ones it overlaps or touches.  If that leaves
+/// more than `MAX_DIRTY_RANGES`, the two ranges with the smallest gap
+/// between them are merged.
+fn add_dirty_range(ranges: &mut Vec<(usize, usize)>, start: usize, end: usize) {
+    if start >= end {
+        return;
+    }
+    // The ranges that end before this one starts, and the ones that
+    // start after it ends, stay as they are.
+    let first = ranges.iter().take_while(|range| range.1 < start).count();
+    let last = first + ranges[first..]
+        .iter()
+        .take_while(|range| range.0 <= end)
+        .count();
+    let merged = ranges[first..last]
+        .iter()
+        .fold((start, end), |(start, end), range| {
+            (start.min(range.0), end.max(range.1))
+        });
+    let _ = ranges.drain(first..last);
+    ranges.insert(first, merged);
+    if ranges.len() > MAX_DIRTY_RANGES {
+        let i = (1..ranges.len())
+            .min_by_key(|&i| ranges[i].0 - ranges[i - 1].1)
+            .expect("More than one range; should never happen");
+        ranges[i - 1].1 = ranges[i].1;
+        let _ = ranges.remove(i);
+    }
+}
+
+impl Clone for SpriteBatch {
+    /// Clones the sprites, but not the batch's GPU buffer, which the
+    /// clone creates for itself when it is first drawn.
+    fn clone(&self) -> Self {
+        Self {
+            image: self.image.clone(),
+            sprites: self.sprites.clone(),
+            blend_mode: self.blend_mode,
+            buffer: RefCell::new(None),
+            dirty: RefCell::new(vec![]),
+        }
+    }
+}
+
 impl graphics::Drawable for SpriteBatch {
     fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
     where
         D: Into<DrawTransform>,
     {
         let param = param.into();
-        // Awkwardly we must update values on all sprites and such.
-        // Also awkwardly we have this chain of colors with differing priorities.
+        ctx.gfx_context.flush_batch()?;
+        // Only sprites that changed since the last draw get sent over.
         self.flush(ctx, &self.image)?;
         let gfx = &mut ctx.gfx_context;
         let sampler = gfx.samplers
@@ -177,7 +310,16 @@ impl graphics::Drawable for SpriteBatch {
         } else {
             None
         };
-        gfx.draw(Some(&slice))?;
+        // Draw out of the batch's own buffer, putting back the shared
+        // one that everything else writes to afterwards.
+        let instances = self.buffer
+            .borrow()
+            .clone()
+            .expect("SpriteBatch buffer not created by flush; should never happen");
+        let shared_instances = mem::replace(&mut gfx.data.rect_instance_properties, instances);
+        let result = gfx.draw(Some(&slice));
+        gfx.data.rect_instance_properties = shared_instances;
+        result?;
         if let Some(mode) = previous_mode {
             gfx.set_blend_mode(mode)?;
         }
@@ -193,3 +335,31 @@ impl graphics::Drawable for SpriteBatch {
         self.blend_mode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_ranges() {
+        let mut ranges = vec![];
+        add_dirty_range(&mut ranges, 10, 11);
+        add_dirty_range(&mut ranges, 2, 3);
+        add_dirty_range(&mut ranges, 20, 25);
+        add_dirty_range(&mut ranges, 5, 5);
+        assert_eq!(ranges, vec![(2, 3), (10, 11), (20, 25)]);
+        // Touching and overlapping ranges are merged.
+        add_dirty_range(&mut ranges, 3, 4);
+        add_dirty_range(&mut ranges, 9, 22);
+        assert_eq!(ranges, vec![(2, 4), (9, 25)]);
+
+        // Past the limit, the closest ranges are merged.
+        let mut ranges = vec![];
+        for i in 0..MAX_DIRTY_RANGES {
+            add_dirty_range(&mut ranges, i * 10, i * 10 + 1);
+        }
+        add_dirty_range(&mut ranges, 33, 34);
+        assert_eq!(ranges.len(), MAX_DIRTY_RANGES);
+        assert_eq!(&ranges[2..4], &[(20, 21), (30, 34)]);
+    }
+}
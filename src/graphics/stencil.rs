@@ -0,0 +1,176 @@
+//! Stencil buffer masking.
+
+use gfx::state::{Comparison, Stencil, StencilOp};
+
+use context::Context;
+use error::GameResult;
+use graphics::{DrawTransform, Drawable};
+
+/// How drawing interacts with the stencil buffer, set with
+/// `set_stencil()`.
+///
+/// The screen and every `Canvas` have a stencil buffer holding one
+/// byte per pixel.  Drawing with `StencilMode::Write` marks pixels
+/// in it, and drawing with `StencilMode::Test` only changes pixels
+/// whose mark passes a test.  This allows drawing through arbitrary
+/// masks, such as a circular minimap or a portal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StencilMode {
+    /// Draw normally, without using the stencil buffer.
+    Off,
+    /// Set the stencil buffer to the given value wherever something is
+    /// drawn, without changing any colors.  Note that the whole shape of
+    /// what is drawn counts, including transparent pixels of images.
+    Write(u8),
+    /// Only draw where the value in the stencil buffer passes the test
+    /// against the given value.
+    Test(StencilTest, u8),
+}
+
+/// A test that decides where drawing with `StencilMode::Test` is
+/// allowed, comparing the value in the stencil buffer to the given one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StencilTest {
+    /// Draw where the stencil buffer holds the value.
+    Equal,
+    /// Draw where the stencil buffer doesn't hold the value.
+    NotEqual,
+    /// Draw where the stencil buffer holds less than the value.
+    Less,
+    /// Draw where the stencil buffer holds the value or less.
+    LessOrEqual,
+    /// Draw where the stencil buffer holds more than the value.
+    Greater,
+    /// Draw where the stencil buffer holds the value or more.
+    GreaterOrEqual,
+}
+
+impl StencilMode {
+    /// The reference value the stencil buffer is written with or
+    /// tested against.
+    pub(crate) fn value(self) -> u8 {
+        match self {
+            StencilMode::Off => 0,
+            StencilMode::Write(value) | StencilMode::Test(_, value) => value,
+        }
+    }
+
+    /// Whether drawing in this mode leaves colors alone.
+    pub(crate) fn writes(self) -> bool {
+        match self {
+            StencilMode::Write(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The mode without its value, which is not part of the
+    /// pipeline state and so doesn't need a pipeline of its own.
+    pub(crate) fn pipeline_key(self) -> StencilMode {
+        match self {
+            StencilMode::Off => StencilMode::Off,
+            StencilMode::Write(_) => StencilMode::Write(0),
+            StencilMode::Test(test, _) => StencilMode::Test(test, 0),
+        }
+    }
+}
+
+impl Default for StencilMode {
+    fn default() -> Self {
+        StencilMode::Off
+    }
+}
+
+impl From<StencilTest> for Comparison {
+    fn from(test: StencilTest) -> Self {
+        // OpenGL compares the reference value to the buffer,
+        // we compare the buffer to the value.
+        match test {
+            StencilTest::Equal => Comparison::Equal,
+            StencilTest::NotEqual => Comparison::NotEqual,
+            StencilTest::Less => Comparison::Greater,
+            StencilTest::LessOrEqual => Comparison::GreaterEqual,
+            StencilTest::Greater => Comparison::Less,
+            StencilTest::GreaterOrEqual => Comparison::LessEqual,
+        }
+    }
+}
+
+impl From<StencilMode> for Stencil {
+    fn from(mode: StencilMode) -> Self {
+        let keep = (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep);
+        match mode {
+            StencilMode::Off => Stencil::new(Comparison::Always, 0, keep),
+            StencilMode::Write(_) => Stencil::new(
+                Comparison::Always,
+                0xFF,
+                (StencilOp::Keep, StencilOp::Keep, StencilOp::Replace),
+            ),
+            StencilMode::Test(test, _) => Stencil::new(test.into(), 0xFF, keep),
+        }
+    }
+}
+
+/// Sets how drawing interacts with the stencil buffer of the screen
+/// or the current canvas.  See `StencilMode`.
+pub fn set_stencil(ctx: &mut Context, mode: StencilMode) {
+    ctx.gfx_context.set_stencil(mode);
+}
+
+/// Returns how drawing currently interacts with the stencil buffer.
+pub fn get_stencil(ctx: &Context) -> StencilMode {
+    ctx.gfx_context.stencil_mode
+}
+
+/// Sets the whole stencil buffer of the screen or the current canvas
+/// to the given value.  `clear()` sets it to 0.
+pub fn clear_stencil(ctx: &mut Context, value: u8) -> GameResult {
+    let gfx = &mut ctx.gfx_context;
+    gfx.flush_batch()?;
+    gfx.encoder.clear_stencil(&gfx.data.stencil.0, value);
+    Ok(())
+}
+
+/// Draws the given `Drawable` into the stencil buffer with
+/// `StencilMode::Write(value)`, then goes back to the previous
+/// stencil mode.
+///
+/// ```rust,ignore
+/// graphics::draw_stencil(ctx, &circle, DrawParam::new(), 1)?;
+/// graphics::set_stencil(ctx, StencilMode::Test(StencilTest::Equal, 1));
+/// graphics::draw(ctx, &minimap, DrawParam::new())?;
+/// graphics::set_stencil(ctx, StencilMode::Off);
+/// ```
+pub fn draw_stencil<D, T>(ctx: &mut Context, drawable: &D, params: T, value: u8) -> GameResult
+where
+    D: Drawable,
+    T: Into<DrawTransform>,
+{
+    let previous = get_stencil(ctx);
+    set_stencil(ctx, StencilMode::Write(value));
+    let result = drawable.draw(ctx, params.into());
+    set_stencil(ctx, previous);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stencil_states() {
+        let mode = StencilMode::Test(StencilTest::Less, 3);
+        assert_eq!(mode.value(), 3);
+        assert_eq!(mode.pipeline_key(), StencilMode::Test(StencilTest::Less, 0));
+        assert!(!mode.writes());
+        // The buffer holding less than 3 means 3 is greater than it.
+        let stencil = Stencil::from(mode);
+        assert_eq!(stencil.front.fun, Comparison::Greater);
+        assert_eq!(stencil.front.op_pass, StencilOp::Keep);
+
+        let stencil = Stencil::from(StencilMode::Write(1));
+        assert_eq!(stencil.front.fun, Comparison::Always);
+        assert_eq!(stencil.front.op_pass, StencilOp::Replace);
+        assert_eq!(stencil.front.mask_write, 0xFF);
+        assert_eq!(Stencil::from(StencilMode::Off).front.mask_write, 0);
+    }
+}
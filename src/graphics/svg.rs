@@ -0,0 +1,1413 @@
+//! Loading of simple SVG drawings, such as icons and outlines made in
+//! Inkscape, into meshes.
+//!
+//! `Svg::new()` reads a subset of SVG 1.1 from the ggez filesystem:
+//! `path`, `rect`, `circle`, `ellipse`, `line`, `polyline` and
+//! `polygon` elements, nested in any number of `g` groups, with
+//! `transform`s and solid fill and stroke colors given either as
+//! presentation attributes or in `style` attributes.  Strokes keep
+//! their width, line caps, line joins and dash patterns, and `opacity`,
+//! `fill-opacity` and `stroke-opacity` are multiplied into the alpha of
+//! the colors.
+//!
+//! Anything else is skipped: text, images, `use` references, clip
+//! paths, masks, markers and everything inside `defs`.  Gradient and
+//! pattern paints fall back to their fallback color if they have one,
+//! and are not drawn otherwise.  Fills always use the even-odd fill
+//! rule, and lengths have to be plain numbers or pixels.  A style
+//! property with a value that isn't understood, such as an unknown
+//! color, logs a warning and keeps the value it would have had without
+//! it.
+//!
+//! Coordinates are kept in the drawing's user units, those of its
+//! `viewBox` if it has one; `Svg::view_box()` tells where the drawing
+//! lies in them, for scaling it to fit with a `DrawParam`.
+
+use std::collections::HashMap;
+use std::f32;
+use std::io::Read;
+use std::path;
+
+use lyon;
+use lyon::path::builder::{FlatPathBuilder, PathBuilder};
+use lyon::tessellation::math::{point, Point};
+use xml::reader::{EventReader, XmlEvent};
+
+use context::Context;
+use filesystem;
+use graphics::{Color, DrawMode, LineCap, LineJoin, Mesh, MeshBuilder, Rect, StrokeOptions, BLACK};
+use GameError;
+use GameResult;
+
+/// The outline of a shape in an `Svg`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgStroke {
+    /// The color of the outline.
+    pub color: Color,
+    /// The width of the outline, in the drawing's user units.
+    pub width: f32,
+    /// How the ends of open sub-paths are drawn.
+    pub line_cap: LineCap,
+    /// How the corners of the outline are drawn.
+    pub line_join: LineJoin,
+    /// The dash pattern, as given to `MeshBuilder::dashes()`; empty for
+    /// a solid outline.
+    pub dashes: Vec<f32>,
+    /// How far into the dash pattern the outline starts.
+    pub dash_offset: f32,
+}
+
+/// A single shape of an `Svg`, with its fill and outline.
+#[derive(Debug, Clone)]
+pub struct SvgShape {
+    /// The `id` attribute of the shape's element, if it has one.
+    pub id: Option<String>,
+    /// The color the shape is filled with, if it is filled.
+    pub fill: Option<Color>,
+    /// The shape's outline, if it has one.
+    pub stroke: Option<SvgStroke>,
+    path: lyon::path::default::Path,
+}
+
+impl SvgShape {
+    /// Adds the shape to a `MeshBuilder`, in its own colors.  The
+    /// builder's color and dashes are left as they were.
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn add_to(&self, builder: &mut MeshBuilder, tolerance: f32) {
+        if let Some(color) = self.fill {
+            let _ = builder.styled_path(DrawMode::Fill, &self.path, tolerance, color, &[], 0.0);
+        }
+        if let Some(ref stroke) = self.stroke {
+            let options = StrokeOptions::default()
+                .with_line_width(stroke.width)
+                .with_line_cap(stroke.line_cap)
+                .with_line_join(stroke.line_join);
+            let _ = builder.styled_path(
+                DrawMode::CustomLine(options),
+                &self.path,
+                tolerance,
+                stroke.color,
+                &stroke.dashes,
+                stroke.dash_offset,
+            );
+        }
+    }
+
+    /// Builds a `Mesh` of the shape alone.
+    pub fn build(&self, ctx: &mut Context, tolerance: f32) -> GameResult<Mesh> {
+        let mut builder = MeshBuilder::new();
+        self.add_to(&mut builder, tolerance);
+        builder.build(ctx)
+    }
+}
+
+/// A vector drawing loaded from an SVG file, as a list of filled and
+/// stroked shapes.  See the module documentation for what is supported.
+#[derive(Debug, Clone)]
+pub struct Svg {
+    shapes: Vec<SvgShape>,
+    view_box: Option<Rect>,
+}
+
+impl Svg {
+    /// Loads a drawing from an SVG file in the ggez filesystem.
+    pub fn new<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let mut text = String::new();
+        let mut file = filesystem::open(ctx, path)?;
+        let _ = file.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    /// Reads a drawing from the text of an SVG file.
+    pub fn parse(text: &str) -> GameResult<Self> {
+        let mut svg = Svg {
+            shapes: vec![],
+            view_box: None,
+        };
+        let mut styles = vec![Style::default()];
+        let mut found_root = false;
+        // How deep we are inside an element whose contents get skipped.
+        let mut skipping = 0;
+        for event in EventReader::from_str(text) {
+            let event = event
+                .map_err(|e| GameError::ResourceLoadError(format!("Could not parse SVG: {}", e)))?;
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    if skipping > 0 {
+                        skipping += 1;
+                        continue;
+                    }
+                    let attributes: HashMap<String, String> = attributes
+                        .into_iter()
+                        .filter(|attribute| attribute.name.prefix.is_none())
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect();
+                    let name = name.local_name;
+                    if !found_root {
+                        if name != "svg" {
+                            return Err(GameError::ResourceLoadError(format!(
+                                "Expected an SVG drawing, found a {:?} element",
+                                name
+                            )));
+                        }
+                        found_root = true;
+                        svg.view_box = view_box(&attributes)?;
+                    }
+
+                    let is_container = match name.as_str() {
+                        "svg" | "g" | "a" | "switch" => true,
+                        _ => false,
+                    };
+                    if !is_container && !is_shape(&name) {
+                        skipping = 1;
+                        continue;
+                    }
+                    let style = element_style(&styles[styles.len() - 1], &attributes)?;
+                    if !style.display {
+                        skipping = 1;
+                        continue;
+                    }
+                    if is_shape(&name) {
+                        if let Some(shape) = shape(&name, &attributes, &style)? {
+                            svg.shapes.push(shape);
+                        }
+                    }
+                    styles.push(style);
+                }
+                XmlEvent::EndElement { .. } => {
+                    if skipping > 0 {
+                        skipping -= 1;
+                    } else {
+                        let _ = styles.pop();
+                    }
+                }
+                _ => (),
+            }
+        }
+        if !found_root {
+            return Err(GameError::ResourceLoadError(
+                "SVG file has no root element".to_owned(),
+            ));
+        }
+        Ok(svg)
+    }
+
+    /// The shapes of the drawing, in the order they are drawn.
+    pub fn shapes(&self) -> &[SvgShape] {
+        &self.shapes
+    }
+
+    /// Returns the first shape with the given `id`, if there is one.
+    pub fn shape(&self, id: &str) -> Option<&SvgShape> {
+        self.shapes
+            .iter()
+            .find(|shape| shape.id.as_ref().map(String::as_str) == Some(id))
+    }
+
+    /// The area of the drawing in its user units, from the `viewBox` of
+    /// the root element, or its `width` and `height` if it has no
+    /// `viewBox`.  `None` if it has neither.
+    pub fn view_box(&self) -> Option<Rect> {
+        self.view_box
+    }
+
+    /// Adds all the shapes of the drawing to a `MeshBuilder`, in their
+    /// own colors.  The builder's color and dashes are left as they
+    /// were.
+    ///
+    /// For the meaning of the `tolerance` parameter, [see here](https://docs.rs/lyon_geom/0.9.0/lyon_geom/#flattening).
+    pub fn add_to(&self, builder: &mut MeshBuilder, tolerance: f32) {
+        for shape in &self.shapes {
+            shape.add_to(builder, tolerance);
+        }
+    }
+
+    /// Builds a single `Mesh` of the whole drawing.  To draw or modify
+    /// parts of it separately, use `SvgShape::build()` on the shapes
+    /// instead.
+    pub fn build(&self, ctx: &mut Context, tolerance: f32) -> GameResult<Mesh> {
+        let mut builder = MeshBuilder::new();
+        self.add_to(&mut builder, tolerance);
+        builder.build(ctx)
+    }
+}
+
+/// A 2D affine transform, `[a, b, c, d, e, f]` as in SVG's `matrix()`:
+/// `x' = a * x + c * y + e` and `y' = b * x + d * y + f`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Transform([f32; 6]);
+
+impl Transform {
+    const IDENTITY: Transform = Transform([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// Returns the transform that applies `other`, then `self`.
+    fn then(&self, other: &Transform) -> Transform {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+        Transform([
+            a * oa + c * ob,
+            b * oa + d * ob,
+            a * oc + c * od,
+            b * oc + d * od,
+            a * oe + c * of + e,
+            b * oe + d * of + f,
+        ])
+    }
+
+    fn apply(&self, p: Point) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+        point(a * p.x + c * p.y + e, b * p.x + d * p.y + f)
+    }
+
+    /// How much the transform scales lengths, on average.
+    fn scale(&self) -> f32 {
+        let [a, b, c, d, _, _] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Paint {
+    None,
+    Color(Color),
+    CurrentColor,
+}
+
+/// The computed style of an element.
+#[derive(Debug, Clone)]
+struct Style {
+    transform: Transform,
+    /// The value of `currentColor`.
+    color: Color,
+    fill: Paint,
+    fill_opacity: f32,
+    stroke: Paint,
+    stroke_opacity: f32,
+    stroke_width: f32,
+    line_cap: LineCap,
+    line_join: LineJoin,
+    dashes: Vec<f32>,
+    dash_offset: f32,
+    /// The element's own `opacity`, which is not inherited.
+    opacity: f32,
+    /// The product of the opacities of the element and its ancestors.
+    alpha: f32,
+    /// Whether the element is displayed at all; not inherited.
+    display: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            transform: Transform::IDENTITY,
+            color: BLACK,
+            fill: Paint::Color(BLACK),
+            fill_opacity: 1.0,
+            stroke: Paint::None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            dashes: vec![],
+            dash_offset: 0.0,
+            opacity: 1.0,
+            alpha: 1.0,
+            display: true,
+        }
+    }
+}
+
+impl Style {
+    fn set(&mut self, name: &str, value: &str) -> GameResult {
+        let value = value.trim();
+        if value == "inherit" {
+            return Ok(());
+        }
+        match name {
+            "fill" => self.fill = parse_paint(value)?,
+            "stroke" => self.stroke = parse_paint(value)?,
+            "color" => self.color = parse_color(value)?,
+            "fill-opacity" => self.fill_opacity = parse_opacity(value)?,
+            "stroke-opacity" => self.stroke_opacity = parse_opacity(value)?,
+            "opacity" => self.opacity = parse_opacity(value)?,
+            "stroke-width" => self.stroke_width = parse_length(value)?,
+            "stroke-linecap" => {
+                self.line_cap = match value {
+                    "butt" => LineCap::Butt,
+                    "round" => LineCap::Round,
+                    "square" => LineCap::Square,
+                    _ => return Err(invalid("stroke-linecap", value)),
+                }
+            }
+            "stroke-linejoin" => {
+                self.line_join = match value {
+                    "miter" => LineJoin::Miter,
+                    "round" => LineJoin::Round,
+                    "bevel" => LineJoin::Bevel,
+                    _ => return Err(invalid("stroke-linejoin", value)),
+                }
+            }
+            "stroke-dasharray" => {
+                self.dashes = if value == "none" {
+                    vec![]
+                } else {
+                    Tokens::new(value).numbers()?
+                }
+            }
+            "stroke-dashoffset" => self.dash_offset = parse_length(value)?,
+            "display" => self.display = value != "none",
+            // Everything else, such as fonts, doesn't matter for shapes.
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+/// The names of the presentation attributes `Style::set()` knows.
+const PROPERTIES: &[&str] = &[
+    "fill",
+    "stroke",
+    "color",
+    "fill-opacity",
+    "stroke-opacity",
+    "opacity",
+    "stroke-width",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "display",
+];
+
+/// Computes the style of an element from its parent's and its own
+/// presentation attributes, `style` and `transform`.
+fn element_style(parent: &Style, attributes: &HashMap<String, String>) -> GameResult<Style> {
+    let mut style = Style {
+        opacity: 1.0,
+        display: true,
+        ..parent.clone()
+    };
+    for name in PROPERTIES {
+        if let Some(value) = attributes.get(*name) {
+            set_property(&mut style, name, value);
+        }
+    }
+    if let Some(declarations) = attributes.get("style") {
+        for declaration in declarations.split(';') {
+            let mut parts = declaration.splitn(2, ':');
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                set_property(&mut style, name.trim(), value);
+            }
+        }
+    }
+    if let Some(transform) = attributes.get("transform") {
+        style.transform = parent.transform.then(&parse_transform(transform)?);
+    }
+    style.alpha = parent.alpha * style.opacity;
+    Ok(style)
+}
+
+/// Sets a style property, keeping the inherited or default value of
+/// it if the new one is invalid or unsupported.  Renderers disagree on
+/// the details of these, so a drawing shouldn't fail to load over one.
+fn set_property(style: &mut Style, name: &str, value: &str) {
+    if let Err(e) = style.set(name, value) {
+        warn!("Ignoring SVG {} property: {}", name, e);
+    }
+}
+
+fn is_shape(name: &str) -> bool {
+    match name {
+        "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon" => true,
+        _ => false,
+    }
+}
+
+/// Turns a shape element into an `SvgShape`, or `None` if it has
+/// nothing to draw.
+fn shape(
+    name: &str,
+    attributes: &HashMap<String, String>,
+    style: &Style,
+) -> GameResult<Option<SvgShape>> {
+    let length = |name: &str| match attributes.get(name) {
+        Some(value) => parse_length(value),
+        None => Ok(0.0),
+    };
+    let mut outline = Outline::new(style.transform);
+    match name {
+        "path" => {
+            if let Some(data) = attributes.get("d") {
+                outline.path_data(data);
+            }
+        }
+        "rect" => {
+            let (x, y) = (length("x")?, length("y")?);
+            let (w, h) = (length("width")?, length("height")?);
+            let (rx, ry) = match (attributes.get("rx"), attributes.get("ry")) {
+                (None, None) => (0.0, 0.0),
+                (Some(_), None) => (length("rx")?, length("rx")?),
+                (None, Some(_)) => (length("ry")?, length("ry")?),
+                (Some(_), Some(_)) => (length("rx")?, length("ry")?),
+            };
+            if w <= 0.0 || h <= 0.0 {
+                return Ok(None);
+            }
+            let (rx, ry) = (rx.min(w / 2.0).max(0.0), ry.min(h / 2.0).max(0.0));
+            outline.move_to(point(x + rx, y));
+            outline.line_to(point(x + w - rx, y));
+            outline.arc_to(rx, ry, 0.0, false, true, point(x + w, y + ry));
+            outline.line_to(point(x + w, y + h - ry));
+            outline.arc_to(rx, ry, 0.0, false, true, point(x + w - rx, y + h));
+            outline.line_to(point(x + rx, y + h));
+            outline.arc_to(rx, ry, 0.0, false, true, point(x, y + h - ry));
+            outline.line_to(point(x, y + ry));
+            outline.arc_to(rx, ry, 0.0, false, true, point(x + rx, y));
+            outline.close();
+        }
+        "circle" | "ellipse" => {
+            let (cx, cy) = (length("cx")?, length("cy")?);
+            let (rx, ry) = if name == "circle" {
+                (length("r")?, length("r")?)
+            } else {
+                (length("rx")?, length("ry")?)
+            };
+            if rx <= 0.0 || ry <= 0.0 {
+                return Ok(None);
+            }
+            outline.move_to(point(cx + rx, cy));
+            outline.arc_to(rx, ry, 0.0, false, true, point(cx - rx, cy));
+            outline.arc_to(rx, ry, 0.0, false, true, point(cx + rx, cy));
+            outline.close();
+        }
+        "line" => {
+            outline.move_to(point(length("x1")?, length("y1")?));
+            outline.line_to(point(length("x2")?, length("y2")?));
+        }
+        "polyline" | "polygon" => {
+            let points = match attributes.get("points") {
+                Some(points) => Tokens::new(points).numbers()?,
+                None => vec![],
+            };
+            for (i, pair) in points.chunks(2).filter(|pair| pair.len() == 2).enumerate() {
+                if i == 0 {
+                    outline.move_to(point(pair[0], pair[1]));
+                } else {
+                    outline.line_to(point(pair[0], pair[1]));
+                }
+            }
+            if name == "polygon" {
+                outline.close();
+            }
+        }
+        _ => unreachable!("Not a shape element: {}", name),
+    }
+    if outline.is_empty() {
+        return Ok(None);
+    }
+
+    let fill = resolve_paint(style.fill, style).map(|mut color| {
+        color.a *= style.fill_opacity * style.alpha;
+        color
+    });
+    let scale = style.transform.scale();
+    let stroke = match resolve_paint(style.stroke, style) {
+        Some(mut color) if style.stroke_width > 0.0 => {
+            color.a *= style.stroke_opacity * style.alpha;
+            Some(SvgStroke {
+                color,
+                width: style.stroke_width * scale,
+                line_cap: style.line_cap,
+                line_join: style.line_join,
+                dashes: style.dashes.iter().map(|dash| dash * scale).collect(),
+                dash_offset: style.dash_offset * scale,
+            })
+        }
+        _ => None,
+    };
+    if fill.is_none() && stroke.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(SvgShape {
+        id: attributes.get("id").cloned(),
+        fill,
+        stroke,
+        path: outline.build(),
+    }))
+}
+
+fn resolve_paint(paint: Paint, style: &Style) -> Option<Color> {
+    match paint {
+        Paint::None => None,
+        Paint::Color(color) => Some(color),
+        Paint::CurrentColor => Some(style.color),
+    }
+}
+
+/// Builds a lyon path out of SVG drawing commands, given in the
+/// element's coordinates and transformed as they are added.
+struct Outline {
+    builder: lyon::path::default::Builder,
+    transform: Transform,
+    start: Point,
+    current: Point,
+    /// Whether a sub-path was closed and the next command has to start
+    /// a new one at the same point.
+    closed: bool,
+    /// The last control point of the previous command, if it was a
+    /// cubic curve, for smooth curves to mirror.
+    cubic_ctrl: Option<Point>,
+    /// The same for quadratic curves.
+    quadratic_ctrl: Option<Point>,
+    empty: bool,
+}
+
+impl Outline {
+    fn new(transform: Transform) -> Self {
+        Outline {
+            builder: lyon::path::default::Path::builder(),
+            transform,
+            start: point(0.0, 0.0),
+            current: point(0.0, 0.0),
+            closed: false,
+            cubic_ctrl: None,
+            quadratic_ctrl: None,
+            empty: true,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    fn build(self) -> lyon::path::default::Path {
+        self.builder.build()
+    }
+
+    fn move_to(&mut self, to: Point) {
+        self.builder.move_to(self.transform.apply(to));
+        self.start = to;
+        self.current = to;
+        self.closed = false;
+        self.cubic_ctrl = None;
+        self.quadratic_ctrl = None;
+        self.empty = false;
+    }
+
+    /// Starts a new sub-path at the current point if the last one was
+    /// closed, or at the origin if there is none.
+    fn continue_path(&mut self) {
+        if self.closed || self.empty {
+            let current = self.current;
+            self.move_to(current);
+        }
+    }
+
+    fn line_to(&mut self, to: Point) {
+        self.continue_path();
+        self.builder.line_to(self.transform.apply(to));
+        self.current = to;
+        self.cubic_ctrl = None;
+        self.quadratic_ctrl = None;
+    }
+
+    fn quadratic_to(&mut self, ctrl: Point, to: Point) {
+        self.continue_path();
+        let transform = self.transform;
+        self.builder
+            .quadratic_bezier_to(transform.apply(ctrl), transform.apply(to));
+        self.current = to;
+        self.cubic_ctrl = None;
+        self.quadratic_ctrl = Some(ctrl);
+    }
+
+    fn cubic_to(&mut self, ctrl1: Point, ctrl2: Point, to: Point) {
+        self.continue_path();
+        let transform = self.transform;
+        self.builder.cubic_bezier_to(
+            transform.apply(ctrl1),
+            transform.apply(ctrl2),
+            transform.apply(to),
+        );
+        self.current = to;
+        self.cubic_ctrl = Some(ctrl2);
+        self.quadratic_ctrl = None;
+    }
+
+    /// Adds an elliptical arc to `to`, parameterized like SVG's `A`
+    /// command, as a series of cubic curves of at most a quarter turn.
+    fn arc_to(
+        &mut self,
+        rx: f32,
+        ry: f32,
+        x_rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        to: Point,
+    ) {
+        let from = self.current;
+        if from == to {
+            return;
+        }
+        let (mut rx, mut ry) = (rx.abs(), ry.abs());
+        if rx == 0.0 || ry == 0.0 {
+            self.line_to(to);
+            return;
+        }
+
+        // The conversion to center parameterization from the SVG spec,
+        // appendix F.6.5.
+        let (sin, cos) = x_rotation.to_radians().sin_cos();
+        let (dx, dy) = ((from.x - to.x) / 2.0, (from.y - to.y) / 2.0);
+        let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let mut coefficient = (numerator / denominator).max(0.0).sqrt();
+        if large_arc == sweep {
+            coefficient = -coefficient;
+        }
+        let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+        let center = point(
+            cos * cx1 - sin * cy1 + (from.x + to.x) / 2.0,
+            sin * cx1 + cos * cy1 + (from.y + to.y) / 2.0,
+        );
+        let start_angle = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+        let end_angle = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+        let mut sweep_angle = end_angle - start_angle;
+        if sweep && sweep_angle < 0.0 {
+            sweep_angle += 2.0 * f32::consts::PI;
+        } else if !sweep && sweep_angle > 0.0 {
+            sweep_angle -= 2.0 * f32::consts::PI;
+        }
+
+        let on_ellipse = |angle: f32| {
+            let (s, c) = angle.sin_cos();
+            point(
+                center.x + cos * rx * c - sin * ry * s,
+                center.y + sin * rx * c + cos * ry * s,
+            )
+        };
+        let tangent = |angle: f32| {
+            let (s, c) = angle.sin_cos();
+            (-cos * rx * s - sin * ry * c, -sin * rx * s + cos * ry * c)
+        };
+        let segments = (sweep_angle.abs() / f32::consts::FRAC_PI_2).ceil().max(1.0) as usize;
+        let step = sweep_angle / segments as f32;
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+        for i in 0..segments {
+            let a = start_angle + step * i as f32;
+            let b = a + step;
+            let (p, q) = (on_ellipse(a), on_ellipse(b));
+            let (ta, tb) = (tangent(a), tangent(b));
+            let end = if i + 1 == segments { to } else { q };
+            self.cubic_to(
+                point(p.x + k * ta.0, p.y + k * ta.1),
+                point(q.x - k * tb.0, q.y - k * tb.1),
+                end,
+            );
+        }
+        self.cubic_ctrl = None;
+    }
+
+    fn close(&mut self) {
+        if !self.empty && !self.closed {
+            self.builder.close();
+            self.current = self.start;
+            self.closed = true;
+        }
+        self.cubic_ctrl = None;
+        self.quadratic_ctrl = None;
+    }
+
+    /// Adds the commands of a `d` attribute.  Errors in the data end
+    /// the path where they happen, as SVG renderers do, with what came
+    /// before kept.
+    fn path_data(&mut self, data: &str) {
+        let mut tokens = Tokens::new(data);
+        let mut command = match tokens.command() {
+            Some(b'M') | Some(b'm') => tokens.command_taken(),
+            _ => {
+                if !tokens.at_end() {
+                    warn!("SVG path data does not start with a move: {:?}", data);
+                }
+                return;
+            }
+        };
+        loop {
+            if let Err(e) = self.path_command(command, &mut tokens) {
+                warn!("Ending SVG path early: {}", e);
+                return;
+            }
+            if tokens.at_end() {
+                return;
+            }
+            command = match tokens.command() {
+                Some(_) => tokens.command_taken(),
+                // More arguments repeat the last command, with a move
+                // turning into a line.
+                None => match command {
+                    b'M' => b'L',
+                    b'm' => b'l',
+                    b'Z' | b'z' => {
+                        warn!("Ending SVG path early: numbers after a close");
+                        return;
+                    }
+                    command => command,
+                },
+            };
+        }
+    }
+
+    fn path_command(&mut self, command: u8, tokens: &mut Tokens) -> GameResult {
+        let relative = command.is_ascii_lowercase();
+        let origin = if relative {
+            self.current
+        } else {
+            point(0.0, 0.0)
+        };
+        let point_arg = |tokens: &mut Tokens| -> GameResult<Point> {
+            let x = tokens.number()?;
+            let y = tokens.number()?;
+            Ok(point(origin.x + x, origin.y + y))
+        };
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                let to = point_arg(tokens)?;
+                self.move_to(to);
+            }
+            b'L' => {
+                let to = point_arg(tokens)?;
+                self.line_to(to);
+            }
+            b'H' => {
+                let x = tokens.number()? + origin.x;
+                let y = self.current.y;
+                self.line_to(point(x, y));
+            }
+            b'V' => {
+                let y = tokens.number()? + origin.y;
+                let x = self.current.x;
+                self.line_to(point(x, y));
+            }
+            b'Q' => {
+                let ctrl = point_arg(tokens)?;
+                let to = point_arg(tokens)?;
+                self.quadratic_to(ctrl, to);
+            }
+            b'T' => {
+                let ctrl = self.mirrored(self.quadratic_ctrl);
+                let to = point_arg(tokens)?;
+                self.quadratic_to(ctrl, to);
+            }
+            b'C' => {
+                let ctrl1 = point_arg(tokens)?;
+                let ctrl2 = point_arg(tokens)?;
+                let to = point_arg(tokens)?;
+                self.cubic_to(ctrl1, ctrl2, to);
+            }
+            b'S' => {
+                let ctrl1 = self.mirrored(self.cubic_ctrl);
+                let ctrl2 = point_arg(tokens)?;
+                let to = point_arg(tokens)?;
+                self.cubic_to(ctrl1, ctrl2, to);
+            }
+            b'A' => {
+                let rx = tokens.number()?;
+                let ry = tokens.number()?;
+                let x_rotation = tokens.number()?;
+                let large_arc = tokens.flag()?;
+                let sweep = tokens.flag()?;
+                let to = point_arg(tokens)?;
+                self.arc_to(rx, ry, x_rotation, large_arc, sweep, to);
+            }
+            b'Z' => self.close(),
+            _ => return Err(invalid("path command", &(command as char).to_string())),
+        }
+        Ok(())
+    }
+
+    /// Reflects the previous control point about the current point, or
+    /// returns the current point if there is none.
+    fn mirrored(&self, ctrl: Option<Point>) -> Point {
+        match ctrl {
+            Some(ctrl) => point(2.0 * self.current.x - ctrl.x, 2.0 * self.current.y - ctrl.y),
+            None => self.current,
+        }
+    }
+}
+
+/// Splits path data, point lists and the like into numbers and
+/// command letters.
+struct Tokens<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokens { text, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_separators(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_ascii_whitespace() || c == b',' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos >= self.text.len()
+    }
+
+    /// Returns the command letter that comes next, if one does, without
+    /// taking it.
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() && c != b'e' && c != b'E' => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Takes the command letter `command()` returned.
+    fn command_taken(&mut self) -> u8 {
+        let c = self.text.as_bytes()[self.pos];
+        self.pos += 1;
+        c
+    }
+
+    fn number(&mut self) -> GameResult<f32> {
+        self.skip_separators();
+        let bytes = self.text.as_bytes();
+        let start = self.pos;
+        let mut end = start;
+        let digits = |mut i: usize| {
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            i
+        };
+        if end < bytes.len() && (bytes[end] == b'-' || bytes[end] == b'+') {
+            end += 1;
+        }
+        end = digits(end);
+        if end < bytes.len() && bytes[end] == b'.' {
+            end = digits(end + 1);
+        }
+        if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+            let mut exponent = end + 1;
+            if exponent < bytes.len() && (bytes[exponent] == b'-' || bytes[exponent] == b'+') {
+                exponent += 1;
+            }
+            if exponent < bytes.len() && bytes[exponent].is_ascii_digit() {
+                end = digits(exponent);
+            }
+        }
+        let number = &self.text[start..end];
+        match number.parse() {
+            Ok(n) => {
+                self.pos = end;
+                Ok(n)
+            }
+            Err(_) => Err(invalid("number", &self.text[start..])),
+        }
+    }
+
+    /// Reads an arc flag, which may be run together with what follows.
+    fn flag(&mut self) -> GameResult<bool> {
+        self.skip_separators();
+        let flag = match self.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(invalid("arc flag", &self.text[self.pos..])),
+        };
+        self.pos += 1;
+        Ok(flag)
+    }
+
+    /// Reads numbers up to the end of the text.
+    fn numbers(&mut self) -> GameResult<Vec<f32>> {
+        let mut numbers = vec![];
+        while !self.at_end() {
+            numbers.push(self.number()?);
+        }
+        Ok(numbers)
+    }
+}
+
+fn view_box(attributes: &HashMap<String, String>) -> GameResult<Option<Rect>> {
+    if let Some(view_box) = attributes.get("viewBox") {
+        let numbers = Tokens::new(view_box).numbers()?;
+        if numbers.len() != 4 {
+            return Err(invalid("viewBox", view_box));
+        }
+        return Ok(Some(Rect::new(
+            numbers[0], numbers[1], numbers[2], numbers[3],
+        )));
+    }
+    match (attributes.get("width"), attributes.get("height")) {
+        (Some(w), Some(h)) => match (parse_length(w), parse_length(h)) {
+            (Ok(w), Ok(h)) => Ok(Some(Rect::new(0.0, 0.0, w, h))),
+            // Sizes in physical units can't be turned into user units.
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn parse_transform(text: &str) -> GameResult<Transform> {
+    let mut transform = Transform::IDENTITY;
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let open = rest.find('(').ok_or_else(|| invalid("transform", text))?;
+        let close = rest.find(')').ok_or_else(|| invalid("transform", text))?;
+        if close < open {
+            return Err(invalid("transform", text));
+        }
+        let name = rest[..open].trim();
+        let args = Tokens::new(&rest[open + 1..close]).numbers()?;
+        let next = match (name, args.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform([a, b, c, d, e, f]),
+            ("translate", &[x]) => Transform([1.0, 0.0, 0.0, 1.0, x, 0.0]),
+            ("translate", &[x, y]) => Transform([1.0, 0.0, 0.0, 1.0, x, y]),
+            ("scale", &[s]) => Transform([s, 0.0, 0.0, s, 0.0, 0.0]),
+            ("scale", &[x, y]) => Transform([x, 0.0, 0.0, y, 0.0, 0.0]),
+            ("rotate", &[angle]) => rotation(angle),
+            ("rotate", &[angle, x, y]) => Transform([1.0, 0.0, 0.0, 1.0, x, y])
+                .then(&rotation(angle))
+                .then(&Transform([1.0, 0.0, 0.0, 1.0, -x, -y])),
+            ("skewX", &[angle]) => Transform([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", &[angle]) => Transform([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => return Err(invalid("transform", text)),
+        };
+        transform = transform.then(&next);
+        rest = rest[close + 1..].trim_left_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Ok(transform)
+}
+
+fn rotation(degrees: f32) -> Transform {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Transform([cos, sin, -sin, cos, 0.0, 0.0])
+}
+
+fn parse_paint(value: &str) -> GameResult<Paint> {
+    if value == "none" {
+        Ok(Paint::None)
+    } else if value == "currentColor" {
+        Ok(Paint::CurrentColor)
+    } else if value.starts_with("url(") {
+        // Gradients and patterns aren't supported; use the fallback
+        // color after the reference if there is one.
+        let fallback = value
+            .find(')')
+            .map_or("", |close| value[close + 1..].trim());
+        if fallback.is_empty() {
+            warn!("Unsupported SVG paint {:?}, not drawing it", value);
+            Ok(Paint::None)
+        } else {
+            parse_paint(fallback)
+        }
+    } else {
+        parse_color(value).map(Paint::Color)
+    }
+}
+
+fn parse_color(value: &str) -> GameResult<Color> {
+    let lower = value.to_ascii_lowercase();
+    if lower.starts_with('#') {
+        let hex = &lower[1..];
+        if !hex.is_ascii() {
+            return Err(invalid("color", value));
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).map(|d| d * 17);
+        let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        let color = match hex.len() {
+            3 => (digit(0), digit(1), digit(2), Ok(255)),
+            4 => (digit(0), digit(1), digit(2), digit(3)),
+            6 => (pair(0), pair(2), pair(4), Ok(255)),
+            8 => (pair(0), pair(2), pair(4), pair(6)),
+            _ => return Err(invalid("color", value)),
+        };
+        return match color {
+            (Ok(r), Ok(g), Ok(b), Ok(a)) => Ok(Color::from_rgba(r, g, b, a)),
+            _ => Err(invalid("color", value)),
+        };
+    }
+    if lower.starts_with("rgb(") || lower.starts_with("rgba(") {
+        let open = lower.find('(').unwrap_or(0);
+        let close = lower.rfind(')').ok_or_else(|| invalid("color", value))?;
+        let parts: Vec<&str> = lower[open + 1..close].split(',').map(str::trim).collect();
+        // Channels are numbers up to 255 or percentages, and alpha is a
+        // number up to 1 or a percentage.
+        let channel = |part: &str, max: f32| {
+            let channel = if part.ends_with('%') {
+                part[..part.len() - 1].parse::<f32>().map(|p| p / 100.0)
+            } else {
+                part.parse::<f32>().map(|c| c / max)
+            };
+            channel
+                .map(|c| c.max(0.0).min(1.0))
+                .map_err(|_| invalid("color", value))
+        };
+        let alpha = match parts.len() {
+            3 => 1.0,
+            4 => channel(parts[3], 1.0)?,
+            _ => return Err(invalid("color", value)),
+        };
+        return Ok(Color::new(
+            channel(parts[0], 255.0)?,
+            channel(parts[1], 255.0)?,
+            channel(parts[2], 255.0)?,
+            alpha,
+        ));
+    }
+    if lower == "transparent" {
+        return Ok(Color::new(0.0, 0.0, 0.0, 0.0));
+    }
+    NAMED_COLORS
+        .binary_search_by_key(&lower.as_str(), |&(name, _)| name)
+        .map(|i| Color::from_rgb_u32(NAMED_COLORS[i].1))
+        .map_err(|_| invalid("color", value))
+}
+
+/// The CSS named colors, sorted by name.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0_F8FF),
+    ("antiquewhite", 0xFA_EBD7),
+    ("aqua", 0x00_FFFF),
+    ("aquamarine", 0x7F_FFD4),
+    ("azure", 0xF0_FFFF),
+    ("beige", 0xF5_F5DC),
+    ("bisque", 0xFF_E4C4),
+    ("black", 0x00_0000),
+    ("blanchedalmond", 0xFF_EBCD),
+    ("blue", 0x00_00FF),
+    ("blueviolet", 0x8A_2BE2),
+    ("brown", 0xA5_2A2A),
+    ("burlywood", 0xDE_B887),
+    ("cadetblue", 0x5F_9EA0),
+    ("chartreuse", 0x7F_FF00),
+    ("chocolate", 0xD2_691E),
+    ("coral", 0xFF_7F50),
+    ("cornflowerblue", 0x64_95ED),
+    ("cornsilk", 0xFF_F8DC),
+    ("crimson", 0xDC_143C),
+    ("cyan", 0x00_FFFF),
+    ("darkblue", 0x00_008B),
+    ("darkcyan", 0x00_8B8B),
+    ("darkgoldenrod", 0xB8_860B),
+    ("darkgray", 0xA9_A9A9),
+    ("darkgreen", 0x00_6400),
+    ("darkgrey", 0xA9_A9A9),
+    ("darkkhaki", 0xBD_B76B),
+    ("darkmagenta", 0x8B_008B),
+    ("darkolivegreen", 0x55_6B2F),
+    ("darkorange", 0xFF_8C00),
+    ("darkorchid", 0x99_32CC),
+    ("darkred", 0x8B_0000),
+    ("darksalmon", 0xE9_967A),
+    ("darkseagreen", 0x8F_BC8F),
+    ("darkslateblue", 0x48_3D8B),
+    ("darkslategray", 0x2F_4F4F),
+    ("darkslategrey", 0x2F_4F4F),
+    ("darkturquoise", 0x00_CED1),
+    ("darkviolet", 0x94_00D3),
+    ("deeppink", 0xFF_1493),
+    ("deepskyblue", 0x00_BFFF),
+    ("dimgray", 0x69_6969),
+    ("dimgrey", 0x69_6969),
+    ("dodgerblue", 0x1E_90FF),
+    ("firebrick", 0xB2_2222),
+    ("floralwhite", 0xFF_FAF0),
+    ("forestgreen", 0x22_8B22),
+    ("fuchsia", 0xFF_00FF),
+    ("gainsboro", 0xDC_DCDC),
+    ("ghostwhite", 0xF8_F8FF),
+    ("gold", 0xFF_D700),
+    ("goldenrod", 0xDA_A520),
+    ("gray", 0x80_8080),
+    ("green", 0x00_8000),
+    ("greenyellow", 0xAD_FF2F),
+    ("grey", 0x80_8080),
+    ("honeydew", 0xF0_FFF0),
+    ("hotpink", 0xFF_69B4),
+    ("indianred", 0xCD_5C5C),
+    ("indigo", 0x4B_0082),
+    ("ivory", 0xFF_FFF0),
+    ("khaki", 0xF0_E68C),
+    ("lavender", 0xE6_E6FA),
+    ("lavenderblush", 0xFF_F0F5),
+    ("lawngreen", 0x7C_FC00),
+    ("lemonchiffon", 0xFF_FACD),
+    ("lightblue", 0xAD_D8E6),
+    ("lightcoral", 0xF0_8080),
+    ("lightcyan", 0xE0_FFFF),
+    ("lightgoldenrodyellow", 0xFA_FAD2),
+    ("lightgray", 0xD3_D3D3),
+    ("lightgreen", 0x90_EE90),
+    ("lightgrey", 0xD3_D3D3),
+    ("lightpink", 0xFF_B6C1),
+    ("lightsalmon", 0xFF_A07A),
+    ("lightseagreen", 0x20_B2AA),
+    ("lightskyblue", 0x87_CEFA),
+    ("lightslategray", 0x77_8899),
+    ("lightslategrey", 0x77_8899),
+    ("lightsteelblue", 0xB0_C4DE),
+    ("lightyellow", 0xFF_FFE0),
+    ("lime", 0x00_FF00),
+    ("limegreen", 0x32_CD32),
+    ("linen", 0xFA_F0E6),
+    ("magenta", 0xFF_00FF),
+    ("maroon", 0x80_0000),
+    ("mediumaquamarine", 0x66_CDAA),
+    ("mediumblue", 0x00_00CD),
+    ("mediumorchid", 0xBA_55D3),
+    ("mediumpurple", 0x93_70DB),
+    ("mediumseagreen", 0x3C_B371),
+    ("mediumslateblue", 0x7B_68EE),
+    ("mediumspringgreen", 0x00_FA9A),
+    ("mediumturquoise", 0x48_D1CC),
+    ("mediumvioletred", 0xC7_1585),
+    ("midnightblue", 0x19_1970),
+    ("mintcream", 0xF5_FFFA),
+    ("mistyrose", 0xFF_E4E1),
+    ("moccasin", 0xFF_E4B5),
+    ("navajowhite", 0xFF_DEAD),
+    ("navy", 0x00_0080),
+    ("oldlace", 0xFD_F5E6),
+    ("olive", 0x80_8000),
+    ("olivedrab", 0x6B_8E23),
+    ("orange", 0xFF_A500),
+    ("orangered", 0xFF_4500),
+    ("orchid", 0xDA_70D6),
+    ("palegoldenrod", 0xEE_E8AA),
+    ("palegreen", 0x98_FB98),
+    ("paleturquoise", 0xAF_EEEE),
+    ("palevioletred", 0xDB_7093),
+    ("papayawhip", 0xFF_EFD5),
+    ("peachpuff", 0xFF_DAB9),
+    ("peru", 0xCD_853F),
+    ("pink", 0xFF_C0CB),
+    ("plum", 0xDD_A0DD),
+    ("powderblue", 0xB0_E0E6),
+    ("purple", 0x80_0080),
+    ("rebeccapurple", 0x66_3399),
+    ("red", 0xFF_0000),
+    ("rosybrown", 0xBC_8F8F),
+    ("royalblue", 0x41_69E1),
+    ("saddlebrown", 0x8B_4513),
+    ("salmon", 0xFA_8072),
+    ("sandybrown", 0xF4_A460),
+    ("seagreen", 0x2E_8B57),
+    ("seashell", 0xFF_F5EE),
+    ("sienna", 0xA0_522D),
+    ("silver", 0xC0_C0C0),
+    ("skyblue", 0x87_CEEB),
+    ("slateblue", 0x6A_5ACD),
+    ("slategray", 0x70_8090),
+    ("slategrey", 0x70_8090),
+    ("snow", 0xFF_FAFA),
+    ("springgreen", 0x00_FF7F),
+    ("steelblue", 0x46_82B4),
+    ("tan", 0xD2_B48C),
+    ("teal", 0x00_8080),
+    ("thistle", 0xD8_BFD8),
+    ("tomato", 0xFF_6347),
+    ("turquoise", 0x40_E0D0),
+    ("violet", 0xEE_82EE),
+    ("wheat", 0xF5_DEB3),
+    ("white", 0xFF_FFFF),
+    ("whitesmoke", 0xF5_F5F5),
+    ("yellow", 0xFF_FF00),
+    ("yellowgreen", 0x9A_CD32),
+];
+
+fn parse_opacity(value: &str) -> GameResult<f32> {
+    value
+        .parse::<f32>()
+        .map(|opacity| opacity.max(0.0).min(1.0))
+        .map_err(|_| invalid("opacity", value))
+}
+
+fn parse_length(value: &str) -> GameResult<f32> {
+    let value = value.trim();
+    let number = if value.ends_with("px") {
+        &value[..value.len() - 2]
+    } else {
+        value
+    };
+    number.trim().parse().map_err(|_| invalid("length", value))
+}
+
+fn invalid(what: &str, value: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Invalid or unsupported SVG {}: {:?}", what, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyon::path::PathEvent;
+
+    fn events(shape: &SvgShape) -> Vec<PathEvent> {
+        shape.path.path_iter().collect()
+    }
+
+    fn assert_near(a: Point, x: f32, y: f32) {
+        assert!(
+            (a.x - x).abs() < 1e-4 && (a.y - y).abs() < 1e-4,
+            "{:?} is not ({}, {})",
+            a,
+            x,
+            y
+        );
+    }
+
+    #[test]
+    fn shapes_and_styles() {
+        let svg = Svg::parse(
+            r##"<?xml version="1.0"?>
+            <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 50" width="200mm">
+              <defs><rect id="hidden" width="5" height="5"/></defs>
+              <g fill="#f00" stroke="blue" transform="translate(10, 20)" opacity="0.5">
+                <rect id="box" width="10" height="4" style="stroke-width:2px;fill-opacity:0.5"/>
+                <circle id="dot" cx="0" cy="0" r="3" transform="scale(2)" stroke="none"/>
+                <line id="bare" x1="0" y1="0" x2="1" y2="1" stroke="none" fill="none"/>
+              </g>
+              <polygon id="tri" points="0,0 10,0 5,5" fill="rgb(0, 255, 0)"
+                       stroke="#000" stroke-dasharray="2 1" display="none"/>
+              <text>Skipped</text>
+            </svg>"##,
+        )
+        .unwrap();
+        assert_eq!(svg.view_box(), Some(Rect::new(0.0, 0.0, 100.0, 50.0)));
+        assert_eq!(svg.shapes().len(), 2);
+
+        let rect = svg.shape("box").unwrap();
+        assert_eq!(rect.fill, Some(Color::new(1.0, 0.0, 0.0, 0.25)));
+        let stroke = rect.stroke.as_ref().unwrap();
+        assert_eq!(stroke.color, Color::new(0.0, 0.0, 1.0, 0.5));
+        assert_eq!(stroke.width, 2.0);
+        match events(rect)[0] {
+            PathEvent::MoveTo(p) => assert_near(p, 10.0, 20.0),
+            ref event => panic!("Expected a move, got {:?}", event),
+        }
+
+        let dot = svg.shape("dot").unwrap();
+        assert!(dot.stroke.is_none());
+        match events(dot)[0] {
+            PathEvent::MoveTo(p) => assert_near(p, 16.0, 20.0),
+            ref event => panic!("Expected a move, got {:?}", event),
+        }
+        assert!(svg.shape("hidden").is_none());
+        assert!(svg.shape("tri").is_none());
+
+        let mut builder = MeshBuilder::new();
+        svg.add_to(&mut builder, 0.1);
+        assert!(!builder.indices().is_empty());
+
+        assert!(Svg::parse("<html/>").is_err());
+        assert!(Svg::parse(r#"<svg><rect width="1" height="x"/></svg>"#).is_err());
+    }
+
+    #[test]
+    fn unsupported_properties() {
+        let svg = Svg::parse(
+            r##"<svg>
+              <g fill="teal" stroke="black" stroke-linejoin="round">
+                <rect id="box" width="10" height="4" fill="blurple"
+                      style="stroke-linejoin:miter-clip;opacity:50%"/>
+              </g>
+            </svg>"##,
+        )
+        .unwrap();
+        // The invalid values are ignored and the inherited ones kept.
+        let rect = svg.shape("box").unwrap();
+        assert_eq!(rect.fill, Some(Color::from_rgb(0, 128, 128)));
+        let stroke = rect.stroke.as_ref().unwrap();
+        assert_eq!(stroke.line_join, LineJoin::Round);
+        assert_eq!(stroke.color, Color::from_rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn path_data() {
+        let mut outline = Outline::new(Transform::IDENTITY);
+        outline.path_data("M1,2l3-1 0 .5h-1V4zm1e1 0 a 5 5 0 01 10 0");
+        let path = outline.build();
+        let events: Vec<_> = path.path_iter().collect();
+        let ends: Vec<_> = events
+            .iter()
+            .filter_map(|event| match *event {
+                PathEvent::MoveTo(p) | PathEvent::LineTo(p) | PathEvent::CubicTo(_, _, p) => {
+                    Some((p.x, p.y))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            &ends[..6],
+            &[
+                (1.0, 2.0),
+                (4.0, 1.0),
+                (4.0, 1.5),
+                (3.0, 1.5),
+                (3.0, 4.0),
+                // The relative move after the close starts at (1, 2).
+                (11.0, 2.0),
+            ]
+        );
+        // A half circle takes two quarter turns.
+        assert_eq!(ends.len(), 8);
+        assert_eq!(ends[7], (21.0, 2.0));
+        match events.iter().rev().nth(1) {
+            Some(&PathEvent::CubicTo(_, _, p)) => assert_near(p, 16.0, -3.0),
+            event => panic!("Expected a curve, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn transforms_and_colors() {
+        let t = parse_transform("translate(10) rotate(90, 1, 1) scale(2 3)").unwrap();
+        assert_near(t.apply(point(1.0, 0.0)), 12.0, 2.0);
+        assert_near(t.apply(point(0.0, 1.0)), 9.0, 0.0);
+        assert!(parse_transform("wobble(1)").is_err());
+
+        assert_eq!(parse_color("#0f0").unwrap(), Color::from_rgb(0, 255, 0));
+        assert_eq!(parse_color("#102030").unwrap(), Color::from_rgb(16, 32, 48));
+        assert_eq!(parse_color("Navy").unwrap(), Color::from_rgb(0, 0, 128));
+        assert_eq!(
+            parse_color("rgb(100%, 0%, 51)").unwrap(),
+            Color::new(1.0, 0.0, 0.2, 1.0)
+        );
+        assert_eq!(
+            parse_color("RebeccaPurple").unwrap(),
+            Color::from_rgb(102, 51, 153)
+        );
+        assert_eq!(parse_color("darkgrey").unwrap(), Color::from_rgb(169, 169, 169));
+        assert!(parse_color("#12").is_err());
+        assert!(parse_color("#é0").is_err());
+        assert!(parse_color("blurple").is_err());
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(parse_paint("url(#grad) none").unwrap(), Paint::None);
+        assert_eq!(
+            parse_paint("url(#grad) red").unwrap(),
+            Paint::Color(Color::from_rgb(255, 0, 0))
+        );
+    }
+}
@@ -477,6 +477,7 @@ where
     D: Into<DrawTransform>,
 {
     let param: DrawTransform = param.into();
+    context.gfx_context.flush_batch()?;
     type Mat4 = na::Matrix4<f32>;
     type Vec3 = na::Vector3<f32>;
     let screen_rect = get_screen_coordinates(context);
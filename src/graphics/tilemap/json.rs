@@ -0,0 +1,428 @@
+//! Reading Tiled's JSON map and tileset formats.
+
+use std::collections::HashMap;
+use std::path;
+use std::time::Duration;
+
+use mint;
+use serde_json::{self, Value};
+
+use super::*;
+
+#[derive(Debug, Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    #[serde(rename = "tilewidth")]
+    tile_width: u32,
+    #[serde(rename = "tileheight")]
+    tile_height: u32,
+    #[serde(default = "orthogonal")]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<Value>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "tilewidth")]
+    tile_width: u32,
+    #[serde(rename = "tileheight")]
+    tile_height: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    margin: u32,
+    columns: u32,
+    #[serde(rename = "tilecount")]
+    tile_count: u32,
+    image: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    animation: Vec<JsonFrame>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFrame {
+    #[serde(rename = "tileid")]
+    tile: u32,
+    duration: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default = "one")]
+    opacity: f32,
+    #[serde(rename = "offsetx", default)]
+    offset_x: f32,
+    #[serde(rename = "offsety", default)]
+    offset_y: f32,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    compression: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    rotation: f32,
+    gid: Option<u32>,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default)]
+    ellipse: bool,
+    #[serde(default)]
+    point: bool,
+    polygon: Option<Vec<JsonPoint>>,
+    polyline: Option<Vec<JsonPoint>>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+fn orthogonal() -> String {
+    "orthogonal".to_owned()
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+pub(super) fn parse_map(
+    text: &str,
+    dir: &path::Path,
+) -> GameResult<(MapData, Vec<ExternalTileset>)> {
+    let map: JsonMap = serde_json::from_str(text)?;
+    if map.orientation != "orthogonal" {
+        return Err(unsupported("non-orthogonal maps"));
+    }
+    if map.infinite {
+        return Err(unsupported("infinite maps"));
+    }
+
+    let mut tilesets = vec![];
+    let mut external = vec![];
+    for value in map.tilesets {
+        let first_gid = value
+            .get("firstgid")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| {
+                GameError::ResourceLoadError("Tiled tileset has no firstgid".to_owned())
+            })? as u32;
+        match value.get("source").and_then(Value::as_str) {
+            Some(source) => {
+                external.push(ExternalTileset {
+                    index: tilesets.len(),
+                    source: resolve(dir, source),
+                });
+                tilesets.push(placeholder_tileset(first_gid));
+            }
+            None => {
+                let tileset = serde_json::from_value(value)?;
+                tilesets.push(convert_tileset(tileset, first_gid, dir)?);
+            }
+        }
+    }
+
+    let mut layers = vec![];
+    collect_layers(map.layers, &LayerAttributes::root(), &mut layers)?;
+
+    let data = MapData {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tile_width,
+        tile_height: map.tile_height,
+        properties: properties(map.properties)?,
+        tilesets,
+        layers,
+    };
+    Ok((data, external))
+}
+
+pub(super) fn parse_tileset(text: &str, first_gid: u32, dir: &path::Path) -> GameResult<Tileset> {
+    let tileset = serde_json::from_str(text)?;
+    convert_tileset(tileset, first_gid, dir)
+}
+
+fn convert_tileset(tileset: JsonTileset, first_gid: u32, dir: &path::Path) -> GameResult<Tileset> {
+    let image = tileset
+        .image
+        .ok_or_else(|| unsupported("tilesets made of separate images"))?;
+    let mut tiles = HashMap::new();
+    for tile in tileset.tiles {
+        let animation = tile.animation
+            .iter()
+            .map(|frame| TileFrame {
+                tile: frame.tile,
+                duration: Duration::from_millis(frame.duration),
+            })
+            .collect();
+        let data = TileData {
+            kind: if tile.kind.is_empty() {
+                tile.class
+            } else {
+                tile.kind
+            },
+            properties: properties(tile.properties)?,
+            animation,
+        };
+        let _ = tiles.insert(tile.id, data);
+    }
+    Ok(Tileset {
+        first_gid,
+        name: tileset.name,
+        tile_width: tileset.tile_width,
+        tile_height: tileset.tile_height,
+        spacing: tileset.spacing,
+        margin: tileset.margin,
+        columns: tileset.columns,
+        tile_count: tileset.tile_count,
+        image: resolve(dir, &image),
+        properties: properties(tileset.properties)?,
+        tiles,
+    })
+}
+
+fn collect_layers(
+    json_layers: Vec<JsonLayer>,
+    group: &LayerAttributes,
+    layers: &mut Vec<Layer>,
+) -> GameResult {
+    for layer in json_layers {
+        let attributes = group.nest(layer.visible, layer.opacity, layer.offset_x, layer.offset_y);
+        match layer.kind.as_str() {
+            "tilelayer" => layers.push(Layer::Tiles(tile_layer(layer, &attributes)?)),
+            "objectgroup" => layers.push(Layer::Objects(object_layer(layer, &attributes)?)),
+            "group" => collect_layers(layer.layers, &attributes, layers)?,
+            "imagelayer" => debug!("Skipping unsupported Tiled image layer"),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn tile_layer(layer: JsonLayer, attributes: &LayerAttributes) -> GameResult<TileLayer> {
+    match layer.compression {
+        Some(ref compression) if !compression.is_empty() => {
+            return Err(unsupported("compressed layer data"))
+        }
+        _ => (),
+    }
+    let tiles = match layer.data {
+        Some(Value::Array(gids)) => gids.iter()
+            .map(|gid| {
+                gid.as_u64().map(|gid| gid as u32).ok_or_else(|| {
+                    GameError::ResourceLoadError(format!("Invalid tile id {} in map", gid))
+                })
+            })
+            .collect::<GameResult<Vec<_>>>()?,
+        Some(Value::String(_)) => return Err(unsupported("base64 layer data")),
+        _ => {
+            let msg = format!("Tiled layer {:?} has no data", layer.name);
+            return Err(GameError::ResourceLoadError(msg));
+        }
+    };
+    check_tile_count(&layer.name, layer.width, layer.height, &tiles)?;
+    Ok(TileLayer {
+        name: layer.name,
+        width: layer.width,
+        height: layer.height,
+        visible: attributes.visible,
+        opacity: attributes.opacity,
+        offset: attributes.offset,
+        properties: properties(layer.properties)?,
+        tiles,
+    })
+}
+
+fn object_layer(layer: JsonLayer, attributes: &LayerAttributes) -> GameResult<ObjectLayer> {
+    let mut objects = Vec::with_capacity(layer.objects.len());
+    for object in layer.objects {
+        let points = |points: Vec<JsonPoint>| {
+            points
+                .into_iter()
+                .map(|p| mint::Point2 { x: p.x, y: p.y })
+                .collect()
+        };
+        let shape = if object.ellipse {
+            ObjectShape::Ellipse
+        } else if object.point {
+            ObjectShape::Point
+        } else if let Some(polygon) = object.polygon {
+            ObjectShape::Polygon(points(polygon))
+        } else if let Some(polyline) = object.polyline {
+            ObjectShape::Polyline(points(polyline))
+        } else {
+            ObjectShape::Rectangle
+        };
+        objects.push(Object {
+            id: object.id,
+            name: object.name,
+            kind: if object.kind.is_empty() {
+                object.class
+            } else {
+                object.kind
+            },
+            x: object.x,
+            y: object.y,
+            width: object.width,
+            height: object.height,
+            rotation: object.rotation,
+            gid: object.gid.and_then(|gid| if gid == 0 { None } else { Some(gid) }),
+            visible: object.visible,
+            shape,
+            properties: properties(object.properties)?,
+        });
+    }
+    Ok(ObjectLayer {
+        name: layer.name,
+        visible: attributes.visible,
+        opacity: attributes.opacity,
+        offset: attributes.offset,
+        properties: properties(layer.properties)?,
+        objects,
+    })
+}
+
+fn properties(json: Vec<JsonProperty>) -> GameResult<Properties> {
+    let mut properties = Properties::new();
+    for property in json {
+        let value = match property.value {
+            Value::String(s) => s,
+            value => value.to_string(),
+        };
+        let _ = properties.insert(property.name, parse_property(&property.kind, &value)?);
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"{
+        "width": 2, "height": 2, "tilewidth": 8, "tileheight": 8,
+        "orientation": "orthogonal", "infinite": false,
+        "properties": [ { "name": "dark", "type": "bool", "value": true } ],
+        "tilesets": [
+            { "firstgid": 1, "name": "t", "tilewidth": 8, "tileheight": 8, "columns": 4,
+              "tilecount": 16, "image": "t.png", "imagewidth": 32, "imageheight": 32,
+              "tiles": [ { "id": 2, "animation": [ { "tileid": 2, "duration": 100 },
+                                                   { "tileid": 3, "duration": 100 } ] } ] },
+            { "firstgid": 17, "source": "../shared/items.json" }
+        ],
+        "layers": [
+            { "type": "tilelayer", "name": "ground", "width": 2, "height": 2,
+              "opacity": 0.5, "visible": true, "data": [1, 3, 0, 17] },
+            { "type": "group", "name": "g", "visible": false, "offsetx": 4, "layers": [
+                { "type": "objectgroup", "name": "walls", "objects": [
+                    { "id": 1, "x": 0, "y": 0, "width": 8, "height": 16, "class": "wall",
+                      "ellipse": true,
+                      "properties": [ { "name": "hp", "type": "float", "value": 2.5 } ] }
+                ] }
+            ] }
+        ]
+    }"#;
+
+    #[test]
+    fn parse_json() {
+        let (data, external) = parse_map(MAP, path::Path::new("/maps")).unwrap();
+        assert_eq!(data.properties["dark"], Property::Bool(true));
+        assert_eq!(data.tilesets[0].image, path::PathBuf::from("/maps/t.png"));
+        assert_eq!(data.tilesets[0].tiles[&2].animation.len(), 2);
+        assert_eq!(external[0].source, path::PathBuf::from("/shared/items.json"));
+        assert_eq!(data.tilesets[1].first_gid, 17);
+
+        match data.layers[0] {
+            Layer::Tiles(ref layer) => {
+                assert_eq!(layer.opacity, 0.5);
+                assert_eq!(layer.tiles, vec![1, 3, 0, 17]);
+            }
+            _ => panic!("Expected a tile layer"),
+        }
+        match data.layers[1] {
+            Layer::Objects(ref layer) => {
+                // Hidden and moved along with its group.
+                assert!(!layer.visible);
+                assert_eq!(layer.offset, mint::Vector2 { x: 4.0, y: 0.0 });
+                let wall = &layer.objects[0];
+                assert_eq!(wall.kind, "wall");
+                assert_eq!(wall.shape, ObjectShape::Ellipse);
+                assert_eq!(wall.properties["hp"], Property::Float(2.5));
+            }
+            _ => panic!("Expected an object layer"),
+        }
+    }
+}
@@ -0,0 +1,808 @@
+//! Loading and drawing of tile maps made with the
+//! [Tiled](https://www.mapeditor.org/) map editor.
+//!
+//! A `Map` can be loaded from either of Tiled's formats, `.tmx` (XML)
+//! or `.json`, with `Map::new()`.  Tilesets may be embedded in the map
+//! or kept in separate `.tsx`/`.json` files next to it, but have to use
+//! a single image each, and layer data has to be stored uncompressed
+//! (the "CSV" or "XML" layer formats in Tiled, or plain arrays in JSON).
+//! Infinite maps and image layers are not supported; the layers of
+//! group layers are flattened into the map's layer list, taking on the
+//! visibility, opacity and offset of their groups.
+//!
+//! Tile layers are drawn through `SpriteBatch`es covering chunks of
+//! 16x16 tiles, and chunks outside of the rectangle given to
+//! `Map::set_view()` are skipped.  Animated tiles are advanced by
+//! `Map::update()`.  Object layers are not drawn, but are available to
+//! game code through `Map::layers()` along with all the custom
+//! properties.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path;
+use std::time::Duration;
+
+use mint;
+
+use context::Context;
+use filesystem;
+use graphics::spritebatch::{SpriteBatch, SpriteIdx};
+use graphics::{Color, DrawParam, DrawTransform, Drawable, Image, Rect};
+use graphics::BlendMode;
+use timer;
+use GameError;
+use GameResult;
+
+mod json;
+mod tmx;
+
+/// The flag bits Tiled stores in the top of a tile's global id.
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x0FFF_FFFF;
+
+/// The width and height of the chunks tile layers are drawn in, in tiles.
+const CHUNK_TILES: u32 = 16;
+
+/// A custom property set on something in Tiled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    /// A `bool` property.
+    Bool(bool),
+    /// An `int` property.
+    Int(i64),
+    /// A `float` property.
+    Float(f64),
+    /// A `string` property, or any other kind, such as `color` or `file`,
+    /// as it was written in the map.
+    String(String),
+}
+
+/// The custom properties of something in a map, by name.
+pub type Properties = HashMap<String, Property>;
+
+/// One frame of an animated tile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TileFrame {
+    /// The id of the tile to show, local to the tileset.
+    pub tile: u32,
+    /// How long the tile is shown.
+    pub duration: Duration,
+}
+
+/// Extra information about a single tile of a tileset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileData {
+    /// The tile's type, if it has one.
+    pub kind: String,
+    /// The tile's custom properties.
+    pub properties: Properties,
+    /// The frames of the tile's animation, if it is animated.
+    pub animation: Vec<TileFrame>,
+}
+
+/// A tileset: an image sliced into tiles, which layers refer to by
+/// global id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+    /// The global id of the first tile in the tileset.
+    pub first_gid: u32,
+    /// The tileset's name.
+    pub name: String,
+    /// The width of a tile, in pixels.
+    pub tile_width: u32,
+    /// The height of a tile, in pixels.
+    pub tile_height: u32,
+    /// The space between tiles in the image, in pixels.
+    pub spacing: u32,
+    /// The space around the tiles in the image, in pixels.
+    pub margin: u32,
+    /// The number of tiles in a row of the image.
+    pub columns: u32,
+    /// The number of tiles in the tileset.
+    pub tile_count: u32,
+    /// The path of the tileset's image in the ggez filesystem.
+    pub image: path::PathBuf,
+    /// The tileset's custom properties.
+    pub properties: Properties,
+    /// Extra information about the tiles that have any, by local id.
+    pub tiles: HashMap<u32, TileData>,
+}
+
+impl Tileset {
+    /// Returns whether the given global tile id belongs to this tileset.
+    pub fn contains(&self, gid: u32) -> bool {
+        let gid = gid & GID_MASK;
+        gid >= self.first_gid && gid - self.first_gid < self.tile_count
+    }
+
+    /// Returns the rectangle of the given local tile id on the tileset
+    /// image, in pixels.
+    pub fn tile_rect(&self, tile: u32) -> Rect {
+        let columns = self.columns.max(1);
+        let (column, row) = (tile % columns, tile / columns);
+        Rect::new(
+            (self.margin + column * (self.tile_width + self.spacing)) as f32,
+            (self.margin + row * (self.tile_height + self.spacing)) as f32,
+            self.tile_width as f32,
+            self.tile_height as f32,
+        )
+    }
+}
+
+/// A layer of tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    /// The layer's name.
+    pub name: String,
+    /// The width of the layer, in tiles.
+    pub width: u32,
+    /// The height of the layer, in tiles.
+    pub height: u32,
+    /// Whether the layer is drawn.
+    pub visible: bool,
+    /// The layer's opacity, from 0.0 to 1.0.
+    pub opacity: f32,
+    /// How far the layer is drawn from its usual position, in pixels.
+    pub offset: mint::Vector2<f32>,
+    /// The layer's custom properties.
+    pub properties: Properties,
+    /// The global ids of the layer's tiles, row by row, including
+    /// Tiled's flip flags.  0 means there is no tile.
+    pub tiles: Vec<u32>,
+}
+
+impl TileLayer {
+    /// Returns the global id of the tile at the given position, without
+    /// flip flags, or `None` if there is no tile there.
+    pub fn get(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        match self.tiles[(y * self.width + x) as usize] & GID_MASK {
+            0 => None,
+            gid => Some(gid),
+        }
+    }
+}
+
+/// The shape of an object in an object layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    /// A rectangle, or a tile if the object has a `gid`.
+    Rectangle,
+    /// An ellipse filling the object's bounds.
+    Ellipse,
+    /// A single point.
+    Point,
+    /// A closed polygon, with points relative to the object's position.
+    Polygon(Vec<mint::Point2<f32>>),
+    /// An open line, with points relative to the object's position.
+    Polyline(Vec<mint::Point2<f32>>),
+}
+
+/// An object in an object layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    /// The object's unique id.
+    pub id: u32,
+    /// The object's name.
+    pub name: String,
+    /// The object's type.
+    pub kind: String,
+    /// The x position of the object, in pixels.
+    pub x: f32,
+    /// The y position of the object, in pixels.
+    pub y: f32,
+    /// The width of the object, in pixels.
+    pub width: f32,
+    /// The height of the object, in pixels.
+    pub height: f32,
+    /// The object's rotation around its position, in degrees.
+    pub rotation: f32,
+    /// The global id of the object's tile, for tile objects.
+    pub gid: Option<u32>,
+    /// Whether the object is visible.
+    pub visible: bool,
+    /// The object's shape.
+    pub shape: ObjectShape,
+    /// The object's custom properties.
+    pub properties: Properties,
+}
+
+/// A layer of objects, such as spawn points or collision shapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLayer {
+    /// The layer's name.
+    pub name: String,
+    /// Whether the layer is visible.
+    pub visible: bool,
+    /// The layer's opacity, from 0.0 to 1.0.
+    pub opacity: f32,
+    /// How far the layer is moved from its usual position, in pixels.
+    /// The positions of the objects don't include this.
+    pub offset: mint::Vector2<f32>,
+    /// The layer's custom properties.
+    pub properties: Properties,
+    /// The objects in the layer.
+    pub objects: Vec<Object>,
+}
+
+/// A layer of a map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layer {
+    /// A layer of tiles.
+    Tiles(TileLayer),
+    /// A layer of objects.
+    Objects(ObjectLayer),
+}
+
+impl Layer {
+    /// Returns the name of the layer.
+    pub fn name(&self) -> &str {
+        match *self {
+            Layer::Tiles(ref layer) => &layer.name,
+            Layer::Objects(ref layer) => &layer.name,
+        }
+    }
+
+    /// Returns the custom properties of the layer.
+    pub fn properties(&self) -> &Properties {
+        match *self {
+            Layer::Tiles(ref layer) => &layer.properties,
+            Layer::Objects(ref layer) => &layer.properties,
+        }
+    }
+}
+
+/// A map as read from a file, before any images are loaded.
+#[derive(Debug, Clone, PartialEq)]
+struct MapData {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    properties: Properties,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+}
+
+/// A tileset that lives in its own file, to be read and filled in
+/// once the map itself has been parsed.
+#[derive(Debug, Clone)]
+struct ExternalTileset {
+    index: usize,
+    source: path::PathBuf,
+}
+
+/// A tile whose sprite needs updating as its animation plays.
+#[derive(Debug, Clone)]
+struct AnimatedTile {
+    batch: usize,
+    sprite: SpriteIdx,
+    param: DrawParam,
+    tileset: usize,
+    tile: u32,
+}
+
+/// A block of `CHUNK_TILES` by `CHUNK_TILES` tiles of a layer,
+/// with one `SpriteBatch` for each tileset it uses.
+#[derive(Debug, Clone)]
+struct Chunk {
+    bounds: Rect,
+    batches: Vec<SpriteBatch>,
+    animated: Vec<AnimatedTile>,
+}
+
+/// A Tiled map, ready to draw.
+#[derive(Debug, Clone)]
+pub struct Map {
+    data: MapData,
+    images: Vec<Image>,
+    /// The chunks of each layer; empty for object layers.
+    chunks: Vec<Vec<Chunk>>,
+    view: Option<Rect>,
+    clock: Duration,
+}
+
+impl Map {
+    /// Loads a map from a `.tmx` or `.json` file, along with its
+    /// external tilesets and tileset images.
+    pub fn new<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| path::Path::new("/"));
+        let text = read_to_string(ctx, path)?;
+        let (mut data, external) = if is_json(path) {
+            json::parse_map(&text, dir)?
+        } else {
+            tmx::parse_map(&text, dir)?
+        };
+
+        for tileset in external {
+            let first_gid = data.tilesets[tileset.index].first_gid;
+            let tileset_dir = tileset
+                .source
+                .parent()
+                .unwrap_or_else(|| path::Path::new("/"));
+            let text = read_to_string(ctx, &tileset.source)?;
+            data.tilesets[tileset.index] = if is_json(&tileset.source) {
+                json::parse_tileset(&text, first_gid, tileset_dir)?
+            } else {
+                tmx::parse_tileset(&text, first_gid, tileset_dir)?
+            };
+        }
+
+        let mut images = Vec::with_capacity(data.tilesets.len());
+        for tileset in &data.tilesets {
+            images.push(Image::new(ctx, &tileset.image)?);
+        }
+        Ok(Self::from_data(data, images))
+    }
+
+    fn from_data(data: MapData, images: Vec<Image>) -> Self {
+        let chunks = data.layers
+            .iter()
+            .map(|layer| match *layer {
+                Layer::Tiles(ref layer) => build_chunks(&data, &images, layer),
+                Layer::Objects(_) => vec![],
+            })
+            .collect();
+        let mut map = Self {
+            data,
+            images,
+            chunks,
+            view: None,
+            clock: Duration::new(0, 0),
+        };
+        map.advance(Duration::new(0, 0));
+        map
+    }
+
+    /// Advances animated tiles by the time since the last update,
+    /// as reported by `timer::get_delta()`.
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::get_delta(ctx));
+    }
+
+    /// Advances animated tiles by the given amount of time.
+    pub fn advance(&mut self, dt: Duration) {
+        self.clock += dt;
+        let clock_ns = nanos(self.clock);
+        for chunk in self.chunks.iter_mut().flat_map(|chunks| chunks.iter_mut()) {
+            for animated in &chunk.animated {
+                let tileset = &self.data.tilesets[animated.tileset];
+                let frames = &tileset.tiles[&animated.tile].animation;
+                let tile = animation_frame(frames, clock_ns);
+                let src = normalized_src(tileset, &self.images[animated.tileset], tile);
+                let param = animated.param.src(src);
+                let _ = chunk.batches[animated.batch].set(animated.sprite, param);
+            }
+        }
+    }
+
+    /// Sets the part of the map that is on screen, in map pixels, so that
+    /// chunks outside of it are not drawn.  `None` draws everything.
+    pub fn set_view(&mut self, view: Option<Rect>) {
+        self.view = view;
+    }
+
+    /// Gets the part of the map that is drawn, if set.
+    pub fn get_view(&self) -> Option<Rect> {
+        self.view
+    }
+
+    /// Draws a single layer of the map, if it is a visible tile layer.
+    pub fn draw_layer<D>(&self, ctx: &mut Context, layer: usize, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        let param = param.into();
+        let visible = match self.data.layers.get(layer) {
+            Some(Layer::Tiles(layer)) => layer.visible,
+            _ => false,
+        };
+        if !visible {
+            return Ok(());
+        }
+        for chunk in &self.chunks[layer] {
+            if let Some(view) = self.view {
+                if !view.overlaps(&chunk.bounds) {
+                    continue;
+                }
+            }
+            for batch in &chunk.batches {
+                batch.draw(ctx, param)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the width of the map, in tiles.
+    pub fn width(&self) -> u32 {
+        self.data.width
+    }
+
+    /// Returns the height of the map, in tiles.
+    pub fn height(&self) -> u32 {
+        self.data.height
+    }
+
+    /// Returns the width of the map's tiles, in pixels.
+    pub fn tile_width(&self) -> u32 {
+        self.data.tile_width
+    }
+
+    /// Returns the height of the map's tiles, in pixels.
+    pub fn tile_height(&self) -> u32 {
+        self.data.tile_height
+    }
+
+    /// Returns the map's custom properties.
+    pub fn properties(&self) -> &Properties {
+        &self.data.properties
+    }
+
+    /// Returns the map's tilesets.
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.data.tilesets
+    }
+
+    /// Returns the tileset the given global tile id belongs to, if any.
+    pub fn tileset_for(&self, gid: u32) -> Option<&Tileset> {
+        self.data.tilesets.iter().find(|tileset| tileset.contains(gid))
+    }
+
+    /// Returns the map's layers, from bottom to top.
+    pub fn layers(&self) -> &[Layer] {
+        &self.data.layers
+    }
+
+    /// Returns the first layer with the given name, if any.
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.data.layers.iter().find(|layer| layer.name() == name)
+    }
+
+    /// Returns an iterator over the map's object layers.
+    pub fn object_layers(&self) -> impl Iterator<Item = &ObjectLayer> {
+        self.data.layers.iter().filter_map(|layer| match *layer {
+            Layer::Objects(ref layer) => Some(layer),
+            Layer::Tiles(_) => None,
+        })
+    }
+}
+
+impl Drawable for Map {
+    fn draw<D>(&self, ctx: &mut Context, param: D) -> GameResult
+    where
+        D: Into<DrawTransform>,
+    {
+        let param = param.into();
+        for layer in 0..self.data.layers.len() {
+            self.draw_layer(ctx, layer, param)?;
+        }
+        Ok(())
+    }
+
+    /// Maps are always drawn with the blend mode of each `SpriteBatch`.
+    fn set_blend_mode(&mut self, _mode: Option<BlendMode>) {}
+
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        None
+    }
+}
+
+/// Stands in for an external tileset until its file has been read.
+fn placeholder_tileset(first_gid: u32) -> Tileset {
+    Tileset {
+        first_gid,
+        name: String::new(),
+        tile_width: 0,
+        tile_height: 0,
+        spacing: 0,
+        margin: 0,
+        columns: 0,
+        tile_count: 0,
+        image: path::PathBuf::new(),
+        properties: Properties::new(),
+        tiles: HashMap::new(),
+    }
+}
+
+/// Resolves a path in a map file, which is relative to the directory
+/// the file is in and may use `..`, into a ggez filesystem path.
+fn resolve(dir: &path::Path, relative: &str) -> path::PathBuf {
+    let mut resolved = path::PathBuf::from("/");
+    for component in dir.join(relative).components() {
+        match component {
+            path::Component::ParentDir => {
+                let _ = resolved.pop();
+            }
+            path::Component::Normal(part) => resolved.push(part),
+            _ => (),
+        }
+    }
+    resolved
+}
+
+fn read_to_string(ctx: &mut Context, path: &path::Path) -> GameResult<String> {
+    let mut s = String::new();
+    let mut file = filesystem::open(ctx, path)?;
+    let _ = file.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+fn is_json(path: &path::Path) -> bool {
+    path.extension() == Some("json".as_ref())
+}
+
+/// Returns the tile an animation shows at the given time, in nanoseconds.
+fn animation_frame(frames: &[TileFrame], clock_ns: u64) -> u32 {
+    let total: u64 = frames.iter().map(|frame| nanos(frame.duration)).sum();
+    if total == 0 {
+        return frames.first().map_or(0, |frame| frame.tile);
+    }
+    let mut t = clock_ns % total;
+    for frame in frames {
+        let duration = nanos(frame.duration);
+        if t < duration {
+            return frame.tile;
+        }
+        t -= duration;
+    }
+    frames[frames.len() - 1].tile
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+/// Returns the rectangle of a tile in the normalized coordinates
+/// of `DrawParam::src`.
+fn normalized_src(tileset: &Tileset, image: &Image, tile: u32) -> Rect {
+    let rect = tileset.tile_rect(tile);
+    let (w, h) = (f32::from(image.width()), f32::from(image.height()));
+    Rect::new(rect.x / w, rect.y / h, rect.w / w, rect.h / h)
+}
+
+/// Returns the rotation and scale that apply Tiled's flip flags to a
+/// tile, and the offset of the flipped tile's top-left corner from where
+/// it is drawn.
+fn flip_transform(gid: u32, width: f32, height: f32) -> (f32, (f32, f32), (f32, f32)) {
+    let h = if gid & FLIPPED_HORIZONTALLY != 0 { -1.0 } else { 1.0 };
+    let v = if gid & FLIPPED_VERTICALLY != 0 { -1.0 } else { 1.0 };
+    if gid & FLIPPED_DIAGONALLY != 0 {
+        // Flipping across the diagonal is a quarter turn plus a
+        // vertical flip, and the other flips then swap axes.
+        // A point (x, y) of the scaled tile ends up at (h * y, v * x).
+        let (sx, sy) = (v, -h);
+        let min_x = (-sy * height).min(0.0);
+        let min_y = (sx * width).min(0.0);
+        (::std::f32::consts::FRAC_PI_2, (sx, sy), (min_x, min_y))
+    } else {
+        let min_x = (h * width).min(0.0);
+        let min_y = (v * height).min(0.0);
+        (0.0, (h, v), (min_x, min_y))
+    }
+}
+
+/// Lays out the tiles of a layer into chunks of sprite batches.
+fn build_chunks(data: &MapData, images: &[Image], layer: &TileLayer) -> Vec<Chunk> {
+    let chunks_x = (layer.width + CHUNK_TILES - 1) / CHUNK_TILES;
+    let chunks_y = (layer.height + CHUNK_TILES - 1) / CHUNK_TILES;
+    let color = Color::new(1.0, 1.0, 1.0, layer.opacity);
+    let (tile_width, tile_height) = (data.tile_width as f32, data.tile_height as f32);
+    let mut chunks = Vec::with_capacity((chunks_x * chunks_y) as usize);
+    for chunk_y in 0..chunks_y {
+        for chunk_x in 0..chunks_x {
+            let mut chunk = Chunk {
+                bounds: Rect::new(0.0, 0.0, 0.0, 0.0),
+                batches: vec![],
+                animated: vec![],
+            };
+            let mut batch_of_tileset: HashMap<usize, usize> = HashMap::new();
+            let mut bounds: Option<Rect> = None;
+            let x_range = chunk_x * CHUNK_TILES..((chunk_x + 1) * CHUNK_TILES).min(layer.width);
+            let y_range = chunk_y * CHUNK_TILES..((chunk_y + 1) * CHUNK_TILES).min(layer.height);
+            for y in y_range {
+                for x in x_range.clone() {
+                    let gid = layer.tiles[(y * layer.width + x) as usize];
+                    let tileset = match data.tilesets.iter().position(|t| t.contains(gid)) {
+                        Some(tileset) => tileset,
+                        None => continue,
+                    };
+                    let set = &data.tilesets[tileset];
+                    let tile = (gid & GID_MASK) - set.first_gid;
+                    let batch = *batch_of_tileset.entry(tileset).or_insert_with(|| {
+                        chunk.batches.push(SpriteBatch::new(images[tileset].clone()));
+                        chunk.batches.len() - 1
+                    });
+
+                    // Tiles bigger than the map's grid stick out upwards,
+                    // with their bottom-left corners on the grid.
+                    let (width, height) = (set.tile_width as f32, set.tile_height as f32);
+                    let (rotation, scale, corner) = flip_transform(gid, width, height);
+                    let left = x as f32 * tile_width + layer.offset.x;
+                    let top = (y + 1) as f32 * tile_height - height + layer.offset.y;
+                    let param = DrawParam::new()
+                        .src(normalized_src(set, &images[tileset], tile))
+                        .dest(mint::Point2 {
+                            x: left - corner.0,
+                            y: top - corner.1,
+                        })
+                        .rotation(rotation)
+                        .scale(mint::Vector2 {
+                            x: scale.0,
+                            y: scale.1,
+                        })
+                        .color(color);
+                    let sprite = chunk.batches[batch].add(param);
+
+                    let animated = set.tiles.get(&tile).into_iter().any(|t| !t.animation.is_empty());
+                    if animated {
+                        chunk.animated.push(AnimatedTile {
+                            batch,
+                            sprite,
+                            param,
+                            tileset,
+                            tile,
+                        });
+                    }
+
+                    let tile_bounds = Rect::new(left, top, width.max(height), width.max(height));
+                    bounds = Some(match bounds {
+                        Some(bounds) => union(bounds, tile_bounds),
+                        None => tile_bounds,
+                    });
+                }
+            }
+            if let Some(bounds) = bounds {
+                chunk.bounds = bounds;
+                chunks.push(chunk);
+            }
+        }
+    }
+    chunks
+}
+
+/// The visibility, opacity and offset of a layer combined with those
+/// of the group layers it is in, which is how Tiled draws it, since
+/// groups are flattened away.
+#[derive(Debug, Copy, Clone)]
+struct LayerAttributes {
+    visible: bool,
+    opacity: f32,
+    offset: mint::Vector2<f32>,
+}
+
+impl LayerAttributes {
+    /// The attributes of a layer that isn't in any group.
+    fn root() -> Self {
+        LayerAttributes {
+            visible: true,
+            opacity: 1.0,
+            offset: mint::Vector2 { x: 0.0, y: 0.0 },
+        }
+    }
+
+    /// Combines the attributes of a group with those of a layer in it.
+    fn nest(&self, visible: bool, opacity: f32, offset_x: f32, offset_y: f32) -> Self {
+        LayerAttributes {
+            visible: self.visible && visible,
+            opacity: self.opacity * opacity,
+            offset: mint::Vector2 {
+                x: self.offset.x + offset_x,
+                y: self.offset.y + offset_y,
+            },
+        }
+    }
+}
+
+/// Checks that a tile layer has as many tiles as its size says.
+fn check_tile_count(name: &str, width: u32, height: u32, tiles: &[u32]) -> GameResult {
+    let expected = width.checked_mul(height).ok_or_else(|| {
+        GameError::ResourceLoadError(format!(
+            "Tiled layer {:?} is too big at {}x{} tiles",
+            name, width, height
+        ))
+    })?;
+    if tiles.len() != expected as usize {
+        return Err(GameError::ResourceLoadError(format!(
+            "Tiled layer {:?} should have {} tiles but has {}",
+            name,
+            expected,
+            tiles.len()
+        )));
+    }
+    Ok(())
+}
+
+fn union(a: Rect, b: Rect) -> Rect {
+    let left = a.left().min(b.left());
+    let top = a.top().min(b.top());
+    let right = a.right().max(b.right());
+    let bottom = a.bottom().max(b.bottom());
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+/// Turns a property value of the given Tiled type into a `Property`.
+fn parse_property(kind: &str, value: &str) -> GameResult<Property> {
+    let invalid = || {
+        GameError::ResourceLoadError(format!("Invalid {} property value {:?}", kind, value))
+    };
+    Ok(match kind {
+        "bool" => Property::Bool(value.parse().map_err(|_| invalid())?),
+        "int" => Property::Int(value.parse().map_err(|_| invalid())?),
+        "float" => Property::Float(value.parse().map_err(|_| invalid())?),
+        _ => Property::String(value.to_owned()),
+    })
+}
+
+/// Parses Tiled's CSV layer data.
+fn parse_csv(data: &str) -> GameResult<Vec<u32>> {
+    data.split(',')
+        .map(str::trim)
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| {
+            gid.parse().map_err(|_| {
+                GameError::ResourceLoadError(format!("Invalid tile id {:?} in map", gid))
+            })
+        })
+        .collect()
+}
+
+fn unsupported(what: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Unsupported Tiled map feature: {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_rects_and_animations() {
+        let tileset = Tileset {
+            first_gid: 5,
+            name: "t".to_owned(),
+            tile_width: 16,
+            tile_height: 8,
+            spacing: 2,
+            margin: 1,
+            columns: 3,
+            tile_count: 9,
+            image: path::PathBuf::from("/t.png"),
+            properties: Properties::new(),
+            tiles: HashMap::new(),
+        };
+        assert!(tileset.contains(5 | FLIPPED_VERTICALLY));
+        assert!(!tileset.contains(14));
+        assert_eq!(tileset.tile_rect(4), Rect::new(19.0, 11.0, 16.0, 8.0));
+
+        let frames = [
+            TileFrame {
+                tile: 1,
+                duration: Duration::from_millis(100),
+            },
+            TileFrame {
+                tile: 2,
+                duration: Duration::from_millis(50),
+            },
+        ];
+        let ms = 1_000_000;
+        assert_eq!(animation_frame(&frames, 0), 1);
+        assert_eq!(animation_frame(&frames, 120 * ms), 2);
+        assert_eq!(animation_frame(&frames, 160 * ms), 1);
+    }
+
+    #[test]
+    fn flips() {
+        let (rotation, scale, corner) = flip_transform(FLIPPED_HORIZONTALLY, 16.0, 8.0);
+        assert_eq!((rotation, scale, corner), (0.0, (-1.0, 1.0), (-16.0, 0.0)));
+        let (rotation, scale, corner) = flip_transform(FLIPPED_DIAGONALLY, 16.0, 16.0);
+        assert_eq!(rotation, ::std::f32::consts::FRAC_PI_2);
+        assert_eq!((scale, corner), ((1.0, -1.0), (0.0, 0.0)));
+        let flags = FLIPPED_DIAGONALLY | FLIPPED_HORIZONTALLY;
+        let (_, scale, corner) = flip_transform(flags, 16.0, 16.0);
+        assert_eq!((scale, corner), ((1.0, 1.0), (-16.0, 0.0)));
+    }
+}
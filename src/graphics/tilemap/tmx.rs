@@ -0,0 +1,441 @@
+//! Reading Tiled's XML formats, `.tmx` for maps and `.tsx` for tilesets.
+
+use std::collections::HashMap;
+use std::path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use mint;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::*;
+
+/// Just enough of an XML document tree to walk a map file.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn parse(text: &str) -> GameResult<Self> {
+        let mut stack: Vec<Element> = vec![];
+        for event in EventReader::from_str(text) {
+            let event = event.map_err(|e| {
+                GameError::ResourceLoadError(format!("Could not parse Tiled XML: {}", e))
+            })?;
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    ..Element::default()
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().expect("Unbalanced XML; should never happen");
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                XmlEvent::Characters(s) | XmlEvent::CData(s) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&s);
+                    }
+                }
+                _ => (),
+            }
+        }
+        Err(GameError::ResourceLoadError(
+            "Tiled XML file has no root element".to_owned(),
+        ))
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    fn parse_attr<T: FromStr>(&self, name: &str, value: &str) -> GameResult<T> {
+        value.parse().map_err(|_| {
+            GameError::ResourceLoadError(format!(
+                "Invalid value {:?} for {} attribute {:?}",
+                value, self.name, name
+            ))
+        })
+    }
+
+    /// Parses an attribute, falling back on `default` if it is missing.
+    fn attr_or<T: FromStr>(&self, name: &str, default: T) -> GameResult<T> {
+        match self.attr(name) {
+            Some(value) => self.parse_attr(name, value),
+            None => Ok(default),
+        }
+    }
+
+    /// Parses an attribute that has to be there.
+    fn required<T: FromStr>(&self, name: &str) -> GameResult<T> {
+        match self.attr(name) {
+            Some(value) => self.parse_attr(name, value),
+            None => Err(GameError::ResourceLoadError(format!(
+                "Tiled {} element is missing the {:?} attribute",
+                self.name, name
+            ))),
+        }
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn properties(&self) -> GameResult<Properties> {
+        let mut properties = Properties::new();
+        if let Some(element) = self.child("properties") {
+            for property in element.children("property") {
+                let name = property.required::<String>("name")?;
+                let kind = property.attr("type").unwrap_or("string");
+                let value = property.attr("value").unwrap_or(&property.text);
+                let _ = properties.insert(name, parse_property(kind, value)?);
+            }
+        }
+        Ok(properties)
+    }
+}
+
+pub(super) fn parse_map(
+    text: &str,
+    dir: &path::Path,
+) -> GameResult<(MapData, Vec<ExternalTileset>)> {
+    let root = Element::parse(text)?;
+    if root.name != "map" {
+        return Err(GameError::ResourceLoadError(format!(
+            "Expected a Tiled map, found a {:?} element",
+            root.name
+        )));
+    }
+    if root.attr_or("orientation", "orthogonal".to_owned())? != "orthogonal" {
+        return Err(unsupported("non-orthogonal maps"));
+    }
+    if root.attr_or("infinite", 0)? != 0 {
+        return Err(unsupported("infinite maps"));
+    }
+
+    let mut tilesets = vec![];
+    let mut external = vec![];
+    for element in root.children("tileset") {
+        let first_gid = element.required("firstgid")?;
+        match element.attr("source") {
+            Some(source) => {
+                external.push(ExternalTileset {
+                    index: tilesets.len(),
+                    source: resolve(dir, source),
+                });
+                tilesets.push(placeholder_tileset(first_gid));
+            }
+            None => tilesets.push(tileset(element, first_gid, dir)?),
+        }
+    }
+
+    let mut layers = vec![];
+    collect_layers(&root, &LayerAttributes::root(), &mut layers)?;
+
+    let data = MapData {
+        width: root.required("width")?,
+        height: root.required("height")?,
+        tile_width: root.required("tilewidth")?,
+        tile_height: root.required("tileheight")?,
+        properties: root.properties()?,
+        tilesets,
+        layers,
+    };
+    Ok((data, external))
+}
+
+pub(super) fn parse_tileset(text: &str, first_gid: u32, dir: &path::Path) -> GameResult<Tileset> {
+    let root = Element::parse(text)?;
+    tileset(&root, first_gid, dir)
+}
+
+fn tileset(element: &Element, first_gid: u32, dir: &path::Path) -> GameResult<Tileset> {
+    let image = element
+        .child("image")
+        .ok_or_else(|| unsupported("tilesets made of separate images"))?;
+    let mut tiles = HashMap::new();
+    for tile in element.children("tile") {
+        let mut animation = vec![];
+        if let Some(frames) = tile.child("animation") {
+            for frame in frames.children("frame") {
+                animation.push(TileFrame {
+                    tile: frame.required("tileid")?,
+                    duration: Duration::from_millis(frame.required("duration")?),
+                });
+            }
+        }
+        let kind = tile.attr("type")
+            .or_else(|| tile.attr("class"))
+            .unwrap_or("")
+            .to_owned();
+        let data = TileData {
+            kind,
+            properties: tile.properties()?,
+            animation,
+        };
+        let _ = tiles.insert(tile.required("id")?, data);
+    }
+
+    let tile_width: u32 = element.required("tilewidth")?;
+    let spacing: u32 = element.attr_or("spacing", 0)?;
+    let margin: u32 = element.attr_or("margin", 0)?;
+    let image_width: u32 = image.attr_or("width", 0)?;
+    let default_columns =
+        (image_width.saturating_sub(2 * margin) + spacing) / (tile_width + spacing).max(1);
+    Ok(Tileset {
+        first_gid,
+        name: element.attr_or("name", String::new())?,
+        tile_width,
+        tile_height: element.required("tileheight")?,
+        spacing,
+        margin,
+        columns: element.attr_or("columns", default_columns)?,
+        tile_count: element.required("tilecount")?,
+        image: resolve(dir, &image.required::<String>("source")?),
+        properties: element.properties()?,
+        tiles,
+    })
+}
+
+fn collect_layers(
+    parent: &Element,
+    group: &LayerAttributes,
+    layers: &mut Vec<Layer>,
+) -> GameResult {
+    for element in &parent.children {
+        match element.name.as_str() {
+            "layer" => {
+                let attributes = layer_attributes(element, group)?;
+                layers.push(Layer::Tiles(tile_layer(element, &attributes)?))
+            }
+            "objectgroup" => {
+                let attributes = layer_attributes(element, group)?;
+                layers.push(Layer::Objects(object_layer(element, &attributes)?))
+            }
+            "group" => collect_layers(element, &layer_attributes(element, group)?, layers)?,
+            "imagelayer" => debug!("Skipping unsupported Tiled image layer"),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the attributes of a layer or group, combined with those of
+/// the group it is in.
+fn layer_attributes(element: &Element, group: &LayerAttributes) -> GameResult<LayerAttributes> {
+    Ok(group.nest(
+        element.attr_or("visible", 1)? != 0,
+        element.attr_or("opacity", 1.0)?,
+        element.attr_or("offsetx", 0.0)?,
+        element.attr_or("offsety", 0.0)?,
+    ))
+}
+
+fn tile_layer(element: &Element, attributes: &LayerAttributes) -> GameResult<TileLayer> {
+    let data = element
+        .child("data")
+        .ok_or_else(|| GameError::ResourceLoadError("Tiled layer has no data".to_owned()))?;
+    if data.attr("compression").is_some() {
+        return Err(unsupported("compressed layer data"));
+    }
+    let tiles = match data.attr("encoding") {
+        Some("csv") => parse_csv(&data.text)?,
+        Some(_) => return Err(unsupported("base64 layer data")),
+        None => data.children("tile")
+            .map(|tile| tile.attr_or("gid", 0))
+            .collect::<GameResult<_>>()?,
+    };
+    let layer = TileLayer {
+        name: element.attr_or("name", String::new())?,
+        width: element.required("width")?,
+        height: element.required("height")?,
+        visible: attributes.visible,
+        opacity: attributes.opacity,
+        offset: attributes.offset,
+        properties: element.properties()?,
+        tiles,
+    };
+    check_tile_count(&layer.name, layer.width, layer.height, &layer.tiles)?;
+    Ok(layer)
+}
+
+fn object_layer(element: &Element, attributes: &LayerAttributes) -> GameResult<ObjectLayer> {
+    let mut objects = vec![];
+    for object in element.children("object") {
+        let shape = if object.child("ellipse").is_some() {
+            ObjectShape::Ellipse
+        } else if object.child("point").is_some() {
+            ObjectShape::Point
+        } else if let Some(polygon) = object.child("polygon") {
+            ObjectShape::Polygon(points(polygon)?)
+        } else if let Some(polyline) = object.child("polyline") {
+            ObjectShape::Polyline(points(polyline)?)
+        } else {
+            ObjectShape::Rectangle
+        };
+        let kind = object.attr("type")
+            .or_else(|| object.attr("class"))
+            .unwrap_or("")
+            .to_owned();
+        objects.push(Object {
+            id: object.attr_or("id", 0)?,
+            name: object.attr_or("name", String::new())?,
+            kind,
+            x: object.attr_or("x", 0.0)?,
+            y: object.attr_or("y", 0.0)?,
+            width: object.attr_or("width", 0.0)?,
+            height: object.attr_or("height", 0.0)?,
+            rotation: object.attr_or("rotation", 0.0)?,
+            gid: match object.attr_or("gid", 0)? {
+                0 => None,
+                gid => Some(gid),
+            },
+            visible: object.attr_or("visible", 1)? != 0,
+            shape,
+            properties: object.properties()?,
+        });
+    }
+    Ok(ObjectLayer {
+        name: element.attr_or("name", String::new())?,
+        visible: attributes.visible,
+        opacity: attributes.opacity,
+        offset: attributes.offset,
+        properties: element.properties()?,
+        objects,
+    })
+}
+
+/// Parses a `points` attribute, like `"0,0 16,0 16,16"`.
+fn points(element: &Element) -> GameResult<Vec<mint::Point2<f32>>> {
+    let points = element.required::<String>("points")?;
+    points
+        .split_whitespace()
+        .map(|point| {
+            let mut coords = point.split(',').map(str::parse::<f32>);
+            match (coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Ok(mint::Point2 { x, y }),
+                _ => Err(GameError::ResourceLoadError(format!(
+                    "Invalid point {:?} in Tiled object",
+                    point
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="gravity" type="float" value="9.5"/>
+ </properties>
+ <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16" spacing="1" tilecount="4" columns="2">
+  <image source="../images/terrain.png" width="33" height="33"/>
+  <tile id="1" type="water">
+   <animation>
+    <frame tileid="1" duration="200"/>
+    <frame tileid="3" duration="200"/>
+   </animation>
+  </tile>
+ </tileset>
+ <tileset firstgid="5" source="items.tsx"/>
+ <layer name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,0,
+2147483649,5,1
+</data>
+ </layer>
+ <group name="extra" offsetx="4" offsety="-2" opacity="0.5">
+  <objectgroup name="spawns" visible="0" offsetx="1" opacity="0.5">
+   <object id="3" name="player" type="spawn" x="8" y="24">
+    <properties>
+     <property name="lives" type="int" value="3"/>
+    </properties>
+    <point/>
+   </object>
+   <object id="4" x="0" y="0">
+    <polygon points="0,0 16,0 8,-12.5"/>
+   </object>
+  </objectgroup>
+ </group>
+ <group name="hidden" visible="0">
+  <layer name="roof" width="1" height="1">
+   <data encoding="csv">1</data>
+  </layer>
+ </group>
+</map>
+"#;
+
+    #[test]
+    fn parse_tmx() {
+        let (data, external) = parse_map(MAP, path::Path::new("/maps")).unwrap();
+        assert_eq!((data.width, data.height, data.tile_width), (3, 2, 16));
+        assert_eq!(data.properties["gravity"], Property::Float(9.5));
+
+        let terrain = &data.tilesets[0];
+        assert_eq!(terrain.image, path::PathBuf::from("/images/terrain.png"));
+        assert_eq!(terrain.tiles[&1].kind, "water");
+        assert_eq!(terrain.tiles[&1].animation[1].tile, 3);
+        assert_eq!(external.len(), 1);
+        assert_eq!(external[0].index, 1);
+        assert_eq!(external[0].source, path::PathBuf::from("/maps/items.tsx"));
+
+        match data.layers[0] {
+            Layer::Tiles(ref layer) => {
+                assert_eq!(layer.tiles, vec![1, 2, 0, 0x8000_0001, 5, 1]);
+                assert_eq!(layer.get(0, 1), Some(1));
+                assert_eq!(layer.get(2, 0), None);
+            }
+            _ => panic!("Expected a tile layer"),
+        }
+        match data.layers[1] {
+            Layer::Objects(ref layer) => {
+                assert!(!layer.visible);
+                assert_eq!(layer.opacity, 0.25);
+                assert_eq!(layer.offset, mint::Vector2 { x: 5.0, y: -2.0 });
+                let player = &layer.objects[0];
+                assert_eq!((player.name.as_str(), player.kind.as_str()), ("player", "spawn"));
+                assert_eq!(player.shape, ObjectShape::Point);
+                assert_eq!(player.properties["lives"], Property::Int(3));
+                match layer.objects[1].shape {
+                    ObjectShape::Polygon(ref points) => {
+                        assert_eq!(points[2], mint::Point2 { x: 8.0, y: -12.5 })
+                    }
+                    ref shape => panic!("Expected a polygon, got {:?}", shape),
+                }
+            }
+            _ => panic!("Expected an object layer"),
+        }
+        match data.layers[2] {
+            Layer::Tiles(ref layer) => assert!(!layer.visible),
+            _ => panic!("Expected a tile layer"),
+        }
+    }
+
+    #[test]
+    fn huge_layer_is_an_error() {
+        let layer = Element::parse(
+            r#"<layer name="huge" width="65536" height="65536"><data encoding="csv">1</data></layer>"#,
+        ).unwrap();
+        assert!(tile_layer(&layer, &LayerAttributes::root()).is_err());
+    }
+}
@@ -47,13 +47,16 @@ extern crate lyon;
 extern crate mint;
 pub extern crate nalgebra;
 extern crate rodio;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 #[macro_use]
 extern crate smart_default;
 extern crate gilrs;
 extern crate toml;
 extern crate winit;
+extern crate xml;
 extern crate zip;
 
 pub mod audio;
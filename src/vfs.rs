@@ -15,6 +15,7 @@ use std::fmt::{self, Debug};
 use std::fs;
 use std::io::{self, Read, Seek, Write};
 use std::path::{self, Path, PathBuf};
+use std::time::SystemTime;
 
 use zip;
 
@@ -149,6 +150,11 @@ pub trait VMetadata {
     /// Returns the length of the thing.  If it is a directory,
     /// the result of this is undefined/platform dependent.
     fn len(&self) -> u64;
+    /// Returns when the thing was last modified, if that is known.
+    /// Zip files don't keep track of it.
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
 }
 
 /// A VFS that points to a directory and uses it as the root of its
@@ -175,6 +181,9 @@ impl VMetadata for PhysicalMetadata {
     fn len(&self) -> u64 {
         self.0.len()
     }
+    fn modified(&self) -> Option<SystemTime> {
+        self.0.modified().ok()
+    }
 }
 
 /// This takes an absolute path and returns either a sanitized relative
@@ -816,6 +825,8 @@ mod tests {
         assert!(ofs.exists(Path::new("/Cargo.toml")));
         assert!(ofs.exists(Path::new("/lib.rs")));
         assert!(!ofs.exists(Path::new("/foobaz.rs")));
+        let metadata = ofs.metadata(Path::new("/lib.rs")).unwrap();
+        assert!(metadata.modified().is_some());
     }
 
     #[test]
@@ -0,0 +1,48 @@
+// #[cfg(all(test, has_display))]
+
+extern crate ggez;
+use ggez::graphics::{self, DrawMode, Rect};
+use ggez::*;
+
+fn make_context() -> (Context, event::EventsLoop) {
+    let cb = ContextBuilder::new("ggez_unit_tests", "ggez");
+    cb.build().unwrap()
+}
+
+/// Returns the RGB values of the pixel at the given position, counting
+/// from the top-left, of a screenshot.
+fn pixel(ctx: &mut Context, image: &graphics::Image, x: usize, y: usize) -> [u8; 3] {
+    let pixels = image.to_rgba8(ctx).unwrap();
+    let i = (y * image.width() as usize + x) * 4;
+    [pixels[i], pixels[i + 1], pixels[i + 2]]
+}
+
+fn check_scissor_rows(ctx: &mut Context) {
+    let screen = graphics::get_screen_coordinates(ctx);
+    graphics::clear(ctx, graphics::BLACK);
+    // Only the top quarter of the screen gets drawn to.
+    let top = Rect::new(screen.x, screen.y, screen.w, screen.h / 4.0);
+    graphics::set_scissor(ctx, Some(top));
+    graphics::rectangle(ctx, graphics::WHITE, DrawMode::Fill, screen).unwrap();
+    graphics::set_scissor(ctx, None);
+
+    let image = graphics::screenshot(ctx).unwrap();
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    assert_eq!(pixel(ctx, &image, w / 2, h / 8), [255, 255, 255]);
+    assert_eq!(pixel(ctx, &image, w / 2, h * 7 / 8), [0, 0, 0]);
+}
+
+#[test]
+fn scissor_clips_the_screen_from_the_top() {
+    let (ctx, _events) = &mut make_context();
+    check_scissor_rows(ctx);
+}
+
+#[test]
+fn scissor_clips_canvases_from_the_top() {
+    let (ctx, _events) = &mut make_context();
+    let canvas = graphics::Canvas::with_window_size(ctx).unwrap();
+    graphics::set_canvas(ctx, Some(&canvas));
+    check_scissor_rows(ctx);
+    graphics::set_canvas(ctx, None);
+}